@@ -15,23 +15,427 @@ CREATE TABLE IF NOT EXISTS employees (
   account_number VARCHAR(50), -- Daniel, mam to robić czy nie?
 
   login VARCHAR(50) UNIQUE,
-  password_hash VARCHAR(255)
+  password_hash VARCHAR(255),
+
+  -- archived people keep their hours and logs but can't get in anymore
+  archived_at TIMESTAMPTZ,
+  archived_by INT,
+  -- right to erasure, only the name and what payroll needs are left
+  erased_at TIMESTAMPTZ,
+  erased_by INT
 );
+-- databases created before employees were archived instead of deleted
+ALTER TABLE employees ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ;
+ALTER TABLE employees ADD COLUMN IF NOT EXISTS archived_by INT;
+ALTER TABLE employees ADD COLUMN IF NOT EXISTS erased_at TIMESTAMPTZ;
+ALTER TABLE employees ADD COLUMN IF NOT EXISTS erased_by INT;
 
 CREATE TABLE IF NOT EXISTS hours (
   id_record SERIAL PRIMARY KEY,
 
   id_employee INT NOT NULL REFERENCES employees(id_person) ON DELETE CASCADE,
 
-  time_start TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
-  time_end TIMESTAMP
+  time_start TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  time_end TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS breaks (
+    id_break SERIAL PRIMARY KEY,
+
+    id_record INT NOT NULL REFERENCES hours(id_record) ON DELETE CASCADE,
+
+    break_start TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    break_end TIMESTAMPTZ
+);
+
+-- shifts of at least min_shift_minutes need required_break_minutes of break,
+-- the highest matching rule wins
+CREATE TABLE IF NOT EXISTS break_rules (
+    min_shift_minutes INT PRIMARY KEY CHECK (min_shift_minutes > 0),
+    required_break_minutes INT NOT NULL CHECK (required_break_minutes >= 0)
 );
 
+-- Kodeks pracy art. 134: 15 min for 6h+ shifts
+INSERT INTO break_rules (min_shift_minutes, required_break_minutes) VALUES (360, 15)
+ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS hours_audit (
+    id_audit SERIAL PRIMARY KEY,
+
+    -- no FK on purpose, history has to outlive the record (and the employee)
+    id_record INT NOT NULL,
+    id_employee INT NOT NULL,
+
+    action VARCHAR(10) NOT NULL CHECK (action IN ('create', 'update', 'delete')),
+
+    old_time_start TIMESTAMPTZ,
+    old_time_end TIMESTAMPTZ,
+    new_time_start TIMESTAMPTZ,
+    new_time_end TIMESTAMPTZ,
+
+    reason TEXT NOT NULL,
+    changed_by INT NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE OR REPLACE FUNCTION hours_audit_immutable() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'hours_audit is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS hours_audit_no_change ON hours_audit;
+CREATE TRIGGER hours_audit_no_change
+    BEFORE UPDATE OR DELETE ON hours_audit
+    FOR EACH ROW EXECUTE FUNCTION hours_audit_immutable();
+
 CREATE TABLE IF NOT EXISTS access_logs (
     id_log SERIAL PRIMARY KEY,
     id_employee INT NOT NULL REFERENCES employees(id_person),
     direction VARCHAR(10) NOT NULL CHECK (direction IN ('IN', 'OUT')),
-    timestamp TIMESTAMP NOT NULL
+    timestamp TIMESTAMPTZ NOT NULL,
+    -- sent by the station with every ack, a retried ack reuses it
+    event_id UUID UNIQUE,
+    -- where it happened, no FK so the log outlives a removed door
+    station VARCHAR(100),
+    id_door INT
+);
+-- databases created before stations sent event ids
+ALTER TABLE access_logs ADD COLUMN IF NOT EXISTS event_id UUID UNIQUE;
+ALTER TABLE access_logs ADD COLUMN IF NOT EXISTS station VARCHAR(100);
+ALTER TABLE access_logs ADD COLUMN IF NOT EXISTS id_door INT;
+
+CREATE TABLE IF NOT EXISTS failed_attempts (
+    id_attempt SERIAL PRIMARY KEY,
+
+    -- whatever the station claimed, unknown ids included, so no FK
+    id_employee INT,
+
+    reason VARCHAR(50) NOT NULL,
+    similarity REAL,
+
+    station VARCHAR(100),
+    direction VARCHAR(10),
+
+    image_path VARCHAR(255),
+    -- set for attempts uploaded by a station after being offline
+    event_id UUID UNIQUE,
+
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+ALTER TABLE failed_attempts ADD COLUMN IF NOT EXISTS event_id UUID UNIQUE;
+
+-- how long archived failed attempt photos are kept, per reason,
+-- '*' applies to reasons without their own row
+CREATE TABLE IF NOT EXISTS photo_retention (
+    reason VARCHAR(50) PRIMARY KEY,
+    retention_days INT NOT NULL CHECK (retention_days >= 0)
+);
+
+INSERT INTO photo_retention (reason, retention_days) VALUES ('*', 30), ('face_mismatched', 90)
+ON CONFLICT DO NOTHING;
+
+-- temporary lockouts after repeated failures, subject is an employee id or a station name
+CREATE TABLE IF NOT EXISTS lockouts (
+    id_lockout SERIAL PRIMARY KEY,
+
+    subject_type VARCHAR(10) NOT NULL CHECK (subject_type IN ('employee', 'station')),
+    subject VARCHAR(100) NOT NULL,
+
+    locked_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    locked_until TIMESTAMPTZ NOT NULL,
+
+    unlocked_by INT,
+    unlocked_at TIMESTAMPTZ
+);
+
+-- issued QR badges, the signed code only carries the id so lost ones can be revoked here
+CREATE TABLE IF NOT EXISTS badges (
+    id_badge SERIAL PRIMARY KEY,
+    id_employee INT NOT NULL REFERENCES employees(id_person) ON DELETE CASCADE,
+
+    issued_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    revoked_by INT,
+    revoked_at TIMESTAMPTZ
+);
+
+-- secrets of rotating QR codes shown by the phone app, last_step stops a code being used twice
+CREATE TABLE IF NOT EXISTS employee_totp (
+    id_employee INT PRIMARY KEY REFERENCES employees(id_person) ON DELETE CASCADE,
+    secret BYTEA NOT NULL,
+    enrolled_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_step BIGINT
+);
+
+-- site > zone > door, a door leads into its zone
+CREATE TABLE IF NOT EXISTS sites (
+    id_site SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL UNIQUE,
+    address VARCHAR(255)
+);
+
+CREATE TABLE IF NOT EXISTS zones (
+    id_zone SERIAL PRIMARY KEY,
+    id_site INT NOT NULL REFERENCES sites(id_site) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    UNIQUE (id_site, name),
+    -- off, soft (let through but recorded) or hard (denied)
+    anti_passback VARCHAR(4) NOT NULL DEFAULT 'off' CHECK (anti_passback IN ('off', 'soft', 'hard')),
+    -- least role that may enter alone and escort others in, NULL when anyone permitted may
+    escort_role VARCHAR(20) CHECK (escort_role IN ('admin', 'manager', 'employee'))
+);
+ALTER TABLE zones ADD COLUMN IF NOT EXISTS anti_passback VARCHAR(4) NOT NULL DEFAULT 'off'
+    CHECK (anti_passback IN ('off', 'soft', 'hard'));
+ALTER TABLE zones ADD COLUMN IF NOT EXISTS escort_role VARCHAR(20)
+    CHECK (escort_role IN ('admin', 'manager', 'employee'));
+
+CREATE TABLE IF NOT EXISTS doors (
+    id_door SERIAL PRIMARY KEY,
+    id_zone INT NOT NULL REFERENCES zones(id_zone) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    UNIQUE (id_zone, name)
+);
+
+-- who may enter a zone, granted to a single employee or to everyone with a role
+CREATE TABLE IF NOT EXISTS zone_permissions (
+    id_permission SERIAL PRIMARY KEY,
+    id_zone INT NOT NULL REFERENCES zones(id_zone) ON DELETE CASCADE,
+
+    id_employee INT REFERENCES employees(id_person) ON DELETE CASCADE,
+    role VARCHAR(20) CHECK (role IN ('admin', 'manager', 'employee')),
+    CHECK ((id_employee IS NULL) <> (role IS NULL)),
+
+    granted_by INT NOT NULL,
+    granted_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX IF NOT EXISTS zone_permissions_employee ON zone_permissions (id_zone, id_employee)
+    WHERE id_employee IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS zone_permissions_role ON zone_permissions (id_zone, role)
+    WHERE role IS NOT NULL;
+
+-- weekly access windows in site local time, for one employee, a role or a zone.
+-- end_time <= start_time runs past midnight, weekdays are 1 (Monday) to 7 (Sunday)
+CREATE TABLE IF NOT EXISTS access_schedules (
+    id_schedule SERIAL PRIMARY KEY,
+
+    id_employee INT REFERENCES employees(id_person) ON DELETE CASCADE,
+    role VARCHAR(20) CHECK (role IN ('admin', 'manager', 'employee')),
+    id_zone INT REFERENCES zones(id_zone) ON DELETE CASCADE,
+    CHECK ((id_employee IS NOT NULL)::int + (role IS NOT NULL)::int + (id_zone IS NOT NULL)::int = 1),
+
+    weekdays INT[] NOT NULL CHECK (weekdays <@ ARRAY[1, 2, 3, 4, 5, 6, 7] AND cardinality(weekdays) > 0),
+    start_time TIME NOT NULL,
+    end_time TIME NOT NULL,
+
+    valid_from DATE,
+    valid_until DATE,
+    -- windows are skipped on holidays unless this is set
+    on_holidays BOOLEAN NOT NULL DEFAULT FALSE,
+
+    created_by INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS holidays (
+    day DATE PRIMARY KEY,
+    name VARCHAR(100) NOT NULL
+);
+
+-- registered entrance stations, they authenticate with a token of which only the hash is kept
+CREATE TABLE IF NOT EXISTS stations (
+    id_station SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL UNIQUE,
+
+    location VARCHAR(255),
+    direction VARCHAR(10) NOT NULL CHECK (direction IN ('IN', 'OUT')),
+    -- stations without a door let everyone through, like before zones existed
+    id_door INT REFERENCES doors(id_door) ON DELETE SET NULL,
+
+    token_hash BYTEA NOT NULL UNIQUE,
+    registered_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    -- from the latest heartbeat
+    last_seen_at TIMESTAMPTZ,
+    version VARCHAR(50),
+    camera_status VARCHAR(100),
+
+    -- settings pushed to the station, config_version goes up with every change
+    config_version INT NOT NULL DEFAULT 1,
+    fps INT NOT NULL DEFAULT 30,
+    countdown_seconds INT NOT NULL DEFAULT 3,
+    request_timeout_seconds INT NOT NULL DEFAULT 5,
+    message_seconds INT NOT NULL DEFAULT 2,
+    face_threshold REAL NOT NULL DEFAULT 0.95
+);
+ALTER TABLE stations ADD COLUMN IF NOT EXISTS id_door INT REFERENCES doors(id_door) ON DELETE SET NULL;
+ALTER TABLE stations ADD COLUMN IF NOT EXISTS config_version INT NOT NULL DEFAULT 1;
+ALTER TABLE stations ADD COLUMN IF NOT EXISTS fps INT NOT NULL DEFAULT 30;
+ALTER TABLE stations ADD COLUMN IF NOT EXISTS countdown_seconds INT NOT NULL DEFAULT 3;
+ALTER TABLE stations ADD COLUMN IF NOT EXISTS request_timeout_seconds INT NOT NULL DEFAULT 5;
+ALTER TABLE stations ADD COLUMN IF NOT EXISTS message_seconds INT NOT NULL DEFAULT 2;
+ALTER TABLE stations ADD COLUMN IF NOT EXISTS face_threshold REAL NOT NULL DEFAULT 0.95;
+
+-- people without an employee record, let in for a limited time on behalf of a host
+CREATE TABLE IF NOT EXISTS visitors (
+    id_visitor SERIAL PRIMARY KEY,
+    first_name VARCHAR(100) NOT NULL,
+    last_name VARCHAR(100) NOT NULL,
+    company VARCHAR(255),
+    kind VARCHAR(20) NOT NULL DEFAULT 'visitor' CHECK (kind IN ('visitor', 'contractor')),
+
+    -- they get into the zones their host may enter
+    id_host INT NOT NULL REFERENCES employees(id_person),
+    valid_from TIMESTAMPTZ NOT NULL,
+    valid_until TIMESTAMPTZ NOT NULL CHECK (valid_until > valid_from),
+
+    -- optional, without a photo the pass alone opens the door
+    face_embedded BYTEA,
+    photo_path VARCHAR(255),
+
+    created_by INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS visitor_passes (
+    id_pass SERIAL PRIMARY KEY,
+    id_visitor INT NOT NULL REFERENCES visitors(id_visitor) ON DELETE CASCADE,
+
+    -- entries allowed, NULL for as many as the validity period allows
+    max_uses INT CHECK (max_uses > 0),
+    uses INT NOT NULL DEFAULT 0,
+
+    issued_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    revoked_by INT,
+    revoked_at TIMESTAMPTZ
+);
+
+-- kept apart from access_logs, visitors don't work shifts
+CREATE TABLE IF NOT EXISTS visitor_logs (
+    id_log SERIAL PRIMARY KEY,
+    id_visitor INT NOT NULL REFERENCES visitors(id_visitor) ON DELETE CASCADE,
+    id_pass INT NOT NULL,
+    direction VARCHAR(10) NOT NULL CHECK (direction IN ('IN', 'OUT')),
+    timestamp TIMESTAMPTZ NOT NULL,
+    event_id UUID UNIQUE,
+    station VARCHAR(100),
+    id_door INT
+);
+
+-- emergency modes, every change is kept and the latest one is in force.
+-- a row without a site covers all of them
+CREATE TABLE IF NOT EXISTS site_modes (
+    id_change SERIAL PRIMARY KEY,
+    id_site INT REFERENCES sites(id_site) ON DELETE CASCADE,
+    mode VARCHAR(20) NOT NULL CHECK (mode IN ('normal', 'lockdown', 'evacuation')),
+    reason TEXT,
+    changed_by INT NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- logs before the latest reset of an employee are left out of their passback state,
+-- a reset without a zone covers all of them
+CREATE TABLE IF NOT EXISTS passback_resets (
+    id_reset SERIAL PRIMARY KEY,
+    id_employee INT NOT NULL REFERENCES employees(id_person) ON DELETE CASCADE,
+    id_zone INT REFERENCES zones(id_zone) ON DELETE CASCADE,
+    reset_by INT NOT NULL,
+    reset_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- passes logged out of order, in soft zones or past a hard one during an evacuation
+CREATE TABLE IF NOT EXISTS passback_violations (
+    id_violation SERIAL PRIMARY KEY,
+    id_log INT NOT NULL UNIQUE REFERENCES access_logs(id_log) ON DELETE CASCADE,
+    id_zone INT NOT NULL REFERENCES zones(id_zone) ON DELETE CASCADE
+);
+
+-- entries held at a station until an escort verifies there too, for an employee
+-- or a visitor's pass
+CREATE TABLE IF NOT EXISTS escort_authorizations (
+    id_authorization SERIAL PRIMARY KEY,
+    station VARCHAR(100) NOT NULL,
+    id_zone INT NOT NULL REFERENCES zones(id_zone) ON DELETE CASCADE,
+
+    id_employee INT REFERENCES employees(id_person) ON DELETE CASCADE,
+    id_pass INT REFERENCES visitor_passes(id_pass) ON DELETE CASCADE,
+    CHECK ((id_employee IS NULL) <> (id_pass IS NULL)),
+
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMPTZ NOT NULL,
+    confirmed_by INT REFERENCES employees(id_person) ON DELETE SET NULL,
+    confirmed_at TIMESTAMPTZ,
+    -- set by the ack of the held person
+    completed_at TIMESTAMPTZ,
+    -- event id of that ack, so only its retries get through again
+    completed_event_id UUID
+);
+ALTER TABLE escort_authorizations ADD COLUMN IF NOT EXISTS completed_event_id UUID;
+
+-- administrative changes with who made them and from where. before/after only hold
+-- the fields that changed, secrets are never written here
+CREATE TABLE IF NOT EXISTS admin_audit (
+    id_audit SERIAL PRIMARY KEY,
+
+    -- no FKs, like hours_audit
+    actor INT NOT NULL,
+    action VARCHAR(50) NOT NULL,
+    target_type VARCHAR(30) NOT NULL,
+    target_id INT,
+
+    before JSONB,
+    after JSONB,
+
+    ip VARCHAR(64),
+    user_agent TEXT,
+    method VARCHAR(10) NOT NULL,
+    path TEXT NOT NULL,
+
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS admin_audit_target ON admin_audit (target_type, target_id);
+
+-- erasure swaps the personal fields of an employee's snapshots for "[erased]"
+CREATE OR REPLACE FUNCTION audit_erase_fields(doc JSONB) RETURNS JSONB AS $$
+    SELECT COALESCE(jsonb_object_agg(key, CASE
+        WHEN key IN ('login', 'account_number', 'photo_path') THEN '"[erased]"'::JSONB
+        ELSE value END), doc)
+    FROM jsonb_each(doc)
+$$ LANGUAGE sql IMMUTABLE;
+
+-- only an erasure, which sets admin_audit.erasure in its transaction, may touch an
+-- entry, and then only its before/after
+CREATE OR REPLACE FUNCTION admin_audit_immutable() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND current_setting('admin_audit.erasure', true) = 'on'
+        AND (NEW.id_audit, NEW.actor, NEW.action, NEW.target_type, NEW.target_id,
+             NEW.ip, NEW.user_agent, NEW.method, NEW.path, NEW.changed_at)
+            IS NOT DISTINCT FROM
+            (OLD.id_audit, OLD.actor, OLD.action, OLD.target_type, OLD.target_id,
+             OLD.ip, OLD.user_agent, OLD.method, OLD.path, OLD.changed_at)
+        AND NEW.before IS NOT DISTINCT FROM audit_erase_fields(OLD.before)
+        AND NEW.after IS NOT DISTINCT FROM audit_erase_fields(OLD.after) THEN
+        RETURN NEW;
+    END IF;
+    RAISE EXCEPTION 'admin_audit is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS admin_audit_no_change ON admin_audit;
+CREATE TRIGGER admin_audit_no_change
+    BEFORE UPDATE OR DELETE ON admin_audit
+    FOR EACH ROW EXECUTE FUNCTION admin_audit_immutable();
+
+-- admin panel logins, like station tokens only the hash of the session token is kept
+CREATE TABLE IF NOT EXISTS sessions (
+    id_session SERIAL PRIMARY KEY,
+    id_employee INT NOT NULL REFERENCES employees(id_person) ON DELETE CASCADE,
+    token_hash BYTEA NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMPTZ NOT NULL
 );
 
 -- idk, ppl recommend to do this
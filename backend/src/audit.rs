@@ -0,0 +1,129 @@
+use crate::models::NewAuditEntry;
+use crate::routes::AppState;
+use actix_web::HttpRequest;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+
+/// Only ever written as changed, never with their value.
+const SECRET_FIELDS: &[&str] = &["password", "password_hash", "face_embedded", "totp_secret"];
+pub const REDACTED: &str = "[redacted]";
+
+fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    if SECRET_FIELDS.contains(&key.as_str()) {
+                        (key, Value::from(REDACTED))
+                    } else {
+                        (key, value)
+                    }
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Keeps the fields that differ between two snapshots, each side with its own values.
+/// A field only one side has shows up only there. Without one of the snapshots
+/// (something created or deleted) the other is kept whole.
+pub fn diff(before: Option<Value>, after: Option<Value>) -> (Option<Value>, Option<Value>) {
+    let (before, after) = match (before.map(redact), after.map(redact)) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => (before, after),
+        other => return other,
+    };
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let (mut old, mut new) = (Map::new(), Map::new());
+    for key in keys {
+        if before.get(key) == after.get(key) {
+            continue;
+        }
+        if let Some(value) = before.get(key) {
+            old.insert(key.clone(), value.clone());
+        }
+        if let Some(value) = after.get(key) {
+            new.insert(key.clone(), value.clone());
+        }
+    }
+    (Some(Value::Object(old)), Some(Value::Object(new)))
+}
+
+impl NewAuditEntry {
+    pub fn new(
+        req: &HttpRequest,
+        actor: i32,
+        action: &str,
+        target_type: &str,
+        target_id: Option<i32>,
+    ) -> Self {
+        Self {
+            actor,
+            action: action.to_string(),
+            target_type: target_type.to_string(),
+            target_id,
+            before: None,
+            after: None,
+            ip: req
+                .connection_info()
+                .realip_remote_addr()
+                .map(str::to_string),
+            user_agent: req
+                .headers()
+                .get("User-Agent")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            method: req.method().to_string(),
+            path: req.path().to_string(),
+        }
+    }
+
+    /// Snapshots of the target around the change, stored as a diff.
+    pub fn changes(mut self, before: Option<Value>, after: Option<Value>) -> Self {
+        (self.before, self.after) = diff(before, after);
+        self
+    }
+}
+
+/// Appends to the audit log. The change itself is already done by now, so a failure
+/// here is only logged.
+pub async fn record(data: &AppState, entry: NewAuditEntry) {
+    let (action, target_id) = (entry.action.clone(), entry.target_id);
+    if let Err(e) = data.db.add_audit_entry(entry).await {
+        eprintln!(
+            "Failed to record audit entry {} of {:?}: {}",
+            action, target_id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_keeps_changed_fields_and_hides_secrets() {
+        let before =
+            json!({"id_person": 4, "first_name": "Anna", "role": "employee", "login": null});
+        let after = json!({
+            "id_person": 4,
+            "first_name": "Anna",
+            "role": "manager",
+            "login": "anna",
+            "password": "hunter2"
+        });
+
+        let (old, new) = diff(Some(before.clone()), Some(after));
+        assert_eq!(old, Some(json!({"role": "employee", "login": null})));
+        assert_eq!(
+            new,
+            Some(json!({"role": "manager", "login": "anna", "password": REDACTED}))
+        );
+
+        // a delete keeps the whole record on the before side
+        let (old, new) = diff(Some(before.clone()), None);
+        assert_eq!(old, Some(before));
+        assert_eq!(new, None);
+    }
+}
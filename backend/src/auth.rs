@@ -0,0 +1,129 @@
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+/// Stored as `pbkdf2-sha256$<iterations>$<salt>$<hash>`, salt and hash in base64.
+const SCHEME: &str = "pbkdf2-sha256";
+const ITERATIONS: u32 = 100_000;
+
+pub enum PasswordCheck {
+    Valid,
+    /// Matched a password stored in plain text before hashing was added, it should be
+    /// hashed now.
+    ValidLegacy,
+    Invalid,
+}
+
+/// PBKDF2-HMAC-SHA256 with a single 32 byte block, which is all a password hash needs.
+fn pbkdf2(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let prf =
+        Hmac::<Sha256>::new_from_slice(password.as_bytes()).expect("HMAC takes keys of any size");
+
+    let mut mac = prf.clone();
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+    let mut block: [u8; 32] = mac.finalize().into_bytes().into();
+    let mut result = block;
+
+    for _ in 1..iterations {
+        let mut mac = prf.clone();
+        mac.update(&block);
+        block = mac.finalize().into_bytes().into();
+        result.iter_mut().zip(block).for_each(|(r, b)| *r ^= b);
+    }
+    result
+}
+
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let hash = pbkdf2(password, &salt, ITERATIONS);
+    format!(
+        "{}${}${}${}",
+        SCHEME,
+        ITERATIONS,
+        STANDARD_NO_PAD.encode(salt),
+        STANDARD_NO_PAD.encode(hash)
+    )
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub fn verify_password(password: &str, stored: &str) -> PasswordCheck {
+    let parts: Vec<&str> = stored.split('$').collect();
+    if parts.first() != Some(&SCHEME) {
+        return if constant_time_eq(password.as_bytes(), stored.as_bytes()) {
+            PasswordCheck::ValidLegacy
+        } else {
+            PasswordCheck::Invalid
+        };
+    }
+
+    let parsed = match parts.as_slice() {
+        [_, iterations, salt, hash] => iterations.parse::<u32>().ok().zip(
+            STANDARD_NO_PAD
+                .decode(salt)
+                .ok()
+                .zip(STANDARD_NO_PAD.decode(hash).ok()),
+        ),
+        _ => None,
+    };
+    match parsed {
+        Some((iterations, (salt, hash)))
+            if iterations > 0 && constant_time_eq(&pbkdf2(password, &salt, iterations), &hash) =>
+        {
+            PasswordCheck::Valid
+        }
+        _ => PasswordCheck::Invalid,
+    }
+}
+
+/// Sessions last `SESSION_HOURS` (default 12), one working day, then the panel logs in again.
+pub fn session_expires_at(now: DateTime<Utc>) -> DateTime<Utc> {
+    let hours = std::env::var("SESSION_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(12);
+    now + Duration::hours(hours)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_hash_round_trip_and_legacy() {
+        let stored = hash_password("correct horse");
+
+        assert!(stored.starts_with("pbkdf2-sha256$100000$"));
+        assert!(matches!(
+            verify_password("correct horse", &stored),
+            PasswordCheck::Valid
+        ));
+        assert!(matches!(
+            verify_password("wrong horse", &stored),
+            PasswordCheck::Invalid
+        ));
+        assert!(matches!(
+            verify_password("plain", "plain"),
+            PasswordCheck::ValidLegacy
+        ));
+        assert!(matches!(
+            verify_password("plain", "pbkdf2-sha256$0$$"),
+            PasswordCheck::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_pbkdf2_matches_rfc_vector() {
+        // RFC 7914 section 11, first 32 bytes
+        let hash = pbkdf2("passwd", b"salt", 1);
+        assert_eq!(hash[..8], [0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f]);
+    }
+}
@@ -0,0 +1,205 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Version tag in front of every code, so the format can change later.
+const PREFIX: &str = "CB1";
+/// Same for visitor passes, signed with the same key.
+const PASS_PREFIX: &str = "CV1";
+
+/// What a badge code vouches for. The badge row is still checked for revocation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadgeClaims {
+    pub employee_id: i32,
+    pub badge_id: i32,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// What a visitor pass code vouches for. Uses and revocation live in `visitor_passes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassClaims {
+    pub visitor_id: i32,
+    pub pass_id: i32,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Signing key from `BADGE_SECRET`. Without it no badges can be issued or accepted.
+/// The placeholder the compose file used to default to counts as unset, it is public.
+pub fn secret() -> Option<Vec<u8>> {
+    std::env::var("BADGE_SECRET")
+        .ok()
+        .filter(|s| !s.is_empty() && s != "change-me")
+        .map(String::into_bytes)
+}
+
+/// Plain employee ids from old printed badges are only accepted by `check_qr` with
+/// `ALLOW_PLAIN_BADGES=1`, anyone can print those.
+pub fn plain_ids_allowed() -> bool {
+    std::env::var("ALLOW_PLAIN_BADGES").is_ok_and(|v| v == "1" || v == "true")
+}
+
+fn signature(payload: &str, secret: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// `CB1.<employee>.<badge>.<issued>.<expires>.<signature>`, times as unix seconds and
+/// the HMAC-SHA256 of everything before the last dot in unpadded base64url.
+pub fn sign(claims: &BadgeClaims, secret: &[u8]) -> String {
+    let payload = format!(
+        "{}.{}.{}.{}.{}",
+        PREFIX,
+        claims.employee_id,
+        claims.badge_id,
+        claims.issued_at.timestamp(),
+        claims.expires_at.timestamp()
+    );
+    let tag = signature(&payload, secret).finalize().into_bytes();
+    format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(tag))
+}
+
+/// Checks the signature and expiry of a scanned code. Errors are the reasons sent back
+/// to the station: `invalid_badge` or `badge_expired`.
+pub fn verify(code: &str, secret: &[u8], now: DateTime<Utc>) -> Result<BadgeClaims, &'static str> {
+    let (payload, tag) = code.trim().rsplit_once('.').ok_or("invalid_badge")?;
+    let tag = URL_SAFE_NO_PAD.decode(tag).map_err(|_| "invalid_badge")?;
+    signature(payload, secret)
+        .verify_slice(&tag)
+        .map_err(|_| "invalid_badge")?;
+
+    let parts: Vec<&str> = payload.split('.').collect();
+    let claims = match parts.as_slice() {
+        [PREFIX, employee_id, badge_id, issued_at, expires_at] => {
+            let timestamp = |value: &str| {
+                value
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            };
+            BadgeClaims {
+                employee_id: employee_id.parse().map_err(|_| "invalid_badge")?,
+                badge_id: badge_id.parse().map_err(|_| "invalid_badge")?,
+                issued_at: timestamp(issued_at).ok_or("invalid_badge")?,
+                expires_at: timestamp(expires_at).ok_or("invalid_badge")?,
+            }
+        }
+        _ => return Err("invalid_badge"),
+    };
+
+    if claims.expires_at <= now {
+        return Err("badge_expired");
+    }
+    Ok(claims)
+}
+
+pub fn is_pass_code(code: &str) -> bool {
+    code.trim().starts_with("CV1.")
+}
+
+/// `CV1.<visitor>.<pass>.<expires>.<signature>`, signed like a badge.
+pub fn sign_pass(claims: &PassClaims, secret: &[u8]) -> String {
+    let payload = format!(
+        "{}.{}.{}.{}",
+        PASS_PREFIX,
+        claims.visitor_id,
+        claims.pass_id,
+        claims.expires_at.timestamp()
+    );
+    let tag = signature(&payload, secret).finalize().into_bytes();
+    format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(tag))
+}
+
+/// Checks a scanned visitor pass, failing with `invalid_pass` or `pass_expired`.
+pub fn verify_pass(
+    code: &str,
+    secret: &[u8],
+    now: DateTime<Utc>,
+) -> Result<PassClaims, &'static str> {
+    let (payload, tag) = code.trim().rsplit_once('.').ok_or("invalid_pass")?;
+    let tag = URL_SAFE_NO_PAD.decode(tag).map_err(|_| "invalid_pass")?;
+    signature(payload, secret)
+        .verify_slice(&tag)
+        .map_err(|_| "invalid_pass")?;
+
+    let parts: Vec<&str> = payload.split('.').collect();
+    let claims = match parts.as_slice() {
+        [PASS_PREFIX, visitor_id, pass_id, expires_at] => PassClaims {
+            visitor_id: visitor_id.parse().map_err(|_| "invalid_pass")?,
+            pass_id: pass_id.parse().map_err(|_| "invalid_pass")?,
+            expires_at: expires_at
+                .parse::<i64>()
+                .ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                .ok_or("invalid_pass")?,
+        },
+        _ => return Err("invalid_pass"),
+    };
+
+    if claims.expires_at <= now {
+        return Err("pass_expired");
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims() -> BadgeClaims {
+        BadgeClaims {
+            employee_id: 5,
+            badge_id: 12,
+            issued_at: "2026-01-01T00:00:00Z".parse().unwrap(),
+            expires_at: "2027-01-01T00:00:00Z".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_badge_roundtrip_and_tampering() {
+        let now = "2026-06-01T00:00:00Z".parse().unwrap();
+        let code = sign(&claims(), b"secret");
+
+        assert_eq!(verify(&code, b"secret", now), Ok(claims()));
+        assert_eq!(verify(&code, b"other", now), Err("invalid_badge"));
+        // someone printing a badge for employee 6 with the old signature
+        let forged = code.replacen("CB1.5.", "CB1.6.", 1);
+        assert_eq!(verify(&forged, b"secret", now), Err("invalid_badge"));
+        assert_eq!(verify("5", b"secret", now), Err("invalid_badge"));
+    }
+
+    #[test]
+    fn test_badge_expired() {
+        let code = sign(&claims(), b"secret");
+        let later = "2027-01-01T00:00:01Z".parse().unwrap();
+
+        assert_eq!(verify(&code, b"secret", later), Err("badge_expired"));
+    }
+
+    #[test]
+    fn test_pass_is_not_a_badge() {
+        let now = "2026-06-01T00:00:00Z".parse().unwrap();
+        let pass = PassClaims {
+            visitor_id: 3,
+            pass_id: 9,
+            expires_at: "2026-06-01T18:00:00Z".parse().unwrap(),
+        };
+        let code = sign_pass(&pass, b"secret");
+
+        assert!(is_pass_code(&code));
+        assert_eq!(verify_pass(&code, b"secret", now), Ok(pass));
+        // same key, but a pass must not open the door as employee 3
+        assert_eq!(verify(&code, b"secret", now), Err("invalid_badge"));
+        assert_eq!(
+            verify_pass(&sign(&claims(), b"secret"), b"secret", now),
+            Err("invalid_pass")
+        );
+        let evening = "2026-06-01T18:00:00Z".parse().unwrap();
+        assert_eq!(verify_pass(&code, b"secret", evening), Err("pass_expired"));
+    }
+}
@@ -0,0 +1,150 @@
+use image::{DynamicImage, ImageOutputFormat, Luma};
+use printpdf::{BuiltinFont, Image, ImageTransform, Mm, PdfDocument};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use std::error::Error;
+use std::io::{BufWriter, Cursor};
+
+const QR_PIXELS: u32 = 300;
+
+// ID-1 card, same size as a bank card
+const CARD_WIDTH: f32 = 85.6;
+const CARD_HEIGHT: f32 = 54.0;
+const PHOTO_BOX: (f32, f32) = (26.0, 34.0);
+const QR_SIZE: f32 = 38.0;
+
+fn qr_image(content: &str) -> Result<DynamicImage, Box<dyn Error>> {
+    let code = QrCode::new(content.as_bytes())?;
+    let image = code
+        .render::<Luma<u8>>()
+        .min_dimensions(QR_PIXELS, QR_PIXELS)
+        .build();
+    Ok(DynamicImage::ImageLuma8(image))
+}
+
+pub fn qr_png(content: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    qr_image(content)?.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?;
+    Ok(bytes)
+}
+
+pub fn qr_svg(content: &str) -> Result<String, Box<dyn Error>> {
+    let code = QrCode::new(content.as_bytes())?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(QR_PIXELS, QR_PIXELS)
+        .build())
+}
+
+/// The built-in PDF fonts only cover Latin-1, so Polish letters outside it are folded
+/// to their base letter instead of coming out as garbage.
+pub fn pdf_safe(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ą' => 'a',
+            'ć' => 'c',
+            'ę' => 'e',
+            'ł' => 'l',
+            'ń' => 'n',
+            'ś' => 's',
+            'ź' | 'ż' => 'z',
+            'Ą' => 'A',
+            'Ć' => 'C',
+            'Ę' => 'E',
+            'Ł' => 'L',
+            'Ń' => 'N',
+            'Ś' => 'S',
+            'Ź' | 'Ż' => 'Z',
+            c if (c as u32) < 0x100 => c,
+            _ => '?',
+        })
+        .collect()
+}
+
+/// Dots per inch that make an image of `pixels` come out `mm` long on paper.
+pub fn dpi_for(pixels: u32, mm: f32) -> f32 {
+    pixels as f32 * 25.4 / mm
+}
+
+/// Printable card: photo on the left (when there is one), name under it, QR on the right.
+pub fn card_pdf(
+    name: &str,
+    employee_id: i32,
+    photo: Option<&DynamicImage>,
+    content: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (doc, page, layer) = PdfDocument::new(
+        format!("Badge {}", employee_id),
+        Mm(CARD_WIDTH),
+        Mm(CARD_HEIGHT),
+        "Badge",
+    );
+    let layer = doc.get_page(page).get_layer(layer);
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+
+    if let Some(photo) = photo {
+        let photo = DynamicImage::ImageRgb8(photo.to_rgb8());
+        // fit into the box, keeping the aspect ratio
+        let dpi = dpi_for(photo.width(), PHOTO_BOX.0).max(dpi_for(photo.height(), PHOTO_BOX.1));
+        Image::from_dynamic_image(&photo).add_to_layer(
+            layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm(4.0)),
+                translate_y: Some(Mm(14.0)),
+                dpi: Some(dpi),
+                ..Default::default()
+            },
+        );
+    }
+
+    layer.use_text(pdf_safe(name), 9.0, Mm(4.0), Mm(8.0), &bold);
+    layer.use_text(
+        format!("ID {}", employee_id),
+        7.0,
+        Mm(4.0),
+        Mm(4.0),
+        &regular,
+    );
+
+    let qr = DynamicImage::ImageRgb8(qr_image(content)?.to_rgb8());
+    let dpi = dpi_for(qr.width(), QR_SIZE);
+    Image::from_dynamic_image(&qr).add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(CARD_WIDTH - QR_SIZE - 4.0)),
+            translate_y: Some(Mm((CARD_HEIGHT - QR_SIZE) / 2.0)),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
+
+    let mut bytes = Vec::new();
+    doc.save(&mut BufWriter::new(&mut bytes))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_badge_formats() {
+        let png = qr_png("CB1.5.12.1767225600.1798761600.sig").unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        let svg = qr_svg("5").unwrap();
+        assert!(svg.contains("<svg"));
+
+        let photo = DynamicImage::new_rgb8(60, 80);
+        let pdf = card_pdf("Łukasz Żółć", 5, Some(&photo), "5").unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn test_pdf_safe_folds_polish_letters() {
+        // ó is in Latin-1 and stays
+        assert_eq!(pdf_safe("Łukasz Żółć"), "Lukasz Zólc");
+        assert_eq!(pdf_safe("José 東"), "José ?");
+    }
+}
@@ -1,7 +1,16 @@
-use crate::models::{AccessLog, CreateEmployeeRequest, Employee, UpdateEmployeeRequest, WorkHours};
+use crate::models::{
+    AccessLog, AccessSchedule, AuditEntry, AuditQuery, Badge, BreakRule, CreateEmployeeRequest,
+    CreateScheduleRequest, CreateSiteRequest, CreateVisitorRequest, Door, Employee, ErasedFiles,
+    EscortAuthorization, ExpiredPhoto, FailedAttempt, FailedAttemptQuery, GrantZoneRequest,
+    HeartbeatRequest, Holiday, Lockout, NewAuditEntry, NewFailedAttempt, PassbackReset,
+    PassbackState, PassbackViolation, PresenceEntry, RegisterStationRequest, RetentionRule,
+    SetModeRequest, Site, SiteModeChange, Station, StationConfig, UpdateEmployeeRequest, Visitor,
+    VisitorLog, VisitorPass, WorkBreak, WorkHours, WorkHoursAudit, Zone, ZonePermission,
+};
 use async_trait::async_trait;
 use mockall::automock;
 use sqlx::{PgPool, Row};
+use uuid::Uuid;
 
 // neeed more beer
 
@@ -19,21 +28,368 @@ pub trait DatabaseRepository: Send + Sync {
         &self,
         id: i32,
     ) -> Result<Option<(i32, String, String)>, sqlx::Error>;
+    async fn get_employee(&self, id: i32) -> Result<Option<Employee>, sqlx::Error>;
     async fn get_employee_embedding(&self, id: i32) -> Result<Option<Vec<u8>>, sqlx::Error>;
+    async fn get_employee_role(&self, id: i32) -> Result<Option<String>, sqlx::Error>;
+    async fn get_login_credentials(
+        &self,
+        login: String,
+    ) -> Result<Option<(i32, Option<String>)>, sqlx::Error>;
+    async fn set_password_hash(&self, id: i32, password_hash: String) -> Result<(), sqlx::Error>;
+    async fn create_session(
+        &self,
+        id_employee: i32,
+        token_hash: Vec<u8>,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), sqlx::Error>;
+    async fn get_session_employee(
+        &self,
+        token_hash: Vec<u8>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<i32>, sqlx::Error>;
+    async fn delete_session(&self, token_hash: Vec<u8>) -> Result<u64, sqlx::Error>;
     async fn add_access_log(
         &self,
+        event_id: Option<Uuid>,
         id: i32,
         direction: String,
-        timestamp: chrono::NaiveDateTime,
-    ) -> Result<(), sqlx::Error>;
+        station: Option<String>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(AccessLog, bool), sqlx::Error>;
     async fn create_employee(&self, req: CreateEmployeeRequest) -> Result<i32, sqlx::Error>;
     async fn update_employee(&self, id: i32, req: UpdateEmployeeRequest)
         -> Result<(), sqlx::Error>;
-    async fn delete_employee(&self, id: i32) -> Result<u64, sqlx::Error>;
-    async fn get_employees(&self) -> Result<Vec<Employee>, sqlx::Error>;
+    async fn archive_employee(
+        &self,
+        id: i32,
+        archived_by: i32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, sqlx::Error>;
+    async fn restore_employee(&self, id: i32) -> Result<u64, sqlx::Error>;
+    async fn get_employees(&self, include_archived: bool) -> Result<Vec<Employee>, sqlx::Error>;
     async fn get_work_hours(&self) -> Result<Vec<WorkHours>, sqlx::Error>;
     async fn start_shift(&self, id: i32) -> Result<(), sqlx::Error>;
     async fn end_shift(&self, id: i32) -> Result<u64, sqlx::Error>;
+    async fn get_work_hours_record(&self, id: i32) -> Result<Option<WorkHours>, sqlx::Error>;
+    async fn create_work_hours(
+        &self,
+        id_employee: i32,
+        time_start: chrono::DateTime<chrono::Utc>,
+        time_end: Option<chrono::DateTime<chrono::Utc>>,
+        changed_by: i32,
+        reason: String,
+    ) -> Result<Option<i32>, sqlx::Error>;
+    async fn update_work_hours(
+        &self,
+        id: i32,
+        time_start: chrono::DateTime<chrono::Utc>,
+        time_end: Option<chrono::DateTime<chrono::Utc>>,
+        changed_by: i32,
+        reason: String,
+    ) -> Result<Option<u64>, sqlx::Error>;
+    async fn delete_work_hours(
+        &self,
+        id: i32,
+        changed_by: i32,
+        reason: String,
+    ) -> Result<u64, sqlx::Error>;
+    async fn get_work_hours_history(&self, id: i32) -> Result<Vec<WorkHoursAudit>, sqlx::Error>;
+    async fn start_break(&self, id_employee: i32) -> Result<u64, sqlx::Error>;
+    async fn end_break(&self, id_employee: i32) -> Result<u64, sqlx::Error>;
+    async fn get_breaks(&self) -> Result<Vec<WorkBreak>, sqlx::Error>;
+    async fn get_break_rules(&self) -> Result<Vec<BreakRule>, sqlx::Error>;
+    async fn set_break_rules(&self, rules: Vec<BreakRule>) -> Result<(), sqlx::Error>;
+    async fn add_failed_attempt(&self, attempt: NewFailedAttempt) -> Result<i32, sqlx::Error>;
+    async fn add_synced_failed_attempt(
+        &self,
+        event_id: Uuid,
+        attempt: NewFailedAttempt,
+    ) -> Result<(i32, bool), sqlx::Error>;
+    async fn import_failed_attempts(
+        &self,
+        attempts: Vec<NewFailedAttempt>,
+    ) -> Result<u64, sqlx::Error>;
+    async fn get_failed_attempts(
+        &self,
+        filter: FailedAttemptQuery,
+        page: i64,
+        page_size: i64,
+    ) -> Result<(Vec<FailedAttempt>, i64), sqlx::Error>;
+    async fn get_failed_attempt(&self, id: i32) -> Result<Option<FailedAttempt>, sqlx::Error>;
+    async fn get_retention_rules(&self) -> Result<Vec<RetentionRule>, sqlx::Error>;
+    async fn set_retention_rules(&self, rules: Vec<RetentionRule>) -> Result<(), sqlx::Error>;
+    async fn get_expired_attempt_photos(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<ExpiredPhoto>, sqlx::Error>;
+    async fn clear_attempt_images(&self, ids: Vec<i32>) -> Result<u64, sqlx::Error>;
+    async fn active_lockout(
+        &self,
+        subject_type: String,
+        subject: String,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<Lockout>, sqlx::Error>;
+    async fn count_failures_since(
+        &self,
+        subject_type: String,
+        subject: String,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<i64, sqlx::Error>;
+    async fn add_lockout(
+        &self,
+        subject_type: String,
+        subject: String,
+        locked_until: chrono::DateTime<chrono::Utc>,
+    ) -> Result<i32, sqlx::Error>;
+    async fn unlock(
+        &self,
+        subject_type: String,
+        subject: String,
+        unlocked_by: i32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, sqlx::Error>;
+    async fn get_active_lockouts(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<Lockout>, sqlx::Error>;
+    async fn create_badge(
+        &self,
+        employee_id: i32,
+        issued_at: chrono::DateTime<chrono::Utc>,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Badge, sqlx::Error>;
+    async fn get_badge(&self, id: i32) -> Result<Option<Badge>, sqlx::Error>;
+    async fn get_badges(&self, employee_id: i32) -> Result<Vec<Badge>, sqlx::Error>;
+    async fn revoke_badge(
+        &self,
+        id: i32,
+        revoked_by: i32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, sqlx::Error>;
+    async fn set_totp_secret(&self, employee_id: i32, secret: Vec<u8>) -> Result<(), sqlx::Error>;
+    async fn get_totp_secret(&self, employee_id: i32) -> Result<Option<Vec<u8>>, sqlx::Error>;
+    async fn use_totp_step(&self, employee_id: i32, step: i64) -> Result<bool, sqlx::Error>;
+    async fn delete_totp(&self, employee_id: i32) -> Result<u64, sqlx::Error>;
+    async fn create_station(
+        &self,
+        req: RegisterStationRequest,
+        token_hash: Vec<u8>,
+    ) -> Result<Option<Station>, sqlx::Error>;
+    async fn set_station_token(
+        &self,
+        id: i32,
+        token_hash: Vec<u8>,
+    ) -> Result<Option<Station>, sqlx::Error>;
+    async fn station_heartbeat(
+        &self,
+        token_hash: Vec<u8>,
+        req: HeartbeatRequest,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<Station>, sqlx::Error>;
+    async fn get_stations(&self) -> Result<Vec<Station>, sqlx::Error>;
+    async fn get_station_by_token(
+        &self,
+        token_hash: Vec<u8>,
+    ) -> Result<Option<Station>, sqlx::Error>;
+    async fn get_station_config(&self, id: i32) -> Result<Option<StationConfig>, sqlx::Error>;
+    async fn update_station_config(
+        &self,
+        config: StationConfig,
+    ) -> Result<Option<StationConfig>, sqlx::Error>;
+    async fn get_face_threshold(&self, id_station: i32) -> Result<Option<f32>, sqlx::Error>;
+    async fn set_station_door(
+        &self,
+        id: i32,
+        id_door: Option<i32>,
+    ) -> Result<Option<Station>, sqlx::Error>;
+    async fn create_site(&self, req: CreateSiteRequest) -> Result<Option<Site>, sqlx::Error>;
+    async fn get_sites(&self) -> Result<Vec<Site>, sqlx::Error>;
+    async fn create_zone(&self, id_site: i32, name: String) -> Result<Option<Zone>, sqlx::Error>;
+    async fn get_zones(&self) -> Result<Vec<Zone>, sqlx::Error>;
+    async fn create_door(&self, id_zone: i32, name: String) -> Result<Option<Door>, sqlx::Error>;
+    async fn get_doors(&self) -> Result<Vec<Door>, sqlx::Error>;
+    async fn get_zone_permissions(&self, id_zone: i32) -> Result<Vec<ZonePermission>, sqlx::Error>;
+    async fn grant_zone_permission(
+        &self,
+        id_zone: i32,
+        req: GrantZoneRequest,
+        granted_by: i32,
+    ) -> Result<Option<ZonePermission>, sqlx::Error>;
+    async fn revoke_zone_permission(
+        &self,
+        id_zone: i32,
+        id_permission: i32,
+    ) -> Result<u64, sqlx::Error>;
+    async fn zone_permitted(
+        &self,
+        employee_id: i32,
+        station: String,
+    ) -> Result<Option<bool>, sqlx::Error>;
+    async fn get_applicable_schedules(
+        &self,
+        employee_id: i32,
+        station: Option<String>,
+    ) -> Result<Vec<AccessSchedule>, sqlx::Error>;
+    async fn get_schedules(&self) -> Result<Vec<AccessSchedule>, sqlx::Error>;
+    async fn create_schedule(
+        &self,
+        req: CreateScheduleRequest,
+        created_by: i32,
+    ) -> Result<AccessSchedule, sqlx::Error>;
+    async fn delete_schedule(&self, id: i32) -> Result<u64, sqlx::Error>;
+    async fn get_holidays(
+        &self,
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+    ) -> Result<Vec<Holiday>, sqlx::Error>;
+    async fn set_holidays(&self, holidays: Vec<Holiday>) -> Result<(), sqlx::Error>;
+    async fn create_visitor(
+        &self,
+        req: CreateVisitorRequest,
+        created_by: i32,
+    ) -> Result<Visitor, sqlx::Error>;
+    async fn get_visitors(&self) -> Result<Vec<Visitor>, sqlx::Error>;
+    async fn get_visitor(&self, id: i32) -> Result<Option<Visitor>, sqlx::Error>;
+    async fn update_visitor_photo(
+        &self,
+        id: i32,
+        embedding: Vec<u8>,
+        photo_path: String,
+    ) -> Result<u64, sqlx::Error>;
+    async fn get_visitor_embedding(&self, id: i32) -> Result<Option<Vec<u8>>, sqlx::Error>;
+    async fn create_visitor_pass(
+        &self,
+        id_visitor: i32,
+        max_uses: Option<i32>,
+        issued_at: chrono::DateTime<chrono::Utc>,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<VisitorPass, sqlx::Error>;
+    async fn get_visitor_pass(&self, id: i32) -> Result<Option<VisitorPass>, sqlx::Error>;
+    async fn get_visitor_passes(&self, id_visitor: i32) -> Result<Vec<VisitorPass>, sqlx::Error>;
+    async fn use_visitor_pass(&self, id: i32) -> Result<bool, sqlx::Error>;
+    async fn revoke_visitor_pass(
+        &self,
+        id: i32,
+        revoked_by: i32,
+        revoked_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, sqlx::Error>;
+    async fn add_visitor_log(
+        &self,
+        event_id: Option<Uuid>,
+        id_pass: i32,
+        direction: String,
+        station: Option<String>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<(VisitorLog, bool)>, sqlx::Error>;
+    async fn get_visitor_logs(&self) -> Result<Vec<VisitorLog>, sqlx::Error>;
+    async fn set_site_mode(
+        &self,
+        req: SetModeRequest,
+        changed_by: i32,
+    ) -> Result<SiteModeChange, sqlx::Error>;
+    async fn get_site_modes(&self) -> Result<Vec<SiteModeChange>, sqlx::Error>;
+    async fn get_site_mode_history(&self) -> Result<Vec<SiteModeChange>, sqlx::Error>;
+    async fn station_mode(
+        &self,
+        station: Option<String>,
+    ) -> Result<Option<SiteModeChange>, sqlx::Error>;
+    async fn get_presence(&self) -> Result<Vec<PresenceEntry>, sqlx::Error>;
+    async fn set_zone_anti_passback(
+        &self,
+        id_zone: i32,
+        mode: String,
+    ) -> Result<Option<Zone>, sqlx::Error>;
+    async fn passback_state(
+        &self,
+        employee_id: i32,
+        station: String,
+    ) -> Result<Option<PassbackState>, sqlx::Error>;
+    async fn reset_passback(
+        &self,
+        employee_id: i32,
+        id_zone: Option<i32>,
+        reset_by: i32,
+    ) -> Result<PassbackReset, sqlx::Error>;
+    async fn add_passback_violation(&self, id_log: i32, id_zone: i32) -> Result<(), sqlx::Error>;
+    async fn get_passback_violations(&self) -> Result<Vec<PassbackViolation>, sqlx::Error>;
+    async fn set_zone_escort(
+        &self,
+        id_zone: i32,
+        escort_role: Option<String>,
+    ) -> Result<Option<Zone>, sqlx::Error>;
+    async fn station_zone(&self, station: String) -> Result<Option<Zone>, sqlx::Error>;
+    async fn request_escort(
+        &self,
+        station: String,
+        id_zone: i32,
+        id_employee: Option<i32>,
+        id_pass: Option<i32>,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<EscortAuthorization, sqlx::Error>;
+    async fn confirm_escort(
+        &self,
+        station: String,
+        escort_id: i32,
+    ) -> Result<Option<EscortAuthorization>, sqlx::Error>;
+    async fn complete_escort(
+        &self,
+        id_authorization: i32,
+        station: String,
+        id_employee: Option<i32>,
+        id_pass: Option<i32>,
+        event_id: Option<Uuid>,
+    ) -> Result<Option<EscortAuthorization>, sqlx::Error>;
+    async fn get_escort_authorizations(&self) -> Result<Vec<EscortAuthorization>, sqlx::Error>;
+    async fn add_audit_entry(&self, entry: NewAuditEntry) -> Result<(), sqlx::Error>;
+    async fn get_audit_entries(
+        &self,
+        filter: AuditQuery,
+        page: i64,
+        page_size: i64,
+    ) -> Result<(Vec<AuditEntry>, i64), sqlx::Error>;
+    async fn get_subject_records(
+        &self,
+        id: i32,
+    ) -> Result<Vec<(String, serde_json::Value)>, sqlx::Error>;
+    async fn erase_employee(
+        &self,
+        id: i32,
+        erased_by: i32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<ErasedFiles>, sqlx::Error>;
+}
+
+/// Whether the employee has another record overlapping the given times. Locks the
+/// employee first, so two edits of their hours can't both pass before either is written.
+async fn hours_overlap(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    id_employee: i32,
+    time_start: chrono::DateTime<chrono::Utc>,
+    time_end: Option<chrono::DateTime<chrono::Utc>>,
+    exclude_record: Option<i32>,
+) -> Result<bool, sqlx::Error> {
+    // NO KEY, so access logs referencing the employee can still be written meanwhile
+    sqlx::query("SELECT 1 FROM employees WHERE id_person = $1 FOR NO KEY UPDATE")
+        .bind(id_employee)
+        .execute(&mut **tx)
+        .await?;
+
+    // open shifts (time_end IS NULL) count as running forever
+    let query = "SELECT EXISTS (
+        SELECT 1 FROM hours
+        WHERE id_employee = $1
+          AND ($4::INT IS NULL OR id_record <> $4)
+          AND time_start < COALESCE($3::TIMESTAMPTZ, 'infinity'::TIMESTAMPTZ)
+          AND COALESCE(time_end, 'infinity'::TIMESTAMPTZ) > $2
+    ) AS overlap";
+    let row = sqlx::query(query)
+        .bind(id_employee)
+        .bind(time_start)
+        .bind(time_end)
+        .bind(exclude_record)
+        .fetch_one(&mut **tx)
+        .await?;
+    Ok(row.get("overlap"))
 }
 
 pub struct PostgresRepository {
@@ -50,7 +406,7 @@ impl PostgresRepository {
 impl DatabaseRepository for PostgresRepository {
     async fn get_access_logs(&self) -> Result<Vec<AccessLog>, sqlx::Error> {
         let query =
-            "SELECT id_log, id_employee, direction, timestamp FROM access_logs ORDER BY timestamp DESC";
+            "SELECT id_log, id_employee, direction, timestamp, event_id, station, id_door FROM access_logs ORDER BY timestamp DESC";
         sqlx::query_as::<_, AccessLog>(query)
             .fetch_all(&self.pool)
             .await
@@ -76,7 +432,9 @@ impl DatabaseRepository for PostgresRepository {
         &self,
         id: i32,
     ) -> Result<Option<(i32, String, String)>, sqlx::Error> {
-        let query = "SELECT id_person, first_name, last_name FROM employees WHERE id_person = $1";
+        // archived people are unknown to the stations and the rest of the API
+        let query = "SELECT id_person, first_name, last_name FROM employees
+            WHERE id_person = $1 AND archived_at IS NULL";
         let row = sqlx::query(query)
             .bind(id)
             .fetch_optional(&self.pool)
@@ -105,21 +463,48 @@ impl DatabaseRepository for PostgresRepository {
         }
     }
 
+    async fn get_employee_role(&self, id: i32) -> Result<Option<String>, sqlx::Error> {
+        let query = "SELECT role FROM employees WHERE id_person = $1 AND archived_at IS NULL";
+        let row = sqlx::query(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("role")))
+    }
+
+    /// The flag is false when `event_id` was seen before, the row is then the original one.
     async fn add_access_log(
         &self,
+        event_id: Option<Uuid>,
         id: i32,
         direction: String,
-        timestamp: chrono::NaiveDateTime,
-    ) -> Result<(), sqlx::Error> {
-        let query =
-            "INSERT INTO access_logs (id_employee, direction, timestamp) VALUES ($1, $2, $3)";
-        sqlx::query(query)
+        station: Option<String>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(AccessLog, bool), sqlx::Error> {
+        // the door is looked up once here, the station may be moved to another door later
+        let query = "INSERT INTO access_logs (id_employee, direction, timestamp, event_id, station, id_door)
+            VALUES ($1, $2, $3, $4, $5, (SELECT id_door FROM stations WHERE name = $5))
+            ON CONFLICT (event_id) DO NOTHING
+            RETURNING id_log, id_employee, direction, timestamp, event_id, station, id_door";
+        let inserted = sqlx::query_as::<_, AccessLog>(query)
             .bind(id)
             .bind(direction)
             .bind(timestamp)
-            .execute(&self.pool)
-            .await
-            .map(|_| ())
+            .bind(event_id)
+            .bind(station)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some(log) = inserted {
+            return Ok((log, true));
+        }
+
+        let query = "SELECT id_log, id_employee, direction, timestamp, event_id, station, id_door FROM access_logs WHERE event_id = $1";
+        let original = sqlx::query_as::<_, AccessLog>(query)
+            .bind(event_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok((original, false))
     }
 
     async fn create_employee(&self, req: CreateEmployeeRequest) -> Result<i32, sqlx::Error> {
@@ -191,19 +576,76 @@ impl DatabaseRepository for PostgresRepository {
         query.execute(&self.pool).await.map(|_| ())
     }
 
-    async fn delete_employee(&self, id: i32) -> Result<u64, sqlx::Error> {
-        let query = "DELETE FROM employees WHERE id_person = $1";
+    async fn archive_employee(
+        &self,
+        id: i32,
+        archived_by: i32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // hours and logs stay for payroll, only the biometrics and credentials go
+        let query = "UPDATE employees SET archived_at = $2, archived_by = $3,
+            face_embedded = NULL, photo_path = NULL
+            WHERE id_person = $1 AND archived_at IS NULL";
+        let result = sqlx::query(query)
+            .bind(id)
+            .bind(now)
+            .bind(archived_by)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() > 0 {
+            sqlx::query(
+                "UPDATE badges SET revoked_by = $2, revoked_at = $3
+                 WHERE id_employee = $1 AND revoked_at IS NULL",
+            )
+            .bind(id)
+            .bind(archived_by)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query("DELETE FROM employee_totp WHERE id_employee = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query("DELETE FROM sessions WHERE id_employee = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn restore_employee(&self, id: i32) -> Result<u64, sqlx::Error> {
+        // an erased employee has nothing left to come back with
+        let query = "UPDATE employees SET archived_at = NULL, archived_by = NULL
+            WHERE id_person = $1 AND archived_at IS NOT NULL AND erased_at IS NULL";
         let result = sqlx::query(query).bind(id).execute(&self.pool).await?;
         Ok(result.rows_affected())
     }
 
-    async fn get_employees(&self) -> Result<Vec<Employee>, sqlx::Error> {
-        let query = "SELECT id_person, first_name, last_name, role, date_of_termination, photo_path, account_number, login FROM employees";
+    async fn get_employees(&self, include_archived: bool) -> Result<Vec<Employee>, sqlx::Error> {
+        let query = "SELECT id_person, first_name, last_name, role, date_of_termination, photo_path, account_number, login, archived_at
+            FROM employees WHERE $1 OR archived_at IS NULL";
         sqlx::query_as::<_, Employee>(query)
+            .bind(include_archived)
             .fetch_all(&self.pool)
             .await
     }
 
+    async fn get_employee(&self, id: i32) -> Result<Option<Employee>, sqlx::Error> {
+        let query = "SELECT id_person, first_name, last_name, role, date_of_termination, photo_path, account_number, login, archived_at FROM employees WHERE id_person = $1";
+        sqlx::query_as::<_, Employee>(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
     async fn get_work_hours(&self) -> Result<Vec<WorkHours>, sqlx::Error> {
         let query =
             "SELECT id_record, id_employee, time_start, time_end FROM hours ORDER BY time_start DESC";
@@ -222,10 +664,1697 @@ impl DatabaseRepository for PostgresRepository {
     }
 
     async fn end_shift(&self, id: i32) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // a break still running when the shift ends is closed together with it
+        sqlx::query(
+            "UPDATE breaks SET break_end = CURRENT_TIMESTAMP WHERE break_end IS NULL AND id_record IN (
+                SELECT id_record FROM hours WHERE id_employee = $1 AND time_end IS NULL
+            )",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
         let query = "UPDATE hours SET time_end = CURRENT_TIMESTAMP WHERE id_record = (
             SELECT id_record FROM hours WHERE id_employee = $1 AND time_end IS NULL ORDER BY time_start DESC LIMIT 1
         )";
-        let result = sqlx::query(query).bind(id).execute(&self.pool).await?;
+        let result = sqlx::query(query).bind(id).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn get_work_hours_record(&self, id: i32) -> Result<Option<WorkHours>, sqlx::Error> {
+        let query =
+            "SELECT id_record, id_employee, time_start, time_end FROM hours WHERE id_record = $1";
+        sqlx::query_as::<_, WorkHours>(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn create_work_hours(
+        &self,
+        id_employee: i32,
+        time_start: chrono::DateTime<chrono::Utc>,
+        time_end: Option<chrono::DateTime<chrono::Utc>>,
+        changed_by: i32,
+        reason: String,
+    ) -> Result<Option<i32>, sqlx::Error> {
+        // None when it overlaps another record of the employee
+        let mut tx = self.pool.begin().await?;
+
+        if hours_overlap(&mut tx, id_employee, time_start, time_end, None).await? {
+            return Ok(None);
+        }
+
+        let row = sqlx::query(
+            "INSERT INTO hours (id_employee, time_start, time_end) VALUES ($1, $2, $3) RETURNING id_record",
+        )
+        .bind(id_employee)
+        .bind(time_start)
+        .bind(time_end)
+        .fetch_one(&mut *tx)
+        .await?;
+        let id_record: i32 = row.get("id_record");
+
+        sqlx::query(
+            "INSERT INTO hours_audit (id_record, id_employee, action, new_time_start, new_time_end, reason, changed_by)
+             VALUES ($1, $2, 'create', $3, $4, $5, $6)",
+        )
+        .bind(id_record)
+        .bind(id_employee)
+        .bind(time_start)
+        .bind(time_end)
+        .bind(reason)
+        .bind(changed_by)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(id_record))
+    }
+
+    async fn update_work_hours(
+        &self,
+        id: i32,
+        time_start: chrono::DateTime<chrono::Utc>,
+        time_end: Option<chrono::DateTime<chrono::Utc>>,
+        changed_by: i32,
+        reason: String,
+    ) -> Result<Option<u64>, sqlx::Error> {
+        // Some(0) when the record doesn't exist, None when it would overlap another one
+        let mut tx = self.pool.begin().await?;
+
+        let old = sqlx::query_as::<_, WorkHours>(
+            "SELECT id_record, id_employee, time_start, time_end FROM hours WHERE id_record = $1 FOR UPDATE",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let old = match old {
+            Some(o) => o,
+            None => return Ok(Some(0)),
+        };
+        if hours_overlap(&mut tx, old.id_employee, time_start, time_end, Some(id)).await? {
+            return Ok(None);
+        }
+
+        sqlx::query("UPDATE hours SET time_start = $1, time_end = $2 WHERE id_record = $3")
+            .bind(time_start)
+            .bind(time_end)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO hours_audit (id_record, id_employee, action, old_time_start, old_time_end, new_time_start, new_time_end, reason, changed_by)
+             VALUES ($1, $2, 'update', $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(id)
+        .bind(old.id_employee)
+        .bind(old.time_start)
+        .bind(old.time_end)
+        .bind(time_start)
+        .bind(time_end)
+        .bind(reason)
+        .bind(changed_by)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(1))
+    }
+
+    async fn delete_work_hours(
+        &self,
+        id: i32,
+        changed_by: i32,
+        reason: String,
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let old = sqlx::query_as::<_, WorkHours>(
+            "DELETE FROM hours WHERE id_record = $1 RETURNING id_record, id_employee, time_start, time_end",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let old = match old {
+            Some(o) => o,
+            None => return Ok(0),
+        };
+
+        sqlx::query(
+            "INSERT INTO hours_audit (id_record, id_employee, action, old_time_start, old_time_end, reason, changed_by)
+             VALUES ($1, $2, 'delete', $3, $4, $5, $6)",
+        )
+        .bind(id)
+        .bind(old.id_employee)
+        .bind(old.time_start)
+        .bind(old.time_end)
+        .bind(reason)
+        .bind(changed_by)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(1)
+    }
+
+    async fn get_work_hours_history(&self, id: i32) -> Result<Vec<WorkHoursAudit>, sqlx::Error> {
+        let query = "SELECT id_audit, id_record, id_employee, action, old_time_start, old_time_end, new_time_start, new_time_end, reason, changed_by, changed_at
+                     FROM hours_audit WHERE id_record = $1 ORDER BY changed_at, id_audit";
+        sqlx::query_as::<_, WorkHoursAudit>(query)
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn start_break(&self, id_employee: i32) -> Result<u64, sqlx::Error> {
+        let query = "INSERT INTO breaks (id_record, break_start)
+            SELECT h.id_record, CURRENT_TIMESTAMP FROM hours h
+            WHERE h.id_employee = $1 AND h.time_end IS NULL
+              AND NOT EXISTS (SELECT 1 FROM breaks b WHERE b.id_record = h.id_record AND b.break_end IS NULL)
+            ORDER BY h.time_start DESC LIMIT 1";
+        let result = sqlx::query(query)
+            .bind(id_employee)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn end_break(&self, id_employee: i32) -> Result<u64, sqlx::Error> {
+        let query = "UPDATE breaks SET break_end = CURRENT_TIMESTAMP WHERE break_end IS NULL AND id_record IN (
+            SELECT id_record FROM hours WHERE id_employee = $1 AND time_end IS NULL
+        )";
+        let result = sqlx::query(query)
+            .bind(id_employee)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn get_breaks(&self) -> Result<Vec<WorkBreak>, sqlx::Error> {
+        let query =
+            "SELECT id_break, id_record, break_start, break_end FROM breaks ORDER BY break_start";
+        sqlx::query_as::<_, WorkBreak>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_break_rules(&self) -> Result<Vec<BreakRule>, sqlx::Error> {
+        let query = "SELECT min_shift_minutes, required_break_minutes FROM break_rules ORDER BY min_shift_minutes";
+        sqlx::query_as::<_, BreakRule>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn set_break_rules(&self, rules: Vec<BreakRule>) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM break_rules")
+            .execute(&mut *tx)
+            .await?;
+
+        for rule in rules {
+            sqlx::query(
+                "INSERT INTO break_rules (min_shift_minutes, required_break_minutes) VALUES ($1, $2)",
+            )
+            .bind(rule.min_shift_minutes)
+            .bind(rule.required_break_minutes)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    async fn add_failed_attempt(&self, attempt: NewFailedAttempt) -> Result<i32, sqlx::Error> {
+        let query = "INSERT INTO failed_attempts (id_employee, reason, similarity, station, direction, image_path, timestamp)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id_attempt";
+        let row = sqlx::query(query)
+            .bind(attempt.id_employee)
+            .bind(attempt.reason)
+            .bind(attempt.similarity)
+            .bind(attempt.station)
+            .bind(attempt.direction)
+            .bind(attempt.image_path)
+            .bind(attempt.timestamp)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get("id_attempt"))
+    }
+
+    /// Like `add_failed_attempt`, false with the original id when `event_id` is known.
+    async fn add_synced_failed_attempt(
+        &self,
+        event_id: Uuid,
+        attempt: NewFailedAttempt,
+    ) -> Result<(i32, bool), sqlx::Error> {
+        let query = "INSERT INTO failed_attempts (id_employee, reason, similarity, station, direction, image_path, timestamp, event_id)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                     ON CONFLICT (event_id) DO NOTHING RETURNING id_attempt";
+        let inserted = sqlx::query(query)
+            .bind(attempt.id_employee)
+            .bind(attempt.reason)
+            .bind(attempt.similarity)
+            .bind(attempt.station)
+            .bind(attempt.direction)
+            .bind(attempt.image_path)
+            .bind(attempt.timestamp)
+            .bind(event_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some(row) = inserted {
+            return Ok((row.get("id_attempt"), true));
+        }
+
+        let row = sqlx::query("SELECT id_attempt FROM failed_attempts WHERE event_id = $1")
+            .bind(event_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok((row.get("id_attempt"), false))
+    }
+
+    async fn import_failed_attempts(
+        &self,
+        attempts: Vec<NewFailedAttempt>,
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut imported = 0;
+
+        // rows already in the table are skipped, so running the import twice is harmless
+        for attempt in attempts {
+            let result = sqlx::query(
+                "INSERT INTO failed_attempts (id_employee, reason, image_path, timestamp)
+                 SELECT $1, $2, $3, $4
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM failed_attempts
+                     WHERE id_employee IS NOT DISTINCT FROM $1 AND reason = $2
+                       AND image_path IS NOT DISTINCT FROM $3 AND timestamp = $4
+                 )",
+            )
+            .bind(attempt.id_employee)
+            .bind(attempt.reason)
+            .bind(attempt.image_path)
+            .bind(attempt.timestamp)
+            .execute(&mut *tx)
+            .await?;
+            imported += result.rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(imported)
+    }
+
+    async fn get_failed_attempts(
+        &self,
+        filter: FailedAttemptQuery,
+        page: i64,
+        page_size: i64,
+    ) -> Result<(Vec<FailedAttempt>, i64), sqlx::Error> {
+        fn push_filters(
+            qb: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+            filter: &FailedAttemptQuery,
+        ) {
+            qb.push(" WHERE TRUE");
+            if let Some(employee_id) = filter.employee_id {
+                qb.push(" AND id_employee = ").push_bind(employee_id);
+            }
+            if let Some(reason) = &filter.reason {
+                qb.push(" AND reason = ").push_bind(reason.clone());
+            }
+            if let Some(station) = &filter.station {
+                qb.push(" AND station = ").push_bind(station.clone());
+            }
+            if let Some(from) = filter.from {
+                qb.push(" AND timestamp >= ").push_bind(from);
+            }
+            if let Some(to) = filter.to {
+                qb.push(" AND timestamp < ").push_bind(to);
+            }
+        }
+
+        let mut count_query =
+            sqlx::QueryBuilder::new("SELECT COUNT(*) AS total FROM failed_attempts");
+        push_filters(&mut count_query, &filter);
+        let total: i64 = count_query
+            .build()
+            .fetch_one(&self.pool)
+            .await?
+            .get("total");
+
+        // column names can't be bound, so only whitelisted ones get into the query
+        let sort_column = match filter.sort_by.as_deref() {
+            Some("employee") => "id_employee",
+            Some("reason") => "reason",
+            Some("similarity") => "similarity",
+            Some("station") => "station",
+            _ => "timestamp",
+        };
+        let sort_order = match filter.order.as_deref() {
+            Some("asc") => "ASC",
+            _ => "DESC",
+        };
+
+        let mut query = sqlx::QueryBuilder::new(
+            "SELECT id_attempt, id_employee, reason, similarity, station, direction, image_path, timestamp FROM failed_attempts",
+        );
+        push_filters(&mut query, &filter);
+        query.push(format!(
+            " ORDER BY {} {} NULLS LAST, id_attempt {}",
+            sort_column, sort_order, sort_order
+        ));
+        query.push(" LIMIT ").push_bind(page_size);
+        query.push(" OFFSET ").push_bind((page - 1) * page_size);
+
+        let items = query
+            .build_query_as::<FailedAttempt>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok((items, total))
+    }
+
+    async fn get_failed_attempt(&self, id: i32) -> Result<Option<FailedAttempt>, sqlx::Error> {
+        let query = "SELECT id_attempt, id_employee, reason, similarity, station, direction, image_path, timestamp
+                     FROM failed_attempts WHERE id_attempt = $1";
+        sqlx::query_as::<_, FailedAttempt>(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_retention_rules(&self) -> Result<Vec<RetentionRule>, sqlx::Error> {
+        let query = "SELECT reason, retention_days FROM photo_retention ORDER BY reason";
+        sqlx::query_as::<_, RetentionRule>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn set_retention_rules(&self, rules: Vec<RetentionRule>) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM photo_retention")
+            .execute(&mut *tx)
+            .await?;
+
+        for rule in rules {
+            sqlx::query("INSERT INTO photo_retention (reason, retention_days) VALUES ($1, $2)")
+                .bind(rule.reason)
+                .bind(rule.retention_days)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await
+    }
+
+    async fn get_expired_attempt_photos(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<ExpiredPhoto>, sqlx::Error> {
+        // reasons with neither their own rule nor a '*' rule are kept forever
+        let query = "SELECT fa.id_attempt, fa.reason, fa.image_path, fa.timestamp
+            FROM failed_attempts fa
+            LEFT JOIN photo_retention r ON r.reason = fa.reason
+            LEFT JOIN photo_retention d ON d.reason = '*'
+            WHERE fa.image_path IS NOT NULL
+              AND fa.timestamp < $1 - make_interval(days => COALESCE(r.retention_days, d.retention_days))
+            ORDER BY fa.timestamp";
+        sqlx::query_as::<_, ExpiredPhoto>(query)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn clear_attempt_images(&self, ids: Vec<i32>) -> Result<u64, sqlx::Error> {
+        let query = "UPDATE failed_attempts SET image_path = NULL WHERE id_attempt = ANY($1)";
+        let result = sqlx::query(query).bind(ids).execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn active_lockout(
+        &self,
+        subject_type: String,
+        subject: String,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<Lockout>, sqlx::Error> {
+        let query = "SELECT id_lockout, subject_type, subject, locked_at, locked_until, unlocked_by, unlocked_at
+            FROM lockouts
+            WHERE subject_type = $1 AND subject = $2 AND unlocked_at IS NULL AND locked_until > $3
+            ORDER BY locked_until DESC LIMIT 1";
+        sqlx::query_as::<_, Lockout>(query)
+            .bind(subject_type)
+            .bind(subject)
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn count_failures_since(
+        &self,
+        subject_type: String,
+        subject: String,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<i64, sqlx::Error> {
+        // the count starts over after every lockout (or unlock), otherwise one more
+        // failure right after a lockout ends would lock again straight away.
+        // employees are counted on face mismatches only, stations on any failure.
+        let query = "SELECT COUNT(*) AS failures FROM failed_attempts
+            WHERE timestamp > $3
+              AND timestamp > COALESCE((
+                  SELECT MAX(COALESCE(unlocked_at, locked_at)) FROM lockouts
+                  WHERE subject_type = $1 AND subject = $2
+              ), '-infinity'::TIMESTAMPTZ)
+              AND CASE WHEN $1 = 'employee'
+                  THEN id_employee::TEXT = $2 AND reason = 'face_mismatched'
+                  ELSE station = $2
+              END";
+        let row = sqlx::query(query)
+            .bind(subject_type)
+            .bind(subject)
+            .bind(since)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get("failures"))
+    }
+
+    async fn add_lockout(
+        &self,
+        subject_type: String,
+        subject: String,
+        locked_until: chrono::DateTime<chrono::Utc>,
+    ) -> Result<i32, sqlx::Error> {
+        let query = "INSERT INTO lockouts (subject_type, subject, locked_until) VALUES ($1, $2, $3) RETURNING id_lockout";
+        let row = sqlx::query(query)
+            .bind(subject_type)
+            .bind(subject)
+            .bind(locked_until)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get("id_lockout"))
+    }
+
+    async fn unlock(
+        &self,
+        subject_type: String,
+        subject: String,
+        unlocked_by: i32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let query = "UPDATE lockouts SET unlocked_by = $3, unlocked_at = $4
+            WHERE subject_type = $1 AND subject = $2 AND unlocked_at IS NULL AND locked_until > $4";
+        let result = sqlx::query(query)
+            .bind(subject_type)
+            .bind(subject)
+            .bind(unlocked_by)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn get_active_lockouts(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<Lockout>, sqlx::Error> {
+        let query = "SELECT id_lockout, subject_type, subject, locked_at, locked_until, unlocked_by, unlocked_at
+            FROM lockouts WHERE unlocked_at IS NULL AND locked_until > $1 ORDER BY locked_at DESC";
+        sqlx::query_as::<_, Lockout>(query)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn create_badge(
+        &self,
+        employee_id: i32,
+        issued_at: chrono::DateTime<chrono::Utc>,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Badge, sqlx::Error> {
+        let query = "INSERT INTO badges (id_employee, issued_at, expires_at) VALUES ($1, $2, $3)
+            RETURNING id_badge, id_employee, issued_at, expires_at, revoked_by, revoked_at";
+        sqlx::query_as::<_, Badge>(query)
+            .bind(employee_id)
+            .bind(issued_at)
+            .bind(expires_at)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn get_badge(&self, id: i32) -> Result<Option<Badge>, sqlx::Error> {
+        let query = "SELECT id_badge, id_employee, issued_at, expires_at, revoked_by, revoked_at
+            FROM badges WHERE id_badge = $1";
+        sqlx::query_as::<_, Badge>(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_badges(&self, employee_id: i32) -> Result<Vec<Badge>, sqlx::Error> {
+        let query = "SELECT id_badge, id_employee, issued_at, expires_at, revoked_by, revoked_at
+            FROM badges WHERE id_employee = $1 ORDER BY issued_at DESC";
+        sqlx::query_as::<_, Badge>(query)
+            .bind(employee_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn revoke_badge(
+        &self,
+        id: i32,
+        revoked_by: i32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let query = "UPDATE badges SET revoked_by = $2, revoked_at = $3
+            WHERE id_badge = $1 AND revoked_at IS NULL";
+        let result = sqlx::query(query)
+            .bind(id)
+            .bind(revoked_by)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn set_totp_secret(&self, employee_id: i32, secret: Vec<u8>) -> Result<(), sqlx::Error> {
+        // enrolling again replaces the secret, so the old phone stops working
+        let query = "INSERT INTO employee_totp (id_employee, secret) VALUES ($1, $2)
+            ON CONFLICT (id_employee) DO UPDATE
+            SET secret = EXCLUDED.secret, enrolled_at = CURRENT_TIMESTAMP, last_step = NULL";
+        sqlx::query(query)
+            .bind(employee_id)
+            .bind(secret)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+    }
+
+    async fn get_totp_secret(&self, employee_id: i32) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        let query = "SELECT secret FROM employee_totp WHERE id_employee = $1";
+        let row = sqlx::query(query)
+            .bind(employee_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get("secret")))
+    }
+
+    async fn use_totp_step(&self, employee_id: i32, step: i64) -> Result<bool, sqlx::Error> {
+        // only moves forward, so a code (or an older one) can't be scanned again
+        let query = "UPDATE employee_totp SET last_step = $2
+            WHERE id_employee = $1 AND (last_step IS NULL OR last_step < $2)";
+        let result = sqlx::query(query)
+            .bind(employee_id)
+            .bind(step)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_totp(&self, employee_id: i32) -> Result<u64, sqlx::Error> {
+        let query = "DELETE FROM employee_totp WHERE id_employee = $1";
+        let result = sqlx::query(query)
+            .bind(employee_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn create_station(
+        &self,
+        req: RegisterStationRequest,
+        token_hash: Vec<u8>,
+    ) -> Result<Option<Station>, sqlx::Error> {
+        // None when the name is taken
+        let query = "INSERT INTO stations (name, location, direction, id_door, token_hash)
+            VALUES ($1, $2, $3, $4, $5) ON CONFLICT (name) DO NOTHING
+            RETURNING id_station, name, location, direction, id_door,
+            (SELECT name FROM doors WHERE doors.id_door = stations.id_door) AS door, registered_at, last_seen_at, version, camera_status, config_version";
+        sqlx::query_as::<_, Station>(query)
+            .bind(req.name.trim())
+            .bind(req.location)
+            .bind(req.direction)
+            .bind(req.id_door)
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn set_station_token(
+        &self,
+        id: i32,
+        token_hash: Vec<u8>,
+    ) -> Result<Option<Station>, sqlx::Error> {
+        let query = "UPDATE stations SET token_hash = $2 WHERE id_station = $1
+            RETURNING id_station, name, location, direction, id_door,
+            (SELECT name FROM doors WHERE doors.id_door = stations.id_door) AS door, registered_at, last_seen_at, version, camera_status, config_version";
+        sqlx::query_as::<_, Station>(query)
+            .bind(id)
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn station_heartbeat(
+        &self,
+        token_hash: Vec<u8>,
+        req: HeartbeatRequest,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<Station>, sqlx::Error> {
+        let query = "UPDATE stations SET last_seen_at = $2, version = $3, camera_status = $4
+            WHERE token_hash = $1
+            RETURNING id_station, name, location, direction, id_door,
+            (SELECT name FROM doors WHERE doors.id_door = stations.id_door) AS door, registered_at, last_seen_at, version, camera_status, config_version";
+        sqlx::query_as::<_, Station>(query)
+            .bind(token_hash)
+            .bind(now)
+            .bind(req.version)
+            .bind(req.camera_status)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_stations(&self) -> Result<Vec<Station>, sqlx::Error> {
+        let query = "SELECT id_station, name, location, direction, id_door,
+            (SELECT name FROM doors WHERE doors.id_door = stations.id_door) AS door, registered_at, last_seen_at, version, camera_status, config_version
+            FROM stations ORDER BY name";
+        sqlx::query_as::<_, Station>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_station_by_token(
+        &self,
+        token_hash: Vec<u8>,
+    ) -> Result<Option<Station>, sqlx::Error> {
+        let query = "SELECT id_station, name, location, direction, id_door,
+            (SELECT name FROM doors WHERE doors.id_door = stations.id_door) AS door, registered_at, last_seen_at, version, camera_status, config_version
+            FROM stations WHERE token_hash = $1";
+        sqlx::query_as::<_, Station>(query)
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_station_config(&self, id: i32) -> Result<Option<StationConfig>, sqlx::Error> {
+        let query =
+            "SELECT id_station, config_version AS version, direction, fps, countdown_seconds,
+            request_timeout_seconds, message_seconds, face_threshold
+            FROM stations WHERE id_station = $1";
+        sqlx::query_as::<_, StationConfig>(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn update_station_config(
+        &self,
+        config: StationConfig,
+    ) -> Result<Option<StationConfig>, sqlx::Error> {
+        // `config.version` is the version the change was based on, None if it moved on meanwhile
+        let query = "UPDATE stations SET config_version = config_version + 1, direction = $3, fps = $4,
+            countdown_seconds = $5, request_timeout_seconds = $6, message_seconds = $7, face_threshold = $8
+            WHERE id_station = $1 AND config_version = $2
+            RETURNING id_station, config_version AS version, direction, fps, countdown_seconds,
+            request_timeout_seconds, message_seconds, face_threshold";
+        sqlx::query_as::<_, StationConfig>(query)
+            .bind(config.id_station)
+            .bind(config.version)
+            .bind(config.direction)
+            .bind(config.fps)
+            .bind(config.countdown_seconds)
+            .bind(config.request_timeout_seconds)
+            .bind(config.message_seconds)
+            .bind(config.face_threshold)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_face_threshold(&self, id_station: i32) -> Result<Option<f32>, sqlx::Error> {
+        let query = "SELECT face_threshold FROM stations WHERE id_station = $1";
+        let row = sqlx::query(query)
+            .bind(id_station)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get("face_threshold")))
+    }
+
+    async fn set_station_door(
+        &self,
+        id: i32,
+        id_door: Option<i32>,
+    ) -> Result<Option<Station>, sqlx::Error> {
+        let query = "UPDATE stations SET id_door = $2 WHERE id_station = $1
+            RETURNING id_station, name, location, direction, id_door,
+            (SELECT name FROM doors WHERE doors.id_door = stations.id_door) AS door, registered_at, last_seen_at, version, camera_status, config_version";
+        sqlx::query_as::<_, Station>(query)
+            .bind(id)
+            .bind(id_door)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn create_site(&self, req: CreateSiteRequest) -> Result<Option<Site>, sqlx::Error> {
+        // None when the name is taken
+        let query = "INSERT INTO sites (name, address) VALUES ($1, $2) ON CONFLICT DO NOTHING
+            RETURNING id_site, name, address";
+        sqlx::query_as::<_, Site>(query)
+            .bind(req.name.trim())
+            .bind(req.address)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_sites(&self) -> Result<Vec<Site>, sqlx::Error> {
+        let query = "SELECT id_site, name, address FROM sites ORDER BY name";
+        sqlx::query_as::<_, Site>(query).fetch_all(&self.pool).await
+    }
+
+    async fn create_zone(&self, id_site: i32, name: String) -> Result<Option<Zone>, sqlx::Error> {
+        // None when the name is taken on that site
+        let query = "INSERT INTO zones (id_site, name) VALUES ($1, $2) ON CONFLICT DO NOTHING
+            RETURNING id_zone, id_site, name, anti_passback, escort_role";
+        sqlx::query_as::<_, Zone>(query)
+            .bind(id_site)
+            .bind(name.trim())
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_zones(&self) -> Result<Vec<Zone>, sqlx::Error> {
+        let query =
+            "SELECT id_zone, id_site, name, anti_passback, escort_role FROM zones ORDER BY id_site, name";
+        sqlx::query_as::<_, Zone>(query).fetch_all(&self.pool).await
+    }
+
+    async fn create_door(&self, id_zone: i32, name: String) -> Result<Option<Door>, sqlx::Error> {
+        let query = "INSERT INTO doors (id_zone, name) VALUES ($1, $2) ON CONFLICT DO NOTHING
+            RETURNING id_door, id_zone, name";
+        sqlx::query_as::<_, Door>(query)
+            .bind(id_zone)
+            .bind(name.trim())
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_doors(&self) -> Result<Vec<Door>, sqlx::Error> {
+        let query = "SELECT id_door, id_zone, name FROM doors ORDER BY id_zone, name";
+        sqlx::query_as::<_, Door>(query).fetch_all(&self.pool).await
+    }
+
+    async fn get_zone_permissions(&self, id_zone: i32) -> Result<Vec<ZonePermission>, sqlx::Error> {
+        let query = "SELECT id_permission, id_zone, id_employee, role, granted_by, granted_at
+            FROM zone_permissions WHERE id_zone = $1 ORDER BY granted_at";
+        sqlx::query_as::<_, ZonePermission>(query)
+            .bind(id_zone)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn grant_zone_permission(
+        &self,
+        id_zone: i32,
+        req: GrantZoneRequest,
+        granted_by: i32,
+    ) -> Result<Option<ZonePermission>, sqlx::Error> {
+        // None when it's already granted
+        let query = "INSERT INTO zone_permissions (id_zone, id_employee, role, granted_by)
+            VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING
+            RETURNING id_permission, id_zone, id_employee, role, granted_by, granted_at";
+        sqlx::query_as::<_, ZonePermission>(query)
+            .bind(id_zone)
+            .bind(req.employee_id)
+            .bind(req.role)
+            .bind(granted_by)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn revoke_zone_permission(
+        &self,
+        id_zone: i32,
+        id_permission: i32,
+    ) -> Result<u64, sqlx::Error> {
+        let query = "DELETE FROM zone_permissions WHERE id_zone = $1 AND id_permission = $2";
+        let result = sqlx::query(query)
+            .bind(id_zone)
+            .bind(id_permission)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn zone_permitted(
+        &self,
+        employee_id: i32,
+        station: String,
+    ) -> Result<Option<bool>, sqlx::Error> {
+        // no row when the station is unknown or not bound to a door
+        let query = "SELECT EXISTS (
+                SELECT 1 FROM zone_permissions p
+                WHERE p.id_zone = d.id_zone AND (p.id_employee = $1
+                    OR p.role = (SELECT role FROM employees WHERE id_person = $1))
+            ) AS permitted
+            FROM stations s JOIN doors d ON d.id_door = s.id_door
+            WHERE s.name = $2";
+        let row = sqlx::query(query)
+            .bind(employee_id)
+            .bind(station)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get("permitted")))
+    }
+
+    async fn get_applicable_schedules(
+        &self,
+        employee_id: i32,
+        station: Option<String>,
+    ) -> Result<Vec<AccessSchedule>, sqlx::Error> {
+        // the employee's own, their role's and those of the zone behind the station's door
+        let query =
+            "SELECT id_schedule, id_employee, role, id_zone, weekdays, start_time, end_time,
+            valid_from, valid_until, on_holidays, created_by, created_at
+            FROM access_schedules
+            WHERE id_employee = $1
+                OR role = (SELECT role FROM employees WHERE id_person = $1)
+                OR id_zone = (SELECT d.id_zone FROM stations s JOIN doors d ON d.id_door = s.id_door
+                    WHERE s.name = $2)";
+        sqlx::query_as::<_, AccessSchedule>(query)
+            .bind(employee_id)
+            .bind(station)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_schedules(&self) -> Result<Vec<AccessSchedule>, sqlx::Error> {
+        let query =
+            "SELECT id_schedule, id_employee, role, id_zone, weekdays, start_time, end_time,
+            valid_from, valid_until, on_holidays, created_by, created_at
+            FROM access_schedules ORDER BY id_schedule";
+        sqlx::query_as::<_, AccessSchedule>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn create_schedule(
+        &self,
+        req: CreateScheduleRequest,
+        created_by: i32,
+    ) -> Result<AccessSchedule, sqlx::Error> {
+        let query = "INSERT INTO access_schedules (id_employee, role, id_zone, weekdays, start_time, end_time,
+                valid_from, valid_until, on_holidays, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id_schedule, id_employee, role, id_zone, weekdays, start_time, end_time,
+            valid_from, valid_until, on_holidays, created_by, created_at";
+        sqlx::query_as::<_, AccessSchedule>(query)
+            .bind(req.employee_id)
+            .bind(req.role)
+            .bind(req.id_zone)
+            .bind(req.weekdays)
+            .bind(req.start_time)
+            .bind(req.end_time)
+            .bind(req.valid_from)
+            .bind(req.valid_until)
+            .bind(req.on_holidays)
+            .bind(created_by)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn delete_schedule(&self, id: i32) -> Result<u64, sqlx::Error> {
+        let query = "DELETE FROM access_schedules WHERE id_schedule = $1";
+        let result = sqlx::query(query).bind(id).execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn get_holidays(
+        &self,
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+    ) -> Result<Vec<Holiday>, sqlx::Error> {
+        let query = "SELECT day, name FROM holidays
+            WHERE ($1::date IS NULL OR day >= $1) AND ($2::date IS NULL OR day <= $2) ORDER BY day";
+        sqlx::query_as::<_, Holiday>(query)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn set_holidays(&self, holidays: Vec<Holiday>) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM holidays")
+            .execute(&mut *tx)
+            .await?;
+        for holiday in holidays {
+            sqlx::query("INSERT INTO holidays (day, name) VALUES ($1, $2)")
+                .bind(holiday.day)
+                .bind(holiday.name)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    async fn create_visitor(
+        &self,
+        req: CreateVisitorRequest,
+        created_by: i32,
+    ) -> Result<Visitor, sqlx::Error> {
+        let query = "INSERT INTO visitors (first_name, last_name, company, kind, id_host, valid_from,
+                valid_until, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id_visitor, first_name, last_name, company, kind, id_host, valid_from, valid_until,
+            photo_path, COALESCE(length(face_embedded), 0) > 0 AS face_enrolled, created_by, created_at";
+        sqlx::query_as::<_, Visitor>(query)
+            .bind(req.first_name)
+            .bind(req.last_name)
+            .bind(req.company)
+            .bind(req.kind.unwrap_or_else(|| "visitor".to_string()))
+            .bind(req.id_host)
+            .bind(req.valid_from)
+            .bind(req.valid_until)
+            .bind(created_by)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn get_visitors(&self) -> Result<Vec<Visitor>, sqlx::Error> {
+        let query = "SELECT id_visitor, first_name, last_name, company, kind, id_host, valid_from, valid_until,
+            photo_path, COALESCE(length(face_embedded), 0) > 0 AS face_enrolled, created_by, created_at
+            FROM visitors ORDER BY valid_from DESC";
+        sqlx::query_as::<_, Visitor>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_visitor(&self, id: i32) -> Result<Option<Visitor>, sqlx::Error> {
+        let query = "SELECT id_visitor, first_name, last_name, company, kind, id_host, valid_from, valid_until,
+            photo_path, COALESCE(length(face_embedded), 0) > 0 AS face_enrolled, created_by, created_at
+            FROM visitors WHERE id_visitor = $1";
+        sqlx::query_as::<_, Visitor>(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn update_visitor_photo(
+        &self,
+        id: i32,
+        embedding: Vec<u8>,
+        photo_path: String,
+    ) -> Result<u64, sqlx::Error> {
+        let query = "UPDATE visitors SET face_embedded = $1, photo_path = $2 WHERE id_visitor = $3";
+        let result = sqlx::query(query)
+            .bind(embedding)
+            .bind(photo_path)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn get_visitor_embedding(&self, id: i32) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        let query = "SELECT face_embedded FROM visitors WHERE id_visitor = $1";
+        let row = sqlx::query(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.and_then(|r| r.get("face_embedded")))
+    }
+
+    async fn create_visitor_pass(
+        &self,
+        id_visitor: i32,
+        max_uses: Option<i32>,
+        issued_at: chrono::DateTime<chrono::Utc>,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<VisitorPass, sqlx::Error> {
+        let query = "INSERT INTO visitor_passes (id_visitor, max_uses, issued_at, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id_pass, id_visitor, max_uses, uses, issued_at, expires_at, revoked_by, revoked_at";
+        sqlx::query_as::<_, VisitorPass>(query)
+            .bind(id_visitor)
+            .bind(max_uses)
+            .bind(issued_at)
+            .bind(expires_at)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn get_visitor_pass(&self, id: i32) -> Result<Option<VisitorPass>, sqlx::Error> {
+        let query = "SELECT id_pass, id_visitor, max_uses, uses, issued_at, expires_at, revoked_by, revoked_at
+            FROM visitor_passes WHERE id_pass = $1";
+        sqlx::query_as::<_, VisitorPass>(query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_visitor_passes(&self, id_visitor: i32) -> Result<Vec<VisitorPass>, sqlx::Error> {
+        let query = "SELECT id_pass, id_visitor, max_uses, uses, issued_at, expires_at, revoked_by, revoked_at
+            FROM visitor_passes WHERE id_visitor = $1 ORDER BY issued_at DESC";
+        sqlx::query_as::<_, VisitorPass>(query)
+            .bind(id_visitor)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn use_visitor_pass(&self, id: i32) -> Result<bool, sqlx::Error> {
+        // one statement, two stations scanning the same single-use pass can't both get in
+        let query = "UPDATE visitor_passes SET uses = uses + 1
+            WHERE id_pass = $1 AND revoked_at IS NULL AND (max_uses IS NULL OR uses < max_uses)";
+        let result = sqlx::query(query).bind(id).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn revoke_visitor_pass(
+        &self,
+        id: i32,
+        revoked_by: i32,
+        revoked_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let query = "UPDATE visitor_passes SET revoked_by = $2, revoked_at = $3
+            WHERE id_pass = $1 AND revoked_at IS NULL";
+        let result = sqlx::query(query)
+            .bind(id)
+            .bind(revoked_by)
+            .bind(revoked_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn add_visitor_log(
+        &self,
+        event_id: Option<Uuid>,
+        id_pass: i32,
+        direction: String,
+        station: Option<String>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<(VisitorLog, bool)>, sqlx::Error> {
+        let query = "INSERT INTO visitor_logs (id_visitor, id_pass, direction, timestamp, event_id, station, id_door)
+            SELECT id_visitor, id_pass, $2, $3, $4, $5, (SELECT id_door FROM stations WHERE name = $5)
+            FROM visitor_passes WHERE id_pass = $1
+            ON CONFLICT (event_id) DO NOTHING
+            RETURNING id_log, id_visitor, id_pass, direction, timestamp, event_id, station, id_door";
+        let inserted = sqlx::query_as::<_, VisitorLog>(query)
+            .bind(id_pass)
+            .bind(direction)
+            .bind(timestamp)
+            .bind(event_id)
+            .bind(station)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some(log) = inserted {
+            return Ok(Some((log, true)));
+        }
+
+        // either an unknown pass or a replayed event_id
+        let query =
+            "SELECT id_log, id_visitor, id_pass, direction, timestamp, event_id, station, id_door
+            FROM visitor_logs WHERE event_id = $1";
+        let original = sqlx::query_as::<_, VisitorLog>(query)
+            .bind(event_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(original.map(|log| (log, false)))
+    }
+
+    async fn get_visitor_logs(&self) -> Result<Vec<VisitorLog>, sqlx::Error> {
+        let query =
+            "SELECT id_log, id_visitor, id_pass, direction, timestamp, event_id, station, id_door
+            FROM visitor_logs ORDER BY timestamp DESC";
+        sqlx::query_as::<_, VisitorLog>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn set_site_mode(
+        &self,
+        req: SetModeRequest,
+        changed_by: i32,
+    ) -> Result<SiteModeChange, sqlx::Error> {
+        let query =
+            "INSERT INTO site_modes (id_site, mode, reason, changed_by) VALUES ($1, $2, $3, $4)
+            RETURNING id_change, id_site, mode, reason, changed_by, changed_at";
+        sqlx::query_as::<_, SiteModeChange>(query)
+            .bind(req.id_site)
+            .bind(req.mode)
+            .bind(req.reason)
+            .bind(changed_by)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn get_site_modes(&self) -> Result<Vec<SiteModeChange>, sqlx::Error> {
+        let query =
+            "SELECT DISTINCT ON (id_site) id_change, id_site, mode, reason, changed_by, changed_at
+            FROM site_modes ORDER BY id_site NULLS FIRST, changed_at DESC, id_change DESC";
+        sqlx::query_as::<_, SiteModeChange>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_site_mode_history(&self) -> Result<Vec<SiteModeChange>, sqlx::Error> {
+        let query = "SELECT id_change, id_site, mode, reason, changed_by, changed_at
+            FROM site_modes ORDER BY changed_at DESC, id_change DESC";
+        sqlx::query_as::<_, SiteModeChange>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn station_mode(
+        &self,
+        station: Option<String>,
+    ) -> Result<Option<SiteModeChange>, sqlx::Error> {
+        // the latest change for all sites or for the site the station's door is on. A
+        // station without a door (or an unknown one) can't tell which site it guards, it
+        // takes a lockdown in force on any site, otherwise the latest change for all sites
+        let query = "WITH station_site AS (
+                SELECT zones.id_site FROM stations
+                JOIN doors ON doors.id_door = stations.id_door
+                JOIN zones ON zones.id_zone = doors.id_zone
+                WHERE stations.name = $1
+            ), in_force AS (
+                SELECT DISTINCT ON (sites.id_site) m.id_change, m.id_site, m.mode, m.reason,
+                    m.changed_by, m.changed_at
+                FROM sites
+                JOIN site_modes m ON m.id_site IS NULL OR m.id_site = sites.id_site
+                WHERE NOT EXISTS (SELECT 1 FROM station_site)
+                    OR sites.id_site = (SELECT id_site FROM station_site)
+                ORDER BY sites.id_site, m.changed_at DESC, m.id_change DESC
+            )
+            SELECT id_change, id_site, mode, reason, changed_by, changed_at FROM (
+                SELECT * FROM in_force
+                WHERE EXISTS (SELECT 1 FROM station_site) OR mode = 'lockdown'
+                UNION ALL
+                SELECT id_change, id_site, mode, reason, changed_by, changed_at FROM site_modes
+                WHERE id_site IS NULL AND NOT EXISTS (SELECT 1 FROM station_site)
+            ) changes
+            ORDER BY mode = 'lockdown' DESC, changed_at DESC, id_change DESC LIMIT 1";
+        sqlx::query_as::<_, SiteModeChange>(query)
+            .bind(station)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_presence(&self) -> Result<Vec<PresenceEntry>, sqlx::Error> {
+        // the latest pass of everyone through the doors of each zone, stations without a
+        // door counting as a zone of their own. Leaving one zone doesn't take anyone out
+        // of the others; an OUT through a station without a door leaves the site. People
+        // inside several zones are listed in the one they entered last.
+        let query = "WITH staff_logs AS (
+                SELECT l.id_employee AS id, l.id_log, l.direction, l.timestamp, l.station,
+                    d.id_zone
+                FROM access_logs l
+                LEFT JOIN doors d ON d.id_door = l.id_door
+            ), guest_logs AS (
+                SELECT l.id_visitor AS id, l.id_log, l.direction, l.timestamp, l.station,
+                    d.id_zone
+                FROM visitor_logs l
+                LEFT JOIN doors d ON d.id_door = l.id_door
+            ), staff AS (
+                SELECT DISTINCT ON (z.id) z.* FROM (
+                    SELECT DISTINCT ON (id, id_zone) * FROM staff_logs
+                    ORDER BY id, id_zone, timestamp DESC, id_log DESC
+                ) z
+                WHERE z.direction = 'IN' AND NOT EXISTS (
+                    SELECT 1 FROM staff_logs o
+                    WHERE o.id = z.id AND o.id_zone IS NULL AND o.direction = 'OUT'
+                        AND o.timestamp > z.timestamp)
+                ORDER BY z.id, z.timestamp DESC, z.id_log DESC
+            ), guests AS (
+                SELECT DISTINCT ON (z.id) z.* FROM (
+                    SELECT DISTINCT ON (id, id_zone) * FROM guest_logs
+                    ORDER BY id, id_zone, timestamp DESC, id_log DESC
+                ) z
+                WHERE z.direction = 'IN' AND NOT EXISTS (
+                    SELECT 1 FROM guest_logs o
+                    WHERE o.id = z.id AND o.id_zone IS NULL AND o.direction = 'OUT'
+                        AND o.timestamp > z.timestamp)
+                ORDER BY z.id, z.timestamp DESC, z.id_log DESC
+            )
+            SELECT 'employee' AS kind, s.id, e.first_name, e.last_name, e.role,
+                NULL::int AS id_host, e.photo_path, s.timestamp AS entered_at, s.station,
+                s.id_zone, zn.name AS zone
+            FROM staff s
+            JOIN employees e ON e.id_person = s.id
+            LEFT JOIN zones zn ON zn.id_zone = s.id_zone
+            UNION ALL
+            SELECT v.kind, g.id, v.first_name, v.last_name, NULL::varchar AS role, v.id_host,
+                v.photo_path, g.timestamp AS entered_at, g.station, g.id_zone, zn.name AS zone
+            FROM guests g
+            JOIN visitors v ON v.id_visitor = g.id
+            LEFT JOIN zones zn ON zn.id_zone = g.id_zone";
+        sqlx::query_as::<_, PresenceEntry>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn set_zone_anti_passback(
+        &self,
+        id_zone: i32,
+        mode: String,
+    ) -> Result<Option<Zone>, sqlx::Error> {
+        let query = "UPDATE zones SET anti_passback = $2 WHERE id_zone = $1
+            RETURNING id_zone, id_site, name, anti_passback, escort_role";
+        sqlx::query_as::<_, Zone>(query)
+            .bind(id_zone)
+            .bind(mode)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn passback_state(
+        &self,
+        employee_id: i32,
+        station: String,
+    ) -> Result<Option<PassbackState>, sqlx::Error> {
+        // passes through any door of the zone count, the station's own door or not
+        let query = "SELECT z.id_zone, z.anti_passback, (
+                SELECT l.direction FROM access_logs l
+                JOIN doors ld ON ld.id_door = l.id_door
+                WHERE l.id_employee = $1 AND ld.id_zone = z.id_zone
+                    AND l.timestamp > COALESCE((
+                        SELECT MAX(r.reset_at) FROM passback_resets r
+                        WHERE r.id_employee = $1 AND (r.id_zone IS NULL OR r.id_zone = z.id_zone)
+                    ), '-infinity')
+                ORDER BY l.timestamp DESC, l.id_log DESC LIMIT 1
+            ) AS last_direction
+            FROM stations s
+            JOIN doors d ON d.id_door = s.id_door
+            JOIN zones z ON z.id_zone = d.id_zone
+            WHERE s.name = $2";
+        sqlx::query_as::<_, PassbackState>(query)
+            .bind(employee_id)
+            .bind(station)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn reset_passback(
+        &self,
+        employee_id: i32,
+        id_zone: Option<i32>,
+        reset_by: i32,
+    ) -> Result<PassbackReset, sqlx::Error> {
+        let query =
+            "INSERT INTO passback_resets (id_employee, id_zone, reset_by) VALUES ($1, $2, $3)
+            RETURNING id_reset, id_employee, id_zone, reset_by, reset_at";
+        sqlx::query_as::<_, PassbackReset>(query)
+            .bind(employee_id)
+            .bind(id_zone)
+            .bind(reset_by)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn add_passback_violation(&self, id_log: i32, id_zone: i32) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO passback_violations (id_log, id_zone) VALUES ($1, $2)
+            ON CONFLICT (id_log) DO NOTHING";
+        sqlx::query(query)
+            .bind(id_log)
+            .bind(id_zone)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_passback_violations(&self) -> Result<Vec<PassbackViolation>, sqlx::Error> {
+        let query = "SELECT v.id_violation, v.id_log, l.id_employee, l.direction, l.station,
+                l.timestamp, v.id_zone, z.name AS zone
+            FROM passback_violations v
+            JOIN access_logs l ON l.id_log = v.id_log
+            JOIN zones z ON z.id_zone = v.id_zone
+            ORDER BY l.timestamp DESC";
+        sqlx::query_as::<_, PassbackViolation>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn set_zone_escort(
+        &self,
+        id_zone: i32,
+        escort_role: Option<String>,
+    ) -> Result<Option<Zone>, sqlx::Error> {
+        let query = "UPDATE zones SET escort_role = $2 WHERE id_zone = $1
+            RETURNING id_zone, id_site, name, anti_passback, escort_role";
+        sqlx::query_as::<_, Zone>(query)
+            .bind(id_zone)
+            .bind(escort_role)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn station_zone(&self, station: String) -> Result<Option<Zone>, sqlx::Error> {
+        let query = "SELECT z.id_zone, z.id_site, z.name, z.anti_passback, z.escort_role
+            FROM stations s
+            JOIN doors d ON d.id_door = s.id_door
+            JOIN zones z ON z.id_zone = d.id_zone
+            WHERE s.name = $1";
+        sqlx::query_as::<_, Zone>(query)
+            .bind(station)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn request_escort(
+        &self,
+        station: String,
+        id_zone: i32,
+        id_employee: Option<i32>,
+        id_pass: Option<i32>,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<EscortAuthorization, sqlx::Error> {
+        let query =
+            "INSERT INTO escort_authorizations (station, id_zone, id_employee, id_pass, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id_authorization, station, id_zone, id_employee, id_pass, requested_at,
+                expires_at, confirmed_by, confirmed_at, completed_at";
+        sqlx::query_as::<_, EscortAuthorization>(query)
+            .bind(station)
+            .bind(id_zone)
+            .bind(id_employee)
+            .bind(id_pass)
+            .bind(expires_at)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn confirm_escort(
+        &self,
+        station: String,
+        escort_id: i32,
+    ) -> Result<Option<EscortAuthorization>, sqlx::Error> {
+        // the longest waiting entry, two escorts verifying at once confirm one each. The
+        // held person then gets a fresh window to pass the door
+        let query = "UPDATE escort_authorizations SET confirmed_by = $2, confirmed_at = now(),
+                expires_at = now() + (expires_at - requested_at)
+            WHERE id_authorization = (
+                SELECT id_authorization FROM escort_authorizations
+                WHERE station = $1 AND confirmed_at IS NULL AND expires_at > now()
+                    AND id_employee IS DISTINCT FROM $2
+                ORDER BY requested_at, id_authorization
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id_authorization, station, id_zone, id_employee, id_pass, requested_at,
+                expires_at, confirmed_by, confirmed_at, completed_at";
+        sqlx::query_as::<_, EscortAuthorization>(query)
+            .bind(station)
+            .bind(escort_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn complete_escort(
+        &self,
+        id_authorization: i32,
+        station: String,
+        id_employee: Option<i32>,
+        id_pass: Option<i32>,
+        event_id: Option<Uuid>,
+    ) -> Result<Option<EscortAuthorization>, sqlx::Error> {
+        // used once, at the station it was held at and before it expires. Only a retry of
+        // the ack that completed it, by its event id, gets through again
+        let query = "UPDATE escort_authorizations SET completed_at = COALESCE(completed_at, now()),
+                completed_event_id = COALESCE(completed_event_id, $5)
+            WHERE id_authorization = $1 AND station = $2 AND confirmed_at IS NOT NULL
+                AND id_employee IS NOT DISTINCT FROM $3 AND id_pass IS NOT DISTINCT FROM $4
+                AND ((completed_at IS NULL AND expires_at > now()) OR completed_event_id = $5)
+            RETURNING id_authorization, station, id_zone, id_employee, id_pass, requested_at,
+                expires_at, confirmed_by, confirmed_at, completed_at";
+        sqlx::query_as::<_, EscortAuthorization>(query)
+            .bind(id_authorization)
+            .bind(station)
+            .bind(id_employee)
+            .bind(id_pass)
+            .bind(event_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_escort_authorizations(&self) -> Result<Vec<EscortAuthorization>, sqlx::Error> {
+        let query = "SELECT id_authorization, station, id_zone, id_employee, id_pass, requested_at,
+                expires_at, confirmed_by, confirmed_at, completed_at
+            FROM escort_authorizations ORDER BY requested_at DESC";
+        sqlx::query_as::<_, EscortAuthorization>(query)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn add_audit_entry(&self, entry: NewAuditEntry) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO admin_audit (actor, action, target_type, target_id, before, after,
+                ip, user_agent, method, path)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+        sqlx::query(query)
+            .bind(entry.actor)
+            .bind(entry.action)
+            .bind(entry.target_type)
+            .bind(entry.target_id)
+            .bind(entry.before)
+            .bind(entry.after)
+            .bind(entry.ip)
+            .bind(entry.user_agent)
+            .bind(entry.method)
+            .bind(entry.path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_audit_entries(
+        &self,
+        filter: AuditQuery,
+        page: i64,
+        page_size: i64,
+    ) -> Result<(Vec<AuditEntry>, i64), sqlx::Error> {
+        fn push_filters(qb: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>, filter: &AuditQuery) {
+            qb.push(" WHERE TRUE");
+            if let Some(actor) = filter.actor {
+                qb.push(" AND actor = ").push_bind(actor);
+            }
+            if let Some(action) = &filter.action {
+                qb.push(" AND action = ").push_bind(action.clone());
+            }
+            if let Some(target_type) = &filter.target_type {
+                qb.push(" AND target_type = ")
+                    .push_bind(target_type.clone());
+            }
+            if let Some(target_id) = filter.target_id {
+                qb.push(" AND target_id = ").push_bind(target_id);
+            }
+            if let Some(from) = filter.from {
+                qb.push(" AND changed_at >= ").push_bind(from);
+            }
+            if let Some(to) = filter.to {
+                qb.push(" AND changed_at < ").push_bind(to);
+            }
+        }
+
+        let mut count_query = sqlx::QueryBuilder::new("SELECT COUNT(*) AS total FROM admin_audit");
+        push_filters(&mut count_query, &filter);
+        let total: i64 = count_query
+            .build()
+            .fetch_one(&self.pool)
+            .await?
+            .get("total");
+
+        let mut query = sqlx::QueryBuilder::new(
+            "SELECT id_audit, actor, action, target_type, target_id, before, after, ip, user_agent, method, path, changed_at FROM admin_audit",
+        );
+        push_filters(&mut query, &filter);
+        query.push(" ORDER BY changed_at DESC, id_audit DESC");
+        query.push(" LIMIT ").push_bind(page_size);
+        query.push(" OFFSET ").push_bind((page - 1) * page_size);
+
+        let items = query
+            .build_query_as::<AuditEntry>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok((items, total))
+    }
+
+    async fn get_subject_records(
+        &self,
+        id: i32,
+    ) -> Result<Vec<(String, serde_json::Value)>, sqlx::Error> {
+        // everything kept about one person, secrets (password hash, TOTP secret) left out
+        let queries = [
+            ("employee", "SELECT id_person, first_name, last_name, role, date_of_termination, account_number, login,
+                photo_path, encode(face_embedded, 'base64') AS face_embedded, archived_at, archived_by, erased_at, erased_by
+                FROM employees WHERE id_person = $1"),
+            ("hours", "SELECT * FROM hours WHERE id_employee = $1 ORDER BY time_start"),
+            ("breaks", "SELECT b.* FROM breaks b JOIN hours h ON h.id_record = b.id_record
+                WHERE h.id_employee = $1 ORDER BY b.break_start"),
+            ("hours_audit", "SELECT * FROM hours_audit WHERE id_employee = $1 ORDER BY id_audit"),
+            ("access_logs", "SELECT * FROM access_logs WHERE id_employee = $1 ORDER BY timestamp"),
+            ("failed_attempts", "SELECT * FROM failed_attempts WHERE id_employee = $1 ORDER BY timestamp"),
+            ("lockouts", "SELECT * FROM lockouts WHERE subject_type = 'employee' AND subject = $1::text
+                ORDER BY locked_at"),
+            ("badges", "SELECT * FROM badges WHERE id_employee = $1 ORDER BY issued_at"),
+            ("totp", "SELECT enrolled_at, last_step FROM employee_totp WHERE id_employee = $1"),
+            ("zone_permissions", "SELECT * FROM zone_permissions WHERE id_employee = $1 ORDER BY id_permission"),
+            ("access_schedules", "SELECT * FROM access_schedules WHERE id_employee = $1 ORDER BY id_schedule"),
+            ("passback_resets", "SELECT * FROM passback_resets WHERE id_employee = $1 ORDER BY reset_at"),
+            ("passback_violations", "SELECT v.* FROM passback_violations v JOIN access_logs l ON l.id_log = v.id_log
+                WHERE l.id_employee = $1 ORDER BY v.id_violation"),
+            ("escort_authorizations", "SELECT * FROM escort_authorizations
+                WHERE id_employee = $1 OR confirmed_by = $1 ORDER BY requested_at"),
+            ("admin_audit", "SELECT * FROM admin_audit WHERE target_type = 'employee' AND target_id = $1
+                ORDER BY id_audit"),
+        ];
+
+        let mut records = Vec::with_capacity(queries.len());
+        for (name, query) in queries {
+            let query = format!(
+                "SELECT COALESCE(json_agg(t), '[]'::json) AS rows FROM ({}) t",
+                query
+            );
+            let row = sqlx::query(&query).bind(id).fetch_one(&self.pool).await?;
+            records.push((name.to_string(), row.get("rows")));
+        }
+        Ok(records)
+    }
+
+    async fn erase_employee(
+        &self,
+        id: i32,
+        erased_by: i32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<ErasedFiles>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // only archived people, the hours and the name on them stay for payroll
+        let query = "WITH old AS (
+                SELECT id_person, photo_path FROM employees
+                WHERE id_person = $1 AND archived_at IS NOT NULL FOR UPDATE
+            )
+            UPDATE employees e SET face_embedded = NULL, photo_path = NULL, login = NULL,
+                password_hash = NULL, account_number = NULL, erased_at = $2, erased_by = $3
+            FROM old WHERE e.id_person = old.id_person
+            RETURNING old.photo_path";
+        let row = sqlx::query(query)
+            .bind(id)
+            .bind(now)
+            .bind(erased_by)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let photo_path: Option<String> = row.get("photo_path");
+
+        // the attempts stay as security records, just no longer about anyone
+        let query = "WITH old AS (
+                SELECT id_attempt, image_path FROM failed_attempts WHERE id_employee = $1 FOR UPDATE
+            )
+            UPDATE failed_attempts f SET id_employee = NULL, image_path = NULL, similarity = NULL
+            FROM old WHERE f.id_attempt = old.id_attempt
+            RETURNING old.image_path";
+        let rows = sqlx::query(query).bind(id).fetch_all(&mut *tx).await?;
+        let frames = rows
+            .iter()
+            .filter_map(|r| r.get::<Option<String>, _>("image_path"))
+            .collect();
+
+        sqlx::query("DELETE FROM employee_totp WHERE id_employee = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        // the audit log is append-only, this setting is the one way past it and only
+        // lets the personal fields in the snapshots be blanked, for this transaction
+        sqlx::query("SET LOCAL admin_audit.erasure = 'on'")
+            .execute(&mut *tx)
+            .await?;
+        let query = "UPDATE admin_audit SET before = audit_erase_fields(before),
+                after = audit_erase_fields(after)
+            WHERE target_type = 'employee' AND target_id = $1";
+        sqlx::query(query).bind(id).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(Some(ErasedFiles { photo_path, frames }))
+    }
+
+    async fn get_login_credentials(
+        &self,
+        login: String,
+    ) -> Result<Option<(i32, Option<String>)>, sqlx::Error> {
+        let query = "SELECT id_person, password_hash FROM employees
+            WHERE login = $1 AND archived_at IS NULL";
+        let row = sqlx::query(query)
+            .bind(login)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| (r.get("id_person"), r.get("password_hash"))))
+    }
+
+    async fn set_password_hash(&self, id: i32, password_hash: String) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE employees SET password_hash = $2 WHERE id_person = $1")
+            .bind(id)
+            .bind(password_hash)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn create_session(
+        &self,
+        id_employee: i32,
+        token_hash: Vec<u8>,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // expired sessions are only cleaned up here, nobody reads them
+        sqlx::query("DELETE FROM sessions WHERE expires_at <= CURRENT_TIMESTAMP")
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO sessions (id_employee, token_hash, expires_at) VALUES ($1, $2, $3)",
+        )
+        .bind(id_employee)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_session_employee(
+        &self,
+        token_hash: Vec<u8>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<i32>, sqlx::Error> {
+        let query = "SELECT s.id_employee FROM sessions s
+            JOIN employees e ON e.id_person = s.id_employee
+            WHERE s.token_hash = $1 AND s.expires_at > $2 AND e.archived_at IS NULL";
+        let row = sqlx::query(query)
+            .bind(token_hash)
+            .bind(now)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("id_employee")))
+    }
+
+    async fn delete_session(&self, token_hash: Vec<u8>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM sessions WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
         Ok(result.rows_affected())
     }
 }
@@ -0,0 +1,124 @@
+use crate::routes::AppState;
+use chrono::{Duration, Utc};
+
+/// Roles from the lowest clearance up.
+pub const ESCORT_ROLES: &[&str] = &["employee", "manager", "admin"];
+
+const DEFAULT_ESCORT_WINDOW_SECONDS: i64 = 60;
+
+/// How long a held entry waits for its escort, `ESCORT_WINDOW_SECONDS` (default 60).
+pub fn escort_window() -> Duration {
+    let seconds = std::env::var("ESCORT_WINDOW_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_ESCORT_WINDOW_SECONDS);
+    Duration::seconds(seconds)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subject {
+    Employee(i32),
+    /// a visitor, by their pass
+    Visitor(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escort {
+    /// no rule at the station, or one this person passes on their own
+    Clear,
+    /// held until an escort verifies at the same station
+    Required(i32),
+    /// the person is an escort and confirmed the longest waiting entry
+    Confirmed(i32),
+}
+
+/// Whether someone with `role` needs an escort into a zone that lets `escort_role` and
+/// above in alone. Visitors have no role and always do.
+pub fn needs_escort(role: Option<&str>, escort_role: &str) -> bool {
+    let clearance = |role: &str| ESCORT_ROLES.iter().position(|r| *r == role);
+    match (role.and_then(clearance), clearance(escort_role)) {
+        (_, None) => false,
+        (Some(have), Some(needed)) => have < needed,
+        (None, Some(_)) => true,
+    }
+}
+
+/// Two-person rule for an entry that already passed every other check. Only entering a
+/// zone with an escort role through its door is held; leaving never is.
+pub async fn check_escort(
+    data: &AppState,
+    subject: Subject,
+    station: Option<&str>,
+    direction: &str,
+) -> Result<Escort, sqlx::Error> {
+    let station = match station {
+        Some(station) if direction == "IN" => station,
+        _ => return Ok(Escort::Clear),
+    };
+    let (zone, escort_role) = match data.db.station_zone(station.to_string()).await? {
+        Some(zone) => match zone.escort_role {
+            Some(ref role) => (zone.id_zone, role.clone()),
+            None => return Ok(Escort::Clear),
+        },
+        None => return Ok(Escort::Clear),
+    };
+
+    let role = match subject {
+        Subject::Employee(id) => data.db.get_employee_role(id).await?,
+        Subject::Visitor(_) => None,
+    };
+    if needs_escort(role.as_deref(), &escort_role) {
+        let (employee, pass) = match subject {
+            Subject::Employee(id) => (Some(id), None),
+            Subject::Visitor(pass) => (None, Some(pass)),
+        };
+        let held = data
+            .db
+            .request_escort(
+                station.to_string(),
+                zone,
+                employee,
+                pass,
+                Utc::now() + escort_window(),
+            )
+            .await?;
+        return Ok(Escort::Required(held.id_authorization));
+    }
+
+    let Subject::Employee(escort) = subject else {
+        return Ok(Escort::Clear);
+    };
+    let confirmed = match data.db.confirm_escort(station.to_string(), escort).await? {
+        Some(confirmed) => confirmed,
+        None => return Ok(Escort::Clear),
+    };
+    // a held visitor's entry is only used up once it is confirmed
+    if let Some(pass) = confirmed.id_pass {
+        if !data.db.use_visitor_pass(pass).await? {
+            eprintln!(
+                "Pass {} was used up while waiting for an escort, not letting them in",
+                pass
+            );
+            return Ok(Escort::Clear);
+        }
+    }
+    Ok(Escort::Confirmed(confirmed.id_authorization))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escort_needed_below_the_zone_role() {
+        assert!(needs_escort(None, "employee"));
+        assert!(needs_escort(Some("employee"), "manager"));
+        assert!(!needs_escort(Some("manager"), "manager"));
+        assert!(!needs_escort(Some("admin"), "manager"));
+        assert!(needs_escort(Some("manager"), "admin"));
+        assert!(!needs_escort(Some("employee"), "employee"));
+        // an unknown rule holds nobody
+        assert!(!needs_escort(None, "janitor"));
+    }
+}
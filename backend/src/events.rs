@@ -0,0 +1,120 @@
+use crate::models::AccessEvent;
+use actix_web::web::Bytes;
+use chrono::Utc;
+use futures::Stream;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+const CHANNEL_CAPACITY: usize = 256;
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+impl AccessEvent {
+    pub fn new(kind: &str, employee_id: Option<i32>) -> Self {
+        Self {
+            kind: kind.to_string(),
+            employee_id,
+            employee_name: None,
+            station: None,
+            direction: None,
+            granted: None,
+            reason: None,
+            similarity: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn outcome(mut self, granted: bool, reason: &str, similarity: Option<f32>) -> Self {
+        self.granted = Some(granted);
+        self.reason = Some(reason.to_string());
+        self.similarity = similarity;
+        self
+    }
+}
+
+/// Fan-out of access decisions to connected dashboards. Nothing is buffered for
+/// clients that aren't connected, and slow clients just miss events.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<AccessEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn has_listeners(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    pub fn publish(&self, event: AccessEvent) {
+        // only fails when nobody is listening
+        let _ = self.sender.send(event);
+    }
+
+    /// Server-Sent Events body: one `event: <kind>` / `data: <json>` block per event,
+    /// plus a comment line every 15 s so proxies don't drop the idle connection.
+    pub fn sse_stream(&self) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        let receiver = self.sender.subscribe();
+        let keepalive = tokio::time::interval(KEEPALIVE);
+
+        futures::stream::unfold(
+            (receiver, keepalive),
+            |(mut receiver, mut keepalive)| async move {
+                loop {
+                    tokio::select! {
+                        msg = receiver.recv() => match msg {
+                            Ok(event) => {
+                                let data = serde_json::to_string(&event).unwrap_or_default();
+                                let chunk = format!("event: {}\ndata: {}\n\n", event.kind, data);
+                                return Some((Ok(Bytes::from(chunk)), (receiver, keepalive)));
+                            }
+                            Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => return None,
+                        },
+                        _ = keepalive.tick() => {
+                            return Some((Ok(Bytes::from_static(b": ping\n\n")), (receiver, keepalive)));
+                        }
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[actix_web::test]
+    async fn test_sse_stream_delivers_published_events() {
+        let bus = EventBus::new();
+        let mut stream = Box::pin(bus.sse_stream());
+
+        // first chunk is the immediate keepalive
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(&first[..], b": ping\n\n");
+        assert!(bus.has_listeners());
+
+        bus.publish(AccessEvent::new("verify_face", Some(7)).outcome(
+            false,
+            "face_mismatched",
+            Some(0.42),
+        ));
+
+        let chunk = stream.next().await.unwrap().unwrap();
+        let text = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(text.starts_with("event: verify_face\ndata: {"));
+        assert!(text.contains("\"reason\":\"face_mismatched\""));
+        assert!(text.ends_with("\n\n"));
+    }
+}
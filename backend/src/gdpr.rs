@@ -0,0 +1,155 @@
+use crate::retention::is_archived_attempt_photo;
+use serde_json::Value;
+use std::fs;
+use std::io::{Cursor, ErrorKind, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+
+/// Images referenced by the exported records, as (name in the archive, path on disk).
+/// Only the file name of a stored path is kept, so nothing can escape `images/`. Frame
+/// paths can come from `/api/log_error`, only those inside the archive are read.
+pub fn image_files(records: &[(String, Value)]) -> Vec<(String, String)> {
+    let mut files = Vec::new();
+    for (name, rows) in records {
+        let column = match name.as_str() {
+            "employee" => "photo_path",
+            "failed_attempts" => "image_path",
+            _ => continue,
+        };
+        let paths = rows
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|row| row[column].as_str())
+            .filter(|path| column != "image_path" || is_archived_attempt_photo(path));
+        for path in paths {
+            if let Some(file_name) = Path::new(path).file_name().and_then(|f| f.to_str()) {
+                files.push((format!("images/{}/{}", name, file_name), path.to_string()));
+            }
+        }
+    }
+    files
+}
+
+/// One JSON file per table plus the images, which are stored as they are
+/// since they are compressed already.
+pub fn export_zip(
+    records: &[(String, Value)],
+    images: &[(String, Vec<u8>)],
+) -> zip::result::ZipResult<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let json = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    for (name, rows) in records {
+        zip.start_file(format!("{}.json", name), json)?;
+        let bytes = serde_json::to_vec_pretty(rows).unwrap_or_default();
+        zip.write_all(&bytes)?;
+    }
+    for (name, bytes) in images {
+        zip.start_file(name.as_str(), stored)?;
+        zip.write_all(bytes)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Deletes erased images, a file that is already gone is fine. Returns the ones that
+/// are still on disk.
+pub fn remove_files(paths: &[String]) -> Vec<String> {
+    let mut left = Vec::new();
+    for path in paths {
+        match fs::remove_file(path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                eprintln!("Failed to delete {}: {}", path, e);
+                left.push(path.clone());
+            }
+        }
+    }
+    left
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Read;
+
+    #[test]
+    fn test_export_holds_records_and_images() {
+        let records = vec![
+            (
+                "employee".to_string(),
+                json!([{"id_person": 4, "photo_path": "uploads/employees/4.jpg"}]),
+            ),
+            ("hours".to_string(), json!([])),
+            (
+                "failed_attempts".to_string(),
+                json!([
+                    {"id_attempt": 6, "image_path": "uploads/failed_attempts/b.jpg"},
+                    {"id_attempt": 7, "image_path": "uploads/failed_attempts/../../etc/a.jpg"},
+                    {"id_attempt": 8, "image_path": null},
+                    {"id_attempt": 9, "image_path": "/etc/passwd"}
+                ]),
+            ),
+        ];
+        let files = image_files(&records);
+        assert_eq!(
+            files,
+            vec![
+                (
+                    "images/employee/4.jpg".to_string(),
+                    "uploads/employees/4.jpg".to_string()
+                ),
+                (
+                    "images/failed_attempts/b.jpg".to_string(),
+                    "uploads/failed_attempts/b.jpg".to_string()
+                ),
+            ]
+        );
+
+        let images = vec![("images/employee/4.jpg".to_string(), vec![1, 2, 3])];
+        let bytes = export_zip(&records, &images).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "employee.json",
+                "failed_attempts.json",
+                "hours.json",
+                "images/employee/4.jpg"
+            ]
+        );
+
+        let mut hours = String::new();
+        archive
+            .by_name("hours.json")
+            .unwrap()
+            .read_to_string(&mut hours)
+            .unwrap();
+        assert_eq!(hours, "[]");
+    }
+
+    #[test]
+    fn test_remove_files_reports_what_stayed() {
+        let dir = std::env::temp_dir().join(format!("erase-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        let file = dir.join("a.jpg");
+        fs::write(&file, b"frame").unwrap();
+
+        let file = file.to_string_lossy().to_string();
+        let gone = dir.join("gone.jpg").to_string_lossy().to_string();
+        // a directory can't be removed as a file
+        let stuck = dir.to_string_lossy().to_string();
+        let left = remove_files(&[file.clone(), gone, stuck.clone()]);
+
+        assert_eq!(left, vec![stuck]);
+        assert!(!Path::new(&file).exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+}
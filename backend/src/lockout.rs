@@ -0,0 +1,174 @@
+use crate::routes::AppState;
+use chrono::{Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Instant;
+
+pub const EMPLOYEE: &str = "employee";
+pub const STATION: &str = "station";
+
+/// Lockout thresholds, read once from the environment:
+/// `LOCKOUT_MAX_FAILURES` face mismatches of one employee (default 5) or
+/// `STATION_LOCKOUT_MAX_FAILURES` failures of any kind at one station (default 20)
+/// within `LOCKOUT_WINDOW_MINUTES` (default 10) lock it for `LOCKOUT_DURATION_MINUTES` (default 15).
+/// `RATE_LIMIT_PER_MINUTE` (default 30) caps requests per employee and per station.
+pub struct LockoutPolicy {
+    pub max_failures: i64,
+    pub station_max_failures: i64,
+    pub window: Duration,
+    pub duration: Duration,
+    pub rate_limit_per_minute: usize,
+}
+
+fn env_number(name: &str, default: i64) -> i64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+impl LockoutPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            max_failures: env_number("LOCKOUT_MAX_FAILURES", 5),
+            station_max_failures: env_number("STATION_LOCKOUT_MAX_FAILURES", 20),
+            window: Duration::minutes(env_number("LOCKOUT_WINDOW_MINUTES", 10)),
+            duration: Duration::minutes(env_number("LOCKOUT_DURATION_MINUTES", 15)),
+            rate_limit_per_minute: env_number("RATE_LIMIT_PER_MINUTE", 30) as usize,
+        }
+    }
+}
+
+/// Sliding one-minute window of request times per key, kept in memory only.
+/// A restart forgets it, which is fine for throttling.
+pub struct RateLimiter {
+    limit: usize,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a request for `key` and says whether it is still within the limit.
+    pub fn allow(&self, key: &str) -> bool {
+        self.allow_at(key, Instant::now())
+    }
+
+    fn allow_at(&self, key: &str, now: Instant) -> bool {
+        let window = std::time::Duration::from_secs(60);
+        let mut hits = self.hits.lock().unwrap();
+
+        // forget idle keys so the map doesn't grow with every id ever scanned
+        hits.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= window)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = hits.entry(key.to_string()).or_default();
+        if times.len() >= self.limit {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+/// Reason to turn the request away with, if the employee or station is over its rate
+/// limit (`rate_limited`) or currently locked out (`locked_out`). Every request counts
+/// against a station, one without a name against the empty one.
+pub async fn check_blocked(
+    data: &AppState,
+    employee_id: i32,
+    station: &str,
+) -> Result<Option<&'static str>, sqlx::Error> {
+    let employee_allowed = data.limiter.allow(&format!("{}:{}", EMPLOYEE, employee_id));
+    let station_allowed = data.limiter.allow(&format!("{}:{}", STATION, station));
+    if !employee_allowed || !station_allowed {
+        return Ok(Some("rate_limited"));
+    }
+
+    let now = Utc::now();
+    if data
+        .db
+        .active_lockout(EMPLOYEE.to_string(), employee_id.to_string(), now)
+        .await?
+        .is_some()
+    {
+        return Ok(Some("locked_out"));
+    }
+    if data
+        .db
+        .active_lockout(STATION.to_string(), station.to_string(), now)
+        .await?
+        .is_some()
+    {
+        return Ok(Some("locked_out"));
+    }
+    Ok(None)
+}
+
+/// Called after a failed attempt was recorded, locks the employee and/or station
+/// once they reach their threshold within the window.
+pub async fn register_failure(
+    data: &AppState,
+    employee_id: Option<i32>,
+    station: &str,
+) -> Result<(), sqlx::Error> {
+    let policy = &data.lockout;
+    let now = Utc::now();
+    let since = now - policy.window;
+
+    let mut subjects = Vec::new();
+    if let Some(employee_id) = employee_id {
+        subjects.push((EMPLOYEE, employee_id.to_string(), policy.max_failures));
+    }
+    subjects.push((STATION, station.to_string(), policy.station_max_failures));
+
+    for (subject_type, subject, max_failures) in subjects {
+        let failures = data
+            .db
+            .count_failures_since(subject_type.to_string(), subject.clone(), since)
+            .await?;
+        if failures >= max_failures {
+            println!(
+                "Locking out {} {} after {} failures",
+                subject_type, subject, failures
+            );
+            data.db
+                .add_lockout(subject_type.to_string(), subject, now + policy.duration)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_sliding_window() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+
+        assert!(limiter.allow_at("station:in", start));
+        assert!(limiter.allow_at("station:in", start));
+        assert!(!limiter.allow_at("station:in", start));
+        // other keys have their own budget
+        assert!(limiter.allow_at("employee:1", start));
+
+        let later = start + std::time::Duration::from_secs(61);
+        assert!(limiter.allow_at("station:in", later));
+    }
+}
@@ -1,34 +1,87 @@
-use crate::models::CreateErrorLogRequest;
-use chrono::Local;
-use csv::WriterBuilder;
+use crate::models::NewFailedAttempt;
+use crate::timezone::from_site_local;
+use chrono::NaiveDateTime;
+use csv::ReaderBuilder;
 use std::error::Error;
-use std::fs::OpenOptions;
+use std::fs::File;
+use std::io::Read;
 
-const LOG_FILE_PATH: &str = "error_logs.csv";
+/// Where failed attempts used to be appended before they moved to the `failed_attempts` table.
+pub const LEGACY_LOG_FILE_PATH: &str = "error_logs.csv";
 
-pub fn log_error(data: CreateErrorLogRequest) -> Result<(), Box<dyn Error>> {
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(LOG_FILE_PATH)?;
+/// Reads the old headerless `date;time;employee;reason;image` file for the one-time import.
+pub fn read_legacy_log(path: &str) -> Result<Vec<NewFailedAttempt>, Box<dyn Error>> {
+    parse_legacy_log(File::open(path)?)
+}
 
-    let mut wtr = WriterBuilder::new()
+/// Dates were written with the server's local clock, so they are read as site local time.
+/// Rows that can't be parsed are reported and skipped.
+fn parse_legacy_log<R: Read>(reader: R) -> Result<Vec<NewFailedAttempt>, Box<dyn Error>> {
+    let mut rdr = ReaderBuilder::new()
         .delimiter(b';')
         .has_headers(false)
-        .from_writer(file);
-
-    let now = Local::now();
-    let date = now.format("%Y-%m-%d").to_string();
-    let time = now.format("%H:%M:%S").to_string();
-
-    wtr.write_record(&[
-        date,
-        time,
-        data.employee,
-        data.error_description,
-        data.image.unwrap_or_default(),
-    ])?;
-
-    wtr.flush()?;
-    Ok(())
+        .flexible(true)
+        .from_reader(reader);
+
+    let mut attempts = Vec::new();
+    for record in rdr.records() {
+        let record = record?;
+
+        let timestamp = match (record.get(0), record.get(1)) {
+            (Some(date), Some(time)) => {
+                NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S")
+                    .ok()
+                    .and_then(from_site_local)
+            }
+            _ => None,
+        };
+        let (timestamp, reason) = match (timestamp, record.get(3)) {
+            (Some(ts), Some(reason)) if !reason.is_empty() => (ts, reason),
+            _ => {
+                eprintln!("Skipping malformed error log row: {:?}", record);
+                continue;
+            }
+        };
+
+        attempts.push(NewFailedAttempt {
+            id_employee: record.get(2).and_then(|e| e.trim().parse().ok()),
+            reason: reason.to_string(),
+            similarity: None,
+            station: None,
+            direction: None,
+            image_path: record.get(4).filter(|p| !p.is_empty()).map(str::to_string),
+            timestamp,
+        });
+    }
+
+    Ok(attempts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_parse_legacy_log() {
+        let csv = "2026-01-27;00:01:05;99999;employee_not_found;uploads/failed_attempts/a.jpg\n\
+                   garbage\n\
+                   2026-01-29;08:58:58;abc;face_mismatched;\n";
+
+        let attempts = parse_legacy_log(csv.as_bytes()).unwrap();
+
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].id_employee, Some(99999));
+        assert_eq!(attempts[0].reason, "employee_not_found");
+        assert_eq!(
+            attempts[0].image_path.as_deref(),
+            Some("uploads/failed_attempts/a.jpg")
+        );
+        assert_eq!(
+            attempts[0].timestamp,
+            Utc.with_ymd_and_hms(2026, 1, 26, 23, 1, 5).unwrap()
+        );
+        assert_eq!(attempts[1].id_employee, None);
+        assert_eq!(attempts[1].image_path, None);
+    }
 }
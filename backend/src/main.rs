@@ -1,12 +1,32 @@
+mod audit;
+mod auth;
+mod badge;
+mod badge_card;
 mod db;
+mod escort;
+mod events;
+mod gdpr;
 mod image_processor;
+mod lockout;
 mod logger;
 mod models;
+mod modes;
+mod passback;
+mod presence;
+mod retention;
 mod routes;
+mod schedule;
+mod stations;
+mod sync;
+mod timezone;
+mod totp;
+mod visitors;
+mod work_time;
+mod zones;
 
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer};
-use db::PostgresRepository;
+use db::{DatabaseRepository, PostgresRepository};
 use dotenvy::dotenv;
 use sqlx::postgres::PgPoolOptions;
 use std::env;
@@ -27,10 +47,58 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to create pool.");
 
+    let repo = PostgresRepository::new(pool);
+
+    // one-time move of the old CSV log into the database: `backend import-error-logs [path]`
+    if env::args().nth(1).as_deref() == Some("import-error-logs") {
+        let path = env::args()
+            .nth(2)
+            .unwrap_or_else(|| logger::LEGACY_LOG_FILE_PATH.to_string());
+        let attempts = logger::read_legacy_log(&path).expect("Failed to read error log.");
+        let total = attempts.len();
+        let imported = repo
+            .import_failed_attempts(attempts)
+            .await
+            .expect("Failed to import error log.");
+        println!(
+            "Imported {} of {} failed attempts from {}",
+            imported, total, path
+        );
+        return Ok(());
+    }
+
+    // first admin password, or a reset when nobody can log in: `backend set-password <login>`,
+    // the password is read from stdin so it doesn't end up in the shell history
+    if env::args().nth(1).as_deref() == Some("set-password") {
+        let login = env::args()
+            .nth(2)
+            .expect("Usage: backend set-password <login>");
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        let password = password.trim_end_matches(['\r', '\n']);
+        if password.is_empty() {
+            panic!("Empty password.");
+        }
+        let (id, _) = repo
+            .get_login_credentials(login.clone())
+            .await
+            .expect("Failed to read employee.")
+            .expect("No active employee with that login.");
+        repo.set_password_hash(id, auth::hash_password(password))
+            .await
+            .expect("Failed to set password.");
+        println!("Password set for {}", login);
+        return Ok(());
+    }
+
+    if badge::secret().is_none() {
+        eprintln!("BADGE_SECRET is not set, badges and visitor passes will be refused");
+    }
+
     println!("Server starting at http://0.0.0.0:8080");
 
-    let repo = PostgresRepository::new(pool);
-    let app_data = web::Data::new(routes::AppState { db: Box::new(repo) });
+    let app_data = web::Data::new(routes::AppState::new(Box::new(repo)));
+    retention::spawn_purge_job(app_data.clone());
 
     HttpServer::new(move || {
         let cors = Cors::permissive();
@@ -41,26 +109,193 @@ async fn main() -> std::io::Result<()> {
             .route("/health", web::get().to(routes::health_check))
             .service(
                 web::scope("/api")
+                    .route("/login", web::post().to(routes::login))
+                    .route("/logout", web::post().to(routes::logout))
                     .route("/log_error", web::post().to(routes::report_error))
                     .route("/employees", web::get().to(routes::get_employees))
                     .route("/employees", web::post().to(routes::create_employee))
                     .route("/employees/{id}", web::patch().to(routes::update_employee))
-                    .route("/employees/{id}", web::delete().to(routes::delete_employee))
+                    .route(
+                        "/employees/{id}",
+                        web::delete().to(routes::archive_employee),
+                    )
+                    .route(
+                        "/employees/{id}/restore",
+                        web::post().to(routes::restore_employee),
+                    )
+                    .route(
+                        "/employees/{id}/export",
+                        web::get().to(routes::export_employee),
+                    )
+                    .route(
+                        "/employees/{id}/erase",
+                        web::post().to(routes::erase_employee),
+                    )
                     .route(
                         "/employees/{id}/photo",
                         web::post().to(routes::upload_employee_photo),
                     )
+                    .route("/employees/{id}/badges", web::get().to(routes::get_badges))
+                    .route(
+                        "/employees/{id}/badges",
+                        web::post().to(routes::issue_badge),
+                    )
+                    .route(
+                        "/employees/{id}/badge",
+                        web::get().to(routes::get_badge_image),
+                    )
+                    .route("/employees/{id}/totp", web::post().to(routes::enroll_totp))
+                    .route(
+                        "/employees/{id}/totp",
+                        web::delete().to(routes::delete_totp),
+                    )
+                    .route(
+                        "/employees/{id}/passback/reset",
+                        web::post().to(routes::reset_passback),
+                    )
+                    .route("/badges/{id}/revoke", web::post().to(routes::revoke_badge))
                     .route("/hours", web::get().to(routes::get_work_hours))
                     .route("/hours/start", web::post().to(routes::start_shift))
                     .route("/hours/end", web::post().to(routes::end_shift))
+                    .route("/hours/break/start", web::post().to(routes::start_break))
+                    .route("/hours/break/end", web::post().to(routes::end_break))
+                    .route(
+                        "/hours/report",
+                        web::get().to(routes::get_work_hours_report),
+                    )
+                    .route("/hours/break_rules", web::get().to(routes::get_break_rules))
+                    .route("/hours/break_rules", web::put().to(routes::set_break_rules))
+                    .route("/hours", web::post().to(routes::create_work_hours))
+                    .route("/hours/{id}", web::patch().to(routes::update_work_hours))
+                    .route("/hours/{id}", web::delete().to(routes::delete_work_hours))
+                    .route(
+                        "/hours/{id}/history",
+                        web::get().to(routes::get_work_hours_history),
+                    )
                     .route("/employee/check_qr", web::post().to(routes::check_qr))
                     .route("/face/verify", web::post().to(routes::verify_face))
                     .route("/access/ack", web::post().to(routes::access_ack))
-                    .route("/access_logs", web::get().to(routes::get_access_logs)),
+                    .route("/stations", web::get().to(routes::get_stations))
+                    .route("/stations", web::post().to(routes::register_station))
+                    .route(
+                        "/stations/heartbeat",
+                        web::post().to(routes::station_heartbeat),
+                    )
+                    .route(
+                        "/stations/{id}/door",
+                        web::put().to(routes::set_station_door),
+                    )
+                    .route(
+                        "/stations/{id}/config",
+                        web::get().to(routes::get_station_config),
+                    )
+                    .route(
+                        "/stations/{id}/config",
+                        web::patch().to(routes::update_station_config),
+                    )
+                    .route(
+                        "/stations/{id}/credentials",
+                        web::post().to(routes::reissue_station_credentials),
+                    )
+                    .route(
+                        "/stations/sync",
+                        web::post().to(routes::sync_station_events),
+                    )
+                    .route("/access_logs", web::get().to(routes::get_access_logs))
+                    .route("/events", web::get().to(routes::event_stream))
+                    .route(
+                        "/security/failed_attempts",
+                        web::get().to(routes::get_failed_attempts),
+                    )
+                    .route(
+                        "/security/failed_attempts/{id}/image",
+                        web::get().to(routes::get_failed_attempt_image),
+                    )
+                    .route(
+                        "/security/retention",
+                        web::get().to(routes::get_retention_rules),
+                    )
+                    .route(
+                        "/security/retention",
+                        web::put().to(routes::set_retention_rules),
+                    )
+                    .route(
+                        "/security/retention/dry_run",
+                        web::get().to(routes::purge_dry_run),
+                    )
+                    .route("/sites", web::get().to(routes::get_sites))
+                    .route("/sites", web::post().to(routes::create_site))
+                    .route("/sites/{id}/zones", web::post().to(routes::create_zone))
+                    .route("/zones", web::get().to(routes::get_zones))
+                    .route("/zones/{id}/doors", web::post().to(routes::create_door))
+                    .route("/doors", web::get().to(routes::get_doors))
+                    .route(
+                        "/zones/{id}/permissions",
+                        web::get().to(routes::get_zone_permissions),
+                    )
+                    .route(
+                        "/zones/{id}/permissions",
+                        web::post().to(routes::grant_zone_permission),
+                    )
+                    .route(
+                        "/zones/{id}/permissions/{permission_id}",
+                        web::delete().to(routes::revoke_zone_permission),
+                    )
+                    .route(
+                        "/zones/{id}/anti_passback",
+                        web::put().to(routes::set_zone_anti_passback),
+                    )
+                    .route("/zones/{id}/escort", web::put().to(routes::set_zone_escort))
+                    .route("/schedules", web::get().to(routes::get_schedules))
+                    .route("/schedules", web::post().to(routes::create_schedule))
+                    .route("/schedules/holidays", web::get().to(routes::get_holidays))
+                    .route("/schedules/holidays", web::put().to(routes::set_holidays))
+                    .route("/schedules/{id}", web::delete().to(routes::delete_schedule))
+                    .route("/visitors", web::get().to(routes::get_visitors))
+                    .route("/visitors", web::post().to(routes::create_visitor))
+                    .route("/visitors/logs", web::get().to(routes::get_visitor_logs))
+                    .route(
+                        "/visitors/{id}/photo",
+                        web::post().to(routes::upload_visitor_photo),
+                    )
+                    .route(
+                        "/visitors/{id}/passes",
+                        web::get().to(routes::get_visitor_passes),
+                    )
+                    .route(
+                        "/visitors/{id}/passes",
+                        web::post().to(routes::issue_visitor_pass),
+                    )
+                    .route(
+                        "/visitor_passes/{id}/image",
+                        web::get().to(routes::get_visitor_pass_image),
+                    )
+                    .route(
+                        "/visitor_passes/{id}/revoke",
+                        web::post().to(routes::revoke_visitor_pass),
+                    )
+                    .route("/presence", web::get().to(routes::get_presence))
+                    .route("/presence/muster", web::get().to(routes::get_muster_list))
+                    .route("/security/mode", web::get().to(routes::get_site_modes))
+                    .route("/security/mode", web::put().to(routes::set_site_mode))
+                    .route(
+                        "/security/mode/history",
+                        web::get().to(routes::get_site_mode_history),
+                    )
+                    .route(
+                        "/security/escorts",
+                        web::get().to(routes::get_escort_authorizations),
+                    )
+                    .route(
+                        "/security/passback_violations",
+                        web::get().to(routes::get_passback_violations),
+                    )
+                    .route("/audit", web::get().to(routes::get_audit_log))
+                    .route("/security/lockouts", web::get().to(routes::get_lockouts))
+                    .route("/security/lockouts/unlock", web::post().to(routes::unlock)),
             )
     })
     .bind(("0.0.0.0", 8080))?
     .run()
     .await
 }
-
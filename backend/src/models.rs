@@ -1,6 +1,7 @@
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Employee {
@@ -12,23 +13,664 @@ pub struct Employee {
     pub photo_path: Option<String>,
     pub account_number: Option<String>,
     pub login: Option<String>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmployeesQuery {
+    /// archived employees are left out unless asked for
+    #[serde(default)]
+    pub include_archived: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct WorkHours {
     pub id_record: i32,
     pub id_employee: i32,
-    pub time_start: NaiveDateTime,
-    pub time_end: Option<NaiveDateTime>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub time_start: DateTime<Utc>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub time_end: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WorkBreak {
+    pub id_break: i32,
+    pub id_record: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub break_start: DateTime<Utc>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub break_end: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct BreakRule {
+    pub min_shift_minutes: i32,
+    pub required_break_minutes: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkHoursReport {
+    pub id_record: i32,
+    pub id_employee: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub time_start: DateTime<Utc>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub time_end: Option<DateTime<Utc>>,
+    pub break_minutes: i64,
+    pub required_break_minutes: i64,
+    pub worked_minutes: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ErrorLogEntry {
-    pub date: String,
-    pub time: String,
-    pub employee: String,
-    pub error_description: String,
+pub struct CreateWorkHoursRequest {
+    pub id_employee: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub time_start: DateTime<Utc>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub time_end: Option<DateTime<Utc>>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateWorkHoursRequest {
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub time_start: Option<DateTime<Utc>>,
+    /// left out keeps the end, `null` reopens the shift
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::timezone::site_time_nullable"
+    )]
+    pub time_end: Option<Option<DateTime<Utc>>>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteWorkHoursRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct WorkHoursAudit {
+    pub id_audit: i32,
+    pub id_record: i32,
+    pub id_employee: i32,
+    pub action: String,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub old_time_start: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub old_time_end: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub new_time_start: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub new_time_end: Option<DateTime<Utc>>,
+    pub reason: String,
+    pub changed_by: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct FailedAttempt {
+    pub id_attempt: i32,
+    pub id_employee: Option<i32>,
+    pub reason: String,
+    pub similarity: Option<f32>,
+    pub station: Option<String>,
+    pub direction: Option<String>,
+    pub image_path: Option<String>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewFailedAttempt {
+    pub id_employee: Option<i32>,
+    pub reason: String,
+    pub similarity: Option<f32>,
+    pub station: Option<String>,
+    pub direction: Option<String>,
     pub image_path: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailedAttemptQuery {
+    pub employee_id: Option<i32>,
+    pub reason: Option<String>,
+    pub station: Option<String>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub to: Option<DateTime<Utc>>,
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+    pub sort_by: Option<String>,
+    pub order: Option<String>,
+}
+
+/// Administrative change to record, see `audit::record`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAuditEntry {
+    pub actor: i32,
+    /// `create`, `update`, `delete` or `photo`
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<i32>,
+    /// changed fields only, None for a create
+    pub before: Option<serde_json::Value>,
+    /// changed fields only, None for a delete
+    pub after: Option<serde_json::Value>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub method: String,
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct AuditEntry {
+    pub id_audit: i32,
+    pub actor: i32,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<i32>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub method: String,
+    pub path: String,
+    #[serde(with = "crate::timezone::site_time")]
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<i32>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub to: Option<DateTime<Utc>>,
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditPage {
+    pub items: Vec<AuditEntry>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailedAttemptPage {
+    pub items: Vec<FailedAttempt>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct RetentionRule {
+    pub reason: String,
+    pub retention_days: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExpiredPhoto {
+    pub id_attempt: i32,
+    pub reason: String,
+    pub image_path: String,
+    #[serde(with = "crate::timezone::site_time")]
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeReport {
+    pub dry_run: bool,
+    pub total: usize,
+    pub by_reason: std::collections::BTreeMap<String, usize>,
+    pub items: Vec<ExpiredPhoto>,
+}
+
+/// Files an erasure left without a reference: the enrollment photo and the failed
+/// attempt frames.
+#[derive(Debug, Default)]
+pub struct ErasedFiles {
+    pub photo_path: Option<String>,
+    pub frames: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Lockout {
+    pub id_lockout: i32,
+    pub subject_type: String,
+    pub subject: String,
+    #[serde(with = "crate::timezone::site_time")]
+    pub locked_at: DateTime<Utc>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub locked_until: DateTime<Utc>,
+    pub unlocked_by: Option<i32>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub unlocked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnlockRequest {
+    pub employee_id: Option<i32>,
+    pub station: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Badge {
+    pub id_badge: i32,
+    pub id_employee: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub issued_at: DateTime<Utc>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub expires_at: DateTime<Utc>,
+    pub revoked_by: Option<i32>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssueBadgeRequest {
+    pub valid_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BadgeImageQuery {
+    /// `png` (default), `svg` or `pdf`
+    pub format: Option<String>,
+    pub badge_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssuedBadge {
+    #[serde(flatten)]
+    pub badge: Badge,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Visitor {
+    pub id_visitor: i32,
+    pub first_name: String,
+    pub last_name: String,
+    pub company: Option<String>,
+    pub kind: String,
+    pub id_host: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub valid_from: DateTime<Utc>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub valid_until: DateTime<Utc>,
+    pub photo_path: Option<String>,
+    pub face_enrolled: bool,
+    pub created_by: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateVisitorRequest {
+    pub first_name: String,
+    pub last_name: String,
+    pub company: Option<String>,
+    /// `visitor` (default) or `contractor`
+    pub kind: Option<String>,
+    pub id_host: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub valid_from: DateTime<Utc>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub valid_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct VisitorPass {
+    pub id_pass: i32,
+    pub id_visitor: i32,
+    /// None for unlimited entries
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub issued_at: DateTime<Utc>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub expires_at: DateTime<Utc>,
+    pub revoked_by: Option<i32>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssuePassRequest {
+    pub max_uses: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssuedPass {
+    #[serde(flatten)]
+    pub pass: VisitorPass,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PassImageQuery {
+    /// `png` (default) or `svg`
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct VisitorLog {
+    pub id_log: i32,
+    pub id_visitor: i32,
+    pub id_pass: i32,
+    pub direction: String,
+    #[serde(with = "crate::timezone::site_time")]
+    pub timestamp: DateTime<Utc>,
+    pub event_id: Option<Uuid>,
+    pub station: Option<String>,
+    pub id_door: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    pub employee_id: i32,
+    /// base32, for typing into an authenticator by hand
+    pub secret: String,
+    pub provisioning_uri: String,
+    pub period: i64,
+    pub digits: u32,
+    /// what the phone app has to show as QR, `{code}` being the current code
+    pub qr_format: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Station {
+    pub id_station: i32,
+    pub name: String,
+    pub location: Option<String>,
+    pub direction: String,
+    pub id_door: Option<i32>,
+    /// name of the door, for display
+    pub door: Option<String>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub registered_at: DateTime<Utc>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub version: Option<String>,
+    pub camera_status: Option<String>,
+    /// the station refetches its config when this changes
+    pub config_version: i32,
+    /// worked out from `last_seen_at` when listing, not stored
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub online: bool,
+    /// emergency mode in force at the station, sent back on heartbeats
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterStationRequest {
+    pub name: String,
+    pub location: Option<String>,
+    pub direction: String,
+    pub id_door: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StationDoorRequest {
+    /// null unbinds the station
+    pub id_door: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Site {
+    pub id_site: i32,
+    pub name: String,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateSiteRequest {
+    pub name: String,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Zone {
+    pub id_zone: i32,
+    pub id_site: i32,
+    pub name: String,
+    /// `off`, `soft` or `hard`
+    pub anti_passback: String,
+    /// least role that may enter without an escort, None when there's no such rule
+    pub escort_role: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetEscortRequest {
+    /// `admin`, `manager` or `employee`, null to drop the rule
+    pub escort_role: Option<String>,
+}
+
+/// An entry held at a station until someone allowed to escort verifies there as well.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct EscortAuthorization {
+    pub id_authorization: i32,
+    pub station: String,
+    pub id_zone: i32,
+    pub id_employee: Option<i32>,
+    pub id_pass: Option<i32>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub requested_at: DateTime<Utc>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub expires_at: DateTime<Utc>,
+    pub confirmed_by: Option<i32>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub confirmed_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::timezone::site_time_opt")]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetPassbackRequest {
+    pub mode: String,
+}
+
+/// Passback setting of the zone behind a station's door, with the direction of the
+/// employee's latest logged pass into or out of that zone since their last reset.
+#[derive(Debug, Clone, FromRow)]
+pub struct PassbackState {
+    pub id_zone: i32,
+    pub anti_passback: String,
+    pub last_direction: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PassbackResetQuery {
+    /// leave out to reset all zones
+    pub id_zone: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PassbackReset {
+    pub id_reset: i32,
+    pub id_employee: i32,
+    pub id_zone: Option<i32>,
+    pub reset_by: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub reset_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PassbackViolation {
+    pub id_violation: i32,
+    pub id_log: i32,
+    pub id_employee: i32,
+    pub direction: String,
+    pub station: Option<String>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub timestamp: DateTime<Utc>,
+    pub id_zone: i32,
+    pub zone: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Door {
+    pub id_door: i32,
+    pub id_zone: i32,
+    pub name: String,
+}
+
+/// Body for new zones and doors, the parent comes from the path.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NameRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ZonePermission {
+    pub id_permission: i32,
+    pub id_zone: i32,
+    pub id_employee: Option<i32>,
+    pub role: Option<String>,
+    pub granted_by: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub granted_at: DateTime<Utc>,
+}
+
+/// Exactly one of `employee_id` and `role`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GrantZoneRequest {
+    pub employee_id: Option<i32>,
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AccessSchedule {
+    pub id_schedule: i32,
+    pub id_employee: Option<i32>,
+    pub role: Option<String>,
+    pub id_zone: Option<i32>,
+    /// 1 = Monday ... 7 = Sunday
+    pub weekdays: Vec<i32>,
+    /// site local time
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_until: Option<NaiveDate>,
+    pub on_holidays: bool,
+    pub created_by: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Exactly one of `employee_id`, `role` and `id_zone`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateScheduleRequest {
+    pub employee_id: Option<i32>,
+    pub role: Option<String>,
+    pub id_zone: Option<i32>,
+    pub weekdays: Vec<i32>,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_until: Option<NaiveDate>,
+    #[serde(default)]
+    pub on_holidays: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Holiday {
+    pub day: NaiveDate,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SiteModeChange {
+    pub id_change: i32,
+    /// None when it applies to all sites
+    pub id_site: Option<i32>,
+    pub mode: String,
+    pub reason: Option<String>,
+    pub changed_by: i32,
+    #[serde(with = "crate::timezone::site_time")]
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetModeRequest {
+    /// `normal`, `lockdown` or `evacuation`
+    pub mode: String,
+    /// leave out to switch all sites
+    pub id_site: Option<i32>,
+    pub reason: Option<String>,
+}
+
+/// Returned on registration and when new credentials are issued, the token is not shown again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StationCredentials {
+    #[serde(flatten)]
+    pub station: Station,
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub login: String,
+    pub password: String,
+}
+
+/// The token is sent back as `Authorization: Bearer` until `expires_at`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub token: String,
+    pub id_person: i32,
+    pub role: String,
+    #[serde(with = "crate::timezone::site_time")]
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Settings a station fetches from `/stations/{id}/config`, `version` is also its ETag.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StationConfig {
+    pub id_station: i32,
+    pub version: i32,
+    pub direction: String,
+    pub fps: i32,
+    pub countdown_seconds: i32,
+    pub request_timeout_seconds: i32,
+    /// how long error messages stay on screen
+    pub message_seconds: i32,
+    /// cosine similarity a face has to exceed, checked by the backend
+    pub face_threshold: f32,
+}
+
+/// Fields left out keep their current value.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StationConfigUpdate {
+    pub direction: Option<String>,
+    pub fps: Option<i32>,
+    pub countdown_seconds: Option<i32>,
+    pub request_timeout_seconds: Option<i32>,
+    pub message_seconds: Option<i32>,
+    pub face_threshold: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeartbeatRequest {
+    pub version: Option<String>,
+    /// `ok`, or whatever is wrong with the camera
+    pub camera_status: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -59,8 +701,17 @@ pub struct UpdateEmployeeRequest {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CheckQrRequest {
+    /// Plain id from old badges, ignored when `code` is sent.
+    #[serde(default)]
     pub employee_id: i32,
+    /// Signed badge payload, see `badge::sign`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Both ignored, the backend takes them from the station the token belongs to.
+    #[serde(default)]
     pub direction: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub station: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -71,6 +722,28 @@ pub struct CheckQrResponse {
     pub first_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// with `outside_access_hours`, when the employee may enter next
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::timezone::site_time_opt"
+    )]
+    pub next_allowed_at: Option<DateTime<Utc>>,
+    /// set when a visitor pass was scanned, `employee_id` is 0 then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visitor: Option<VisitorEntry>,
+}
+
+/// What the station passes on to `verify_face` and the ack for a visitor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisitorEntry {
+    pub visitor_id: i32,
+    pub pass_id: i32,
+    pub kind: String,
+    /// whether `verify_face` will compare the face or only check the pass
+    pub face_enrolled: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,13 +751,38 @@ pub struct VerifyFaceResponse {
     pub access_granted: bool,
     pub reason: String,
     pub similarity: Option<f32>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::timezone::site_time_opt"
+    )]
+    pub next_allowed_at: Option<DateTime<Utc>>,
+    /// with `escort_required` the entry being held, with `escort_confirmed` the one
+    /// this escort just confirmed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization_id: Option<i32>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AccessAckRequest {
+    /// Idempotency key, the same for every retry of one decision.
+    #[serde(default)]
+    pub event_id: Option<Uuid>,
+    #[serde(default)]
     pub employee_id: i32,
+    /// instead of `employee_id` for visitors, logged to `visitor_logs`
+    #[serde(default)]
+    pub pass_id: Option<i32>,
+    /// the confirmed escort authorization of a held entry
+    #[serde(default)]
+    pub authorization_id: Option<i32>,
+    /// Both ignored, the backend takes them from the station the token belongs to.
+    #[serde(default)]
     pub direction: String,
-    pub timestamp: NaiveDateTime,
+    #[serde(default)]
+    pub station: Option<String>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -92,6 +790,51 @@ pub struct AccessAckResponse {
     pub status: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_log: Option<i32>,
+}
+
+/// One decision the station couldn't deliver while the backend was unreachable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncEvent {
+    pub event_id: Uuid,
+    /// `access_ack` or `failed_attempt`
+    pub kind: String,
+    pub employee_id: Option<i32>,
+    pub direction: String,
+    #[serde(with = "crate::timezone::site_time")]
+    pub timestamp: DateTime<Utc>,
+    /// failed attempts only
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub similarity: Option<f32>,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncRequest {
+    /// ignored, events are signed and stored for the station the token belongs to
+    #[serde(default)]
+    pub station: String,
+    pub events: Vec<SyncEvent>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncResult {
+    pub event_id: Uuid,
+    /// `accepted`, `duplicate`, `rejected` or `error` (worth retrying)
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// `id_log` or `id_attempt` of the stored row
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncResponse {
+    pub results: Vec<SyncResult>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -99,10 +842,69 @@ pub struct AccessLog {
     pub id_log: i32,
     pub id_employee: i32,
     pub direction: String,
-    pub timestamp: NaiveDateTime,
+    #[serde(with = "crate::timezone::site_time")]
+    pub timestamp: DateTime<Utc>,
+    pub event_id: Option<Uuid>,
+    pub station: Option<String>,
+    pub id_door: Option<i32>,
+}
+
+/// Someone whose latest pass through a station was IN.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PresenceEntry {
+    /// `employee`, `visitor` or `contractor`
+    pub kind: String,
+    /// employee or visitor id, depending on `kind`
+    pub id: i32,
+    pub first_name: String,
+    pub last_name: String,
+    pub role: Option<String>,
+    /// visitors only
+    pub id_host: Option<i32>,
+    pub photo_path: Option<String>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub entered_at: DateTime<Utc>,
+    pub station: Option<String>,
+    pub id_zone: Option<i32>,
+    pub zone: Option<String>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub inside_minutes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ZoneCount {
+    /// None for people who came in through a station without a door
+    pub id_zone: Option<i32>,
+    pub zone: Option<String>,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Presence {
+    #[serde(with = "crate::timezone::site_time")]
+    pub as_of: DateTime<Utc>,
+    pub total: usize,
+    pub zones: Vec<ZoneCount>,
+    pub people: Vec<PresenceEntry>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct EmployeeIdRequest {
     pub id_employee: i32,
 }
+
+/// Pushed to dashboards over `/api/events` as things happen at the stations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessEvent {
+    pub kind: String,
+    pub employee_id: Option<i32>,
+    pub employee_name: Option<String>,
+    pub station: Option<String>,
+    pub direction: Option<String>,
+    pub granted: Option<bool>,
+    pub reason: Option<String>,
+    pub similarity: Option<f32>,
+    #[serde(with = "crate::timezone::site_time")]
+    pub timestamp: DateTime<Utc>,
+}
@@ -0,0 +1,80 @@
+use crate::routes::AppState;
+
+pub const MODES: &[&str] = &["normal", "lockdown", "evacuation"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SiteMode {
+    Normal,
+    /// nobody but admins gets through, in either direction
+    Lockdown,
+    /// every exit opens without any checks, entering works as usual
+    Evacuation,
+}
+
+impl SiteMode {
+    pub fn parse(mode: &str) -> Self {
+        match mode {
+            "lockdown" => SiteMode::Lockdown,
+            "evacuation" => SiteMode::Evacuation,
+            _ => SiteMode::Normal,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SiteMode::Normal => "normal",
+            SiteMode::Lockdown => "lockdown",
+            SiteMode::Evacuation => "evacuation",
+        }
+    }
+
+    pub fn opens_exit(self, direction: &str) -> bool {
+        self == SiteMode::Evacuation && direction == "OUT"
+    }
+
+    pub fn denies(self, role: Option<&str>) -> bool {
+        self == SiteMode::Lockdown && role != Some("admin")
+    }
+}
+
+/// Mode in force at `station`: the latest change made for all sites or for the site
+/// of the station's door. Stations without a door follow the site-wide changes and
+/// a lockdown of any site.
+pub async fn current_mode(data: &AppState, station: Option<&str>) -> Result<SiteMode, sqlx::Error> {
+    let change = data.db.station_mode(station.map(str::to_string)).await?;
+    Ok(change.map_or(SiteMode::Normal, |c| SiteMode::parse(&c.mode)))
+}
+
+/// Whether a lockdown keeps the employee out. Only looks the role up during one.
+pub async fn lockdown_denies(
+    data: &AppState,
+    mode: SiteMode,
+    employee_id: i32,
+) -> Result<bool, sqlx::Error> {
+    if mode != SiteMode::Lockdown {
+        return Ok(false);
+    }
+    let role = data.db.get_employee_role(employee_id).await?;
+    Ok(mode.denies(role.as_deref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockdown_and_evacuation_rules() {
+        assert!(SiteMode::Lockdown.denies(Some("manager")));
+        assert!(SiteMode::Lockdown.denies(None));
+        assert!(!SiteMode::Lockdown.denies(Some("admin")));
+        assert!(!SiteMode::Evacuation.denies(None));
+
+        assert!(SiteMode::Evacuation.opens_exit("OUT"));
+        assert!(!SiteMode::Evacuation.opens_exit("IN"));
+        assert!(!SiteMode::Lockdown.opens_exit("OUT"));
+
+        for mode in MODES {
+            assert_eq!(SiteMode::parse(mode).as_str(), *mode);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::routes::AppState;
+
+pub const PASSBACK_MODES: &[&str] = &["off", "soft", "hard"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AntiPassback {
+    Off,
+    /// out of order passes go through, but are recorded
+    Soft,
+    /// out of order passes are denied
+    Hard,
+}
+
+impl AntiPassback {
+    pub fn parse(mode: &str) -> Self {
+        match mode {
+            "soft" => AntiPassback::Soft,
+            "hard" => AntiPassback::Hard,
+            _ => AntiPassback::Off,
+        }
+    }
+}
+
+/// Whether `direction` repeats the previous pass into or out of a zone: entering twice
+/// without leaving in between, the badge was handed back through the door, or the other
+/// way round. Without any pass logged yet there is nothing to repeat.
+pub fn out_of_order(last_direction: Option<&str>, direction: &str) -> bool {
+    last_direction == Some(direction)
+}
+
+/// Zone and its setting when passing `station` breaks the order of the employee's
+/// passes in the zone behind its door. Stations without a door and zones with
+/// anti-passback off never do.
+pub async fn check_passback(
+    data: &AppState,
+    employee_id: i32,
+    station: Option<&str>,
+    direction: &str,
+) -> Result<Option<(i32, AntiPassback)>, sqlx::Error> {
+    let station = match station {
+        Some(station) => station,
+        None => return Ok(None),
+    };
+    let state = match data
+        .db
+        .passback_state(employee_id, station.to_string())
+        .await?
+    {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+    let mode = AntiPassback::parse(&state.anti_passback);
+    if mode == AntiPassback::Off || !out_of_order(state.last_direction.as_deref(), direction) {
+        return Ok(None);
+    }
+    Ok(Some((state.id_zone, mode)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_direction_is_out_of_order() {
+        assert!(!out_of_order(None, "IN"));
+        assert!(!out_of_order(None, "OUT"));
+        assert!(!out_of_order(Some("OUT"), "IN"));
+        assert!(!out_of_order(Some("IN"), "OUT"));
+        assert!(out_of_order(Some("IN"), "IN"));
+        assert!(out_of_order(Some("OUT"), "OUT"));
+
+        for mode in PASSBACK_MODES {
+            assert_eq!(
+                AntiPassback::parse(mode) == AntiPassback::Off,
+                *mode == "off"
+            );
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::badge_card::{dpi_for, pdf_safe};
+use crate::models::{Presence, PresenceEntry, ZoneCount};
+use crate::timezone::site_tz;
+use chrono::{DateTime, Utc};
+use image::DynamicImage;
+use printpdf::{BuiltinFont, Image, ImageTransform, Mm, PdfDocument};
+use std::error::Error;
+use std::io::BufWriter;
+
+// A4 portrait
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 12.0;
+const HEADER_HEIGHT: f32 = 22.0;
+const ROW_HEIGHT: f32 = 24.0;
+const PHOTO_BOX: (f32, f32) = (16.0, 20.0);
+
+/// Sorts the people by zone and name, so a warden can go down the list zone by zone,
+/// and counts them per zone. People without a zone come last.
+pub fn summarize(mut people: Vec<PresenceEntry>, now: DateTime<Utc>) -> Presence {
+    people.sort_by(|a, b| {
+        // the id keeps equally named zones of different sites apart
+        (
+            a.zone.is_none(),
+            &a.zone,
+            a.id_zone,
+            &a.last_name,
+            &a.first_name,
+        )
+            .cmp(&(
+                b.zone.is_none(),
+                &b.zone,
+                b.id_zone,
+                &b.last_name,
+                &b.first_name,
+            ))
+    });
+
+    let mut zones: Vec<ZoneCount> = Vec::new();
+    for person in &mut people {
+        person.inside_minutes = (now - person.entered_at).num_minutes().max(0);
+        match zones.last_mut() {
+            Some(last) if last.id_zone == person.id_zone => last.count += 1,
+            _ => zones.push(ZoneCount {
+                id_zone: person.id_zone,
+                zone: person.zone.clone(),
+                count: 1,
+            }),
+        }
+    }
+
+    Presence {
+        as_of: now,
+        total: people.len(),
+        zones,
+        people,
+    }
+}
+
+fn duration(minutes: i64) -> String {
+    match minutes {
+        m if m < 60 => format!("{} min", m),
+        m => format!("{} h {} min", m / 60, m % 60),
+    }
+}
+
+/// Printable muster list, one row per person with their photo (`photos` in the same
+/// order as `presence.people`) and an empty box to tick off at the assembly point.
+pub fn muster_pdf(
+    presence: &Presence,
+    photos: &[Option<DynamicImage>],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (doc, page, layer) = PdfDocument::new(
+        "Muster list",
+        Mm(PAGE_WIDTH),
+        Mm(PAGE_HEIGHT),
+        "Muster list",
+    );
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+
+    let as_of = presence.as_of.with_timezone(&site_tz());
+    let header = doc.get_page(page).get_layer(layer);
+    header.use_text(
+        "Muster list",
+        16.0,
+        Mm(MARGIN),
+        Mm(PAGE_HEIGHT - MARGIN - 6.0),
+        &bold,
+    );
+    let counts: Vec<String> = presence
+        .zones
+        .iter()
+        .map(|z| format!("{}: {}", z.zone.as_deref().unwrap_or("no zone"), z.count))
+        .collect();
+    header.use_text(
+        pdf_safe(&format!(
+            "{} inside as of {}   {}",
+            presence.total,
+            as_of.format("%d.%m.%Y %H:%M"),
+            counts.join(", ")
+        )),
+        9.0,
+        Mm(MARGIN),
+        Mm(PAGE_HEIGHT - MARGIN - 13.0),
+        &regular,
+    );
+
+    let rows_per_page = ((PAGE_HEIGHT - 2.0 * MARGIN - HEADER_HEIGHT) / ROW_HEIGHT) as usize;
+    let mut layer = header;
+    for (i, person) in presence.people.iter().enumerate() {
+        let row = i % rows_per_page;
+        if i > 0 && row == 0 {
+            let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Muster list");
+            layer = doc.get_page(page).get_layer(new_layer);
+        }
+        let top = PAGE_HEIGHT - MARGIN - HEADER_HEIGHT - row as f32 * ROW_HEIGHT;
+
+        if let Some(Some(photo)) = photos.get(i) {
+            let photo = DynamicImage::ImageRgb8(photo.to_rgb8());
+            let dpi = dpi_for(photo.width(), PHOTO_BOX.0).max(dpi_for(photo.height(), PHOTO_BOX.1));
+            Image::from_dynamic_image(&photo).add_to_layer(
+                layer.clone(),
+                ImageTransform {
+                    translate_x: Some(Mm(MARGIN)),
+                    translate_y: Some(Mm(top - PHOTO_BOX.1)),
+                    dpi: Some(dpi),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let text_x = Mm(MARGIN + PHOTO_BOX.0 + 4.0);
+        layer.use_text(
+            "[   ]",
+            12.0,
+            Mm(PAGE_WIDTH - MARGIN - 12.0),
+            Mm(top - 8.0),
+            &bold,
+        );
+        layer.use_text(
+            pdf_safe(&format!("{} {}", person.first_name, person.last_name)),
+            11.0,
+            text_x,
+            Mm(top - 6.0),
+            &bold,
+        );
+        let who = match (&person.role, person.id_host) {
+            (Some(role), _) => format!("{} #{}", role, person.id),
+            (None, Some(host)) => format!("{} #{}, host #{}", person.kind, person.id, host),
+            (None, None) => format!("{} #{}", person.kind, person.id),
+        };
+        layer.use_text(pdf_safe(&who), 8.0, text_x, Mm(top - 11.0), &regular);
+        layer.use_text(
+            pdf_safe(&format!(
+                "{}, in since {} ({}) via {}",
+                person.zone.as_deref().unwrap_or("no zone"),
+                person
+                    .entered_at
+                    .with_timezone(&site_tz())
+                    .format("%d.%m %H:%M"),
+                duration(person.inside_minutes),
+                person.station.as_deref().unwrap_or("-")
+            )),
+            8.0,
+            text_x,
+            Mm(top - 15.0),
+            &regular,
+        );
+    }
+
+    let mut bytes = Vec::new();
+    doc.save(&mut BufWriter::new(&mut bytes))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person(
+        id: i32,
+        last_name: &str,
+        zone: Option<(i32, &str)>,
+        entered_at: &str,
+    ) -> PresenceEntry {
+        PresenceEntry {
+            kind: "employee".to_string(),
+            id,
+            first_name: "Anna".to_string(),
+            last_name: last_name.to_string(),
+            role: Some("employee".to_string()),
+            id_host: None,
+            photo_path: None,
+            entered_at: entered_at.parse().unwrap(),
+            station: Some("station_in".to_string()),
+            id_zone: zone.map(|z| z.0),
+            zone: zone.map(|z| z.1.to_string()),
+            inside_minutes: 0,
+        }
+    }
+
+    #[test]
+    fn test_counts_per_zone_and_time_inside() {
+        let now = "2026-03-04T12:00:00Z".parse().unwrap();
+        let people = vec![
+            person(1, "Nowak", None, "2026-03-04T11:59:00Z"),
+            person(2, "Zielińska", Some((3, "Lab")), "2026-03-04T08:30:00Z"),
+            person(3, "Adamska", Some((3, "Lab")), "2026-03-04T10:00:00Z"),
+            person(4, "Kowal", Some((1, "Hall")), "2026-03-04T11:00:00Z"),
+        ];
+
+        let presence = summarize(people, now);
+
+        assert_eq!(presence.total, 4);
+        let names: Vec<&str> = presence
+            .people
+            .iter()
+            .map(|p| p.last_name.as_str())
+            .collect();
+        assert_eq!(names, ["Kowal", "Adamska", "Zielińska", "Nowak"]);
+        let counts: Vec<(Option<i32>, usize)> = presence
+            .zones
+            .iter()
+            .map(|z| (z.id_zone, z.count))
+            .collect();
+        assert_eq!(counts, [(Some(1), 1), (Some(3), 2), (None, 1)]);
+        assert_eq!(presence.people[2].inside_minutes, 210);
+        assert_eq!(duration(210), "3 h 30 min");
+    }
+
+    #[test]
+    fn test_muster_pdf_spans_pages() {
+        let now = "2026-03-04T12:00:00Z".parse().unwrap();
+        let people = (1..=25)
+            .map(|id| person(id, "Nowak", None, "2026-03-04T08:00:00Z"))
+            .collect();
+        let presence = summarize(people, now);
+        let photos = vec![Some(DynamicImage::new_rgb8(60, 80))];
+
+        let pdf = muster_pdf(&presence, &photos).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::db::DatabaseRepository;
+use crate::models::PurgeReport;
+use crate::routes::AppState;
+use actix_web::web;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path};
+use std::time::Duration;
+
+const DEFAULT_PURGE_INTERVAL_HOURS: u64 = 24;
+
+/// True for relative paths inside `uploads/failed_attempts` without any `..` tricks.
+pub fn is_archived_attempt_photo(path: &str) -> bool {
+    let path = Path::new(path);
+    path.starts_with("uploads/failed_attempts")
+        && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Deletes archived failed attempt photos older than their retention and clears their
+/// `image_path`. With `dry_run` nothing is touched, the report only says what would go.
+pub async fn purge_expired_photos(
+    db: &dyn DatabaseRepository,
+    dry_run: bool,
+) -> Result<PurgeReport, sqlx::Error> {
+    let expired = db.get_expired_attempt_photos(Utc::now()).await?;
+
+    let mut by_reason: BTreeMap<String, usize> = BTreeMap::new();
+    for photo in &expired {
+        *by_reason.entry(photo.reason.clone()).or_default() += 1;
+    }
+
+    if !dry_run {
+        let mut purged = Vec::new();
+        for photo in &expired {
+            if !is_archived_attempt_photo(&photo.image_path) {
+                // not ours to delete, just drop the reference
+                purged.push(photo.id_attempt);
+                continue;
+            }
+            match fs::remove_file(&photo.image_path) {
+                Ok(_) => purged.push(photo.id_attempt),
+                Err(e) if e.kind() == ErrorKind::NotFound => purged.push(photo.id_attempt),
+                Err(e) => eprintln!("Failed to delete {}: {}", photo.image_path, e),
+            }
+        }
+        if !purged.is_empty() {
+            db.clear_attempt_images(purged).await?;
+        }
+    }
+
+    Ok(PurgeReport {
+        dry_run,
+        total: expired.len(),
+        by_reason,
+        items: expired,
+    })
+}
+
+/// Runs the purge in the background every `PHOTO_PURGE_INTERVAL_HOURS` (default 24),
+/// starting right away.
+pub fn spawn_purge_job(data: web::Data<AppState>) {
+    let hours = std::env::var("PHOTO_PURGE_INTERVAL_HOURS")
+        .ok()
+        .and_then(|h| h.parse::<u64>().ok())
+        .filter(|h| *h > 0)
+        .unwrap_or(DEFAULT_PURGE_INTERVAL_HOURS);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(hours * 3600));
+        loop {
+            interval.tick().await;
+            match purge_expired_photos(data.db.as_ref(), false).await {
+                Ok(report) if report.total > 0 => {
+                    println!("Purged {} expired failed attempt photos", report.total)
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Photo purge failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MockDatabaseRepository;
+    use crate::models::ExpiredPhoto;
+
+    fn expired(id: i32, reason: &str, path: &str) -> ExpiredPhoto {
+        ExpiredPhoto {
+            id_attempt: id,
+            reason: reason.to_string(),
+            image_path: path.to_string(),
+            timestamp: "2025-01-01T00:00:00Z".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_archived_attempt_photo_paths() {
+        assert!(is_archived_attempt_photo("uploads/failed_attempts/a.jpg"));
+        assert!(!is_archived_attempt_photo(
+            "uploads/failed_attempts/../../.env"
+        ));
+        assert!(!is_archived_attempt_photo("/etc/passwd"));
+        assert!(!is_archived_attempt_photo("uploads/employees/1.jpg"));
+    }
+
+    #[actix_web::test]
+    async fn test_dry_run_touches_nothing() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        mock_repo
+            .expect_get_expired_attempt_photos()
+            .returning(|_| {
+                Ok(vec![
+                    expired(1, "face_mismatched", "uploads/failed_attempts/a.jpg"),
+                    expired(2, "face_mismatched", "uploads/failed_attempts/b.jpg"),
+                    expired(3, "employee_not_found", "uploads/failed_attempts/c.jpg"),
+                ])
+            });
+        mock_repo.expect_clear_attempt_images().never();
+
+        let report = purge_expired_photos(&mock_repo, true).await.unwrap();
+
+        assert_eq!(report.total, 3);
+        assert_eq!(report.by_reason["face_mismatched"], 2);
+        assert_eq!(report.by_reason["employee_not_found"], 1);
+    }
+
+    #[actix_web::test]
+    async fn test_purge_clears_references_of_missing_files() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        mock_repo
+            .expect_get_expired_attempt_photos()
+            .returning(|_| {
+                Ok(vec![expired(
+                    4,
+                    "face_mismatched",
+                    "uploads/failed_attempts/already-gone.jpg",
+                )])
+            });
+        mock_repo
+            .expect_clear_attempt_images()
+            .with(mockall::predicate::eq(vec![4]))
+            .times(1)
+            .returning(|_| Ok(1));
+
+        let report = purge_expired_photos(&mock_repo, false).await.unwrap();
+
+        assert!(!report.dry_run);
+        assert_eq!(report.total, 1);
+    }
+}
@@ -1,20 +1,213 @@
+use crate::audit;
+use crate::auth;
 use crate::db::DatabaseRepository;
-use crate::logger;
 use crate::models::{
-    AccessAckRequest, AccessAckResponse, CheckQrRequest, CheckQrResponse, CreateEmployeeRequest,
-    CreateErrorLogRequest, EmployeeIdRequest, UpdateEmployeeRequest, VerifyFaceResponse,
+    AccessAckRequest, AccessAckResponse, AccessEvent, AuditPage, AuditQuery, BadgeImageQuery,
+    BreakRule, CheckQrRequest, CheckQrResponse, CreateEmployeeRequest, CreateErrorLogRequest,
+    CreateScheduleRequest, CreateSiteRequest, CreateVisitorRequest, CreateWorkHoursRequest,
+    DeleteWorkHoursRequest, EmployeeIdRequest, EmployeesQuery, FailedAttemptPage,
+    FailedAttemptQuery, GrantZoneRequest, HeartbeatRequest, Holiday, IssueBadgeRequest,
+    IssuePassRequest, IssuedBadge, IssuedPass, LoginRequest, NameRequest, NewAuditEntry,
+    NewFailedAttempt, PassImageQuery, PassbackResetQuery, Presence, RegisterStationRequest,
+    RetentionRule, Session, SetEscortRequest, SetModeRequest, SetPassbackRequest, Station,
+    StationConfig, StationConfigUpdate, StationCredentials, StationDoorRequest, SyncRequest,
+    SyncResponse, TotpEnrollment, UnlockRequest, UpdateEmployeeRequest, UpdateWorkHoursRequest,
+    VerifyFaceResponse, VisitorEntry,
 };
 use actix_multipart::Multipart;
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, SubsecRound, Utc};
 use futures::{StreamExt, TryStreamExt};
 use std::fs;
 use std::io::Write;
 use uuid::Uuid;
 
+use crate::badge::{self, BadgeClaims, PassClaims};
+use crate::badge_card;
+use crate::escort::{self, Escort, Subject};
+use crate::events::EventBus;
+use crate::gdpr;
 use crate::image_processor;
+use crate::lockout::{self, LockoutPolicy, RateLimiter};
+use crate::modes::{self, SiteMode};
+use crate::passback::{self, AntiPassback};
+use crate::presence;
+use crate::retention::{self, is_archived_attempt_photo};
+use crate::schedule::{self, ScheduleCheck};
+use crate::stations;
+use crate::sync;
+use crate::totp;
+use crate::visitors;
+use crate::work_time;
+use crate::zones;
 
 pub struct AppState {
     pub db: Box<dyn DatabaseRepository>,
+    pub events: EventBus,
+    pub lockout: LockoutPolicy,
+    pub limiter: RateLimiter,
+    pub allow_plain_badges: bool,
+}
+
+impl AppState {
+    pub fn new(db: Box<dyn DatabaseRepository>) -> Self {
+        let lockout = LockoutPolicy::from_env();
+        Self {
+            db,
+            events: EventBus::new(),
+            limiter: RateLimiter::new(lockout.rate_limit_per_minute),
+            lockout,
+            allow_plain_badges: badge::plain_ids_allowed(),
+        }
+    }
+}
+
+/// Employee behind a panel session, the token comes from `POST /api/login`.
+async fn session_actor(
+    data: &web::Data<AppState>,
+    token: Option<String>,
+) -> Result<i32, HttpResponse> {
+    let token = match token {
+        Some(token) => token,
+        None => return Err(HttpResponse::Unauthorized().body("Not logged in")),
+    };
+    match data
+        .db
+        .get_session_employee(stations::hash_token(&token), Utc::now())
+        .await
+    {
+        Ok(Some(id)) => Ok(id),
+        Ok(None) => Err(HttpResponse::Unauthorized().body("Session expired or unknown")),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err(HttpResponse::InternalServerError().body("Database error"))
+        }
+    }
+}
+
+/// Id of the employee performing an administrative change, from their session token
+/// sent as `Authorization: Bearer`.
+async fn require_session(
+    data: &web::Data<AppState>,
+    req: &HttpRequest,
+) -> Result<i32, HttpResponse> {
+    session_actor(data, stations::bearer_token(req)).await
+}
+
+/// `EventSource` can't set headers, so the event stream also takes the session token as
+/// a `token` query parameter. Nothing else does, query strings end up in logs and browser history.
+fn query_token(req: &HttpRequest) -> Option<String> {
+    web::Query::<std::collections::HashMap<String, String>>::from_query(req.query_string())
+        .ok()?
+        .get("token")
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// Roles allowed to look at security data (failed attempts and their photos).
+const SECURITY_ROLES: &[&str] = &["admin", "manager"];
+const ADMIN_ROLES: &[&str] = &["admin"];
+
+const DEFAULT_ACK_MAX_SKEW_SECONDS: i64 = 300;
+const DEFAULT_FACE_THRESHOLD: f32 = 0.95;
+const DEFAULT_BADGE_VALID_DAYS: i64 = 365;
+const MAX_BADGE_VALID_DAYS: i64 = 5 * 365;
+
+/// Resolves the caller from their session and checks they have one of `roles`.
+async fn require_role(
+    data: &web::Data<AppState>,
+    req: &HttpRequest,
+    roles: &[&str],
+) -> Result<i32, HttpResponse> {
+    let id = require_session(data, req).await?;
+    check_role(data, id, roles).await
+}
+
+async fn check_role(
+    data: &web::Data<AppState>,
+    id: i32,
+    roles: &[&str],
+) -> Result<i32, HttpResponse> {
+    match data.db.get_employee_role(id).await {
+        Ok(Some(role)) if roles.contains(&role.as_str()) => Ok(id),
+        Ok(_) => Err(HttpResponse::Forbidden().body("Not allowed")),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err(HttpResponse::InternalServerError().body("Database error"))
+        }
+    }
+}
+
+pub async fn login(data: web::Data<AppState>, req: web::Json<LoginRequest>) -> impl Responder {
+    let req = req.into_inner();
+    if !data.limiter.allow(&format!("login:{}", req.login)) {
+        return HttpResponse::TooManyRequests().body("Too many login attempts");
+    }
+
+    let (id, stored) = match data.db.get_login_credentials(req.login).await {
+        Ok(Some((id, Some(stored)))) => (id, stored),
+        Ok(_) => return HttpResponse::Unauthorized().body("Invalid login or password"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+    match auth::verify_password(&req.password, &stored) {
+        auth::PasswordCheck::Valid => {}
+        auth::PasswordCheck::ValidLegacy => {
+            if let Err(e) = data
+                .db
+                .set_password_hash(id, auth::hash_password(&req.password))
+                .await
+            {
+                eprintln!("Failed to hash the password of {}: {}", id, e);
+            }
+        }
+        auth::PasswordCheck::Invalid => {
+            return HttpResponse::Unauthorized().body("Invalid login or password")
+        }
+    }
+
+    let role = match data.db.get_employee_role(id).await {
+        Ok(Some(role)) => role,
+        Ok(None) => return HttpResponse::Unauthorized().body("Invalid login or password"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+    let token = stations::generate_token();
+    let expires_at = auth::session_expires_at(Utc::now());
+    match data
+        .db
+        .create_session(id, stations::hash_token(&token), expires_at)
+        .await
+    {
+        Ok(()) => HttpResponse::Ok().json(Session {
+            token,
+            id_person: id,
+            role,
+            expires_at,
+        }),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn logout(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    let token = match stations::bearer_token(&http_req) {
+        Some(token) => token,
+        None => return HttpResponse::Unauthorized().body("Not logged in"),
+    };
+    match data.db.delete_session(stations::hash_token(&token)).await {
+        Ok(_) => HttpResponse::Ok().body("Logged out"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
 }
 
 pub async fn health_check() -> impl Responder {
@@ -31,12 +224,12 @@ pub async fn get_access_logs(data: web::Data<AppState>) -> impl Responder {
     }
 }
 
-pub async fn upload_employee_photo(
-    data: web::Data<AppState>,
-    path: web::Path<i32>,
-    mut payload: Multipart,
-) -> impl Responder {
-    let id_person = path.into_inner();
+/// Writes the multipart `photo` field to `<dir>/<id>.jpg`, None if there is no such field.
+async fn save_photo_upload(
+    payload: &mut Multipart,
+    dir: &str,
+    id: i32,
+) -> Result<Option<String>, HttpResponse> {
     let mut photo_path: Option<String> = None;
 
     while let Ok(Some(mut field)) = payload.try_next().await {
@@ -44,81 +237,142 @@ pub async fn upload_employee_photo(
         let field_name = content_disposition.get_name().unwrap_or("");
 
         if field_name == "photo" {
-            let filename = format!("uploads/employees/{}.jpg", id_person);
-            if let Err(e) = fs::create_dir_all("uploads/employees") {
+            let filename = format!("{}/{}.jpg", dir, id);
+            if let Err(e) = fs::create_dir_all(dir) {
                 eprintln!("Failed to create directory: {}", e);
-                return HttpResponse::InternalServerError().body("Server error");
+                return Err(HttpResponse::InternalServerError().body("Server error"));
             }
 
             let mut f = match fs::File::create(&filename) {
                 Ok(file) => file,
                 Err(e) => {
                     eprintln!("Failed to create file: {}", e);
-                    return HttpResponse::InternalServerError().body("Server error");
+                    return Err(HttpResponse::InternalServerError().body("Server error"));
                 }
             };
 
             while let Some(chunk) = field.next().await {
                 if let Err(e) = f.write_all(&chunk.unwrap_or_default()) {
                     eprintln!("Failed to write file: {}", e);
-                    return HttpResponse::InternalServerError().body("Server error");
+                    return Err(HttpResponse::InternalServerError().body("Server error"));
                 }
             }
             photo_path = Some(filename);
         }
     }
+    Ok(photo_path)
+}
 
-    if let Some(p_path) = photo_path {
-        if !std::path::Path::new("arcface.onnx").exists() && std::env::var("MOCK_MODEL").is_err() {
-            eprintln!("Model arcface.onnx not found.");
-            return HttpResponse::InternalServerError().body("Model not found");
-        }
+/// Embedding of an enrollment photo as little-endian f32s, empty with MOCK_MODEL.
+fn enrollment_embedding(photo_path: &str) -> Result<Vec<u8>, HttpResponse> {
+    if !std::path::Path::new("arcface.onnx").exists() && std::env::var("MOCK_MODEL").is_err() {
+        eprintln!("Model arcface.onnx not found.");
+        return Err(HttpResponse::InternalServerError().body("Model not found"));
+    }
+    if std::env::var("MOCK_MODEL").is_ok() {
+        return Ok(vec![]);
+    }
 
-        if std::env::var("MOCK_MODEL").is_ok() {
-            return match data
-                .db
-                .update_employee_photo(id_person, vec![], p_path)
-                .await
-            {
-                Ok(_) => HttpResponse::Ok().body("Photo uploaded and processed"),
-                Err(e) => {
-                    eprintln!("Database error: {}", e);
-                    HttpResponse::InternalServerError().body("Database error")
-                }
-            };
+    match image_processor::face_embedding(photo_path, "arcface.onnx") {
+        Ok(embedding) => {
+            let mut bytes: Vec<u8> = Vec::with_capacity(embedding.len() * 4);
+            for float in embedding {
+                bytes.extend_from_slice(&float.to_le_bytes());
+            }
+            Ok(bytes)
+        }
+        Err(e) => {
+            eprintln!("Face embedding failed: {}", e);
+            Err(HttpResponse::InternalServerError().body("Face processing error"))
         }
+    }
+}
 
-        match image_processor::face_embedding(&p_path, "arcface.onnx") {
-            Ok(embedding) => {
-                let mut bytes: Vec<u8> = Vec::with_capacity(embedding.len() * 4);
-                for float in embedding {
-                    bytes.extend_from_slice(&float.to_le_bytes());
-                }
+pub async fn upload_employee_photo(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let id_person = path.into_inner();
+    let before = employee_snapshot(&data, id_person).await;
+    if before.as_ref().is_some_and(|b| !b["archived_at"].is_null()) {
+        return HttpResponse::Conflict().body("Employee is archived");
+    }
+    let p_path = match save_photo_upload(&mut payload, "uploads/employees", id_person).await {
+        Ok(Some(p_path)) => p_path,
+        Ok(None) => return HttpResponse::BadRequest().body("Missing photo field"),
+        Err(resp) => return resp,
+    };
+    let bytes = match enrollment_embedding(&p_path) {
+        Ok(bytes) => bytes,
+        Err(resp) => return resp,
+    };
 
-                match data
-                    .db
-                    .update_employee_photo(id_person, bytes, p_path)
-                    .await
-                {
-                    Ok(_) => HttpResponse::Ok().body("Photo uploaded and processed"),
-                    Err(e) => {
-                        eprintln!("Database error: {}", e);
-                        HttpResponse::InternalServerError().body("Database error")
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("Face embedding failed: {}", e);
-                HttpResponse::InternalServerError().body("Face processing error")
+    match data
+        .db
+        .update_employee_photo(id_person, bytes, p_path)
+        .await
+    {
+        Ok(_) => {
+            if before.is_some() {
+                // the embedding itself stays out of the log, only that it was replaced
+                let after = employee_snapshot(&data, id_person).await.map(|mut after| {
+                    after["face_embedded"] = audit::REDACTED.into();
+                    after
+                });
+                let entry =
+                    NewAuditEntry::new(&http_req, actor, "photo", "employee", Some(id_person));
+                audit::record(&data, entry.changes(before, after)).await;
             }
+            HttpResponse::Ok().body("Photo uploaded and processed")
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
         }
-    } else {
-        HttpResponse::BadRequest().body("Missing photo field")
     }
 }
 
-pub async fn report_error(req: web::Json<CreateErrorLogRequest>) -> impl Responder {
-    match logger::log_error(req.into_inner()) {
+pub async fn report_error(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<CreateErrorLogRequest>,
+) -> impl Responder {
+    let station = match authenticated_station(&data, &http_req).await {
+        Ok(station) => station,
+        Err(resp) => return resp,
+    };
+    let req = req.into_inner();
+    // the frame is served and deleted later, so it has to be one of ours
+    if req
+        .image
+        .as_deref()
+        .is_some_and(|p| !is_archived_attempt_photo(p))
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "invalid_image"}));
+    }
+    let id_employee = req.employee.trim().parse().ok();
+    let mut event = AccessEvent::new("error_report", id_employee);
+    event.station = Some(station.name.clone());
+    event.direction = Some(station.direction.clone());
+    publish_event(&data, event.outcome(false, &req.error_description, None)).await;
+
+    let attempt = NewFailedAttempt {
+        id_employee,
+        reason: req.error_description,
+        similarity: None,
+        station: Some(station.name),
+        direction: Some(station.direction),
+        image_path: req.image,
+        timestamp: Utc::now(),
+    };
+
+    match data.db.add_failed_attempt(attempt).await {
         Ok(_) => HttpResponse::Ok().body("Error logged"),
         Err(e) => {
             eprintln!("Failed to log error: {}", e);
@@ -127,20 +381,436 @@ pub async fn report_error(req: web::Json<CreateErrorLogRequest>) -> impl Respond
     }
 }
 
-pub async fn check_qr(data: web::Data<AppState>, req: web::Json<CheckQrRequest>) -> impl Responder {
-    match data.db.get_employee_by_id(req.employee_id).await {
-        Ok(Some((_, first_name, last_name))) => HttpResponse::Ok().json(CheckQrResponse {
+/// The station calling a gate endpoint, from its `Authorization: Bearer` token. Where a
+/// scan happened (name, door, direction) comes from this row, whatever the body says.
+async fn authenticated_station(
+    data: &web::Data<AppState>,
+    http_req: &HttpRequest,
+) -> Result<Station, HttpResponse> {
+    let token = match stations::bearer_token(http_req) {
+        Some(token) => token,
+        None => {
+            return Err(HttpResponse::Unauthorized()
+                .json(serde_json::json!({"error": "station_token_required"})))
+        }
+    };
+    match data
+        .db
+        .get_station_by_token(stations::hash_token(&token))
+        .await
+    {
+        Ok(Some(station)) => Ok(station),
+        Ok(None) => Err(HttpResponse::Unauthorized()
+            .json(serde_json::json!({"error": "unknown_station_token"}))),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err(HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "database_error"})))
+        }
+    }
+}
+
+/// A station's token or a session with one of `roles`, for actions both the gate and
+/// the staff behind the panel take.
+async fn require_station_or_role(
+    data: &web::Data<AppState>,
+    http_req: &HttpRequest,
+    roles: &[&str],
+) -> Result<(), HttpResponse> {
+    if let Some(token) = stations::bearer_token(http_req) {
+        match data
+            .db
+            .get_station_by_token(stations::hash_token(&token))
+            .await
+        {
+            Ok(Some(_)) => return Ok(()),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                return Err(HttpResponse::InternalServerError().body("Database error"));
+            }
+        }
+    }
+    require_role(data, http_req, roles).await.map(|_| ())
+}
+
+pub async fn check_qr(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<CheckQrRequest>,
+) -> impl Responder {
+    let station = match authenticated_station(&data, &http_req).await {
+        Ok(station) => station,
+        Err(resp) => return resp,
+    };
+    let mut req = req.into_inner();
+    req.station = Some(station.name.clone());
+    req.direction = station.direction;
+
+    let mode = match modes::current_mode(&data, req.station.as_deref()).await {
+        Ok(mode) => mode,
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "database_error"}));
+        }
+    };
+    // nobody is checked on the way out of an evacuated site, not even their badge
+    if mode.opens_exit(&req.direction) {
+        let mut event = AccessEvent::new("check_qr", None);
+        event.station = req.station.clone();
+        event.direction = Some(req.direction.clone());
+        publish_event(&data, event.outcome(true, "evacuation", None)).await;
+        return HttpResponse::Ok().json(CheckQrResponse {
             exists: true,
             employee_id: req.employee_id,
-            first_name: Some(first_name),
-            last_name: Some(last_name),
-        }),
-        Ok(None) => HttpResponse::Ok().json(CheckQrResponse {
-            exists: false,
-            employee_id: req.employee_id,
             first_name: None,
             last_name: None,
-        }),
+            reason: Some("evacuation".to_string()),
+            next_allowed_at: None,
+            visitor: None,
+        });
+    }
+    if let Some(code) = req.code.as_deref().filter(|c| badge::is_pass_code(c)) {
+        if mode == SiteMode::Lockdown {
+            let mut event = AccessEvent::new("check_qr", None);
+            event.station = req.station.clone();
+            event.direction = Some(req.direction.clone());
+            publish_event(&data, event.outcome(false, "lockdown", None)).await;
+            return HttpResponse::Ok().json(CheckQrResponse {
+                exists: false,
+                employee_id: 0,
+                first_name: None,
+                last_name: None,
+                reason: Some("lockdown".to_string()),
+                next_allowed_at: None,
+                visitor: None,
+            });
+        }
+        return check_visitor_pass(&data, &req, code).await;
+    }
+    let employee_id = match req.code.as_deref() {
+        Some(code) => {
+            let verified = if totp::is_totp_code(code) {
+                verify_totp(&data, code, true).await
+            } else {
+                verify_badge(&data, code).await
+            };
+            match verified {
+                Ok(Ok(employee_id)) => employee_id,
+                Ok(Err((reason, claimed))) => {
+                    return reject_badge(&data, &req, reason, claimed).await
+                }
+                Err(e) => {
+                    eprintln!("Database error: {}", e);
+                    return HttpResponse::InternalServerError()
+                        .json(serde_json::json!({"error": "database_error"}));
+                }
+            }
+        }
+        None if !data.allow_plain_badges => {
+            return reject_badge(&data, &req, "invalid_badge", None).await
+        }
+        None => req.employee_id,
+    };
+
+    let mut event = AccessEvent::new("check_qr", Some(employee_id));
+    event.station = req.station.clone();
+    event.direction = Some(req.direction.clone());
+
+    match lockout::check_blocked(&data, employee_id, &station.name).await {
+        Ok(Some(reason)) => {
+            publish_event(&data, event.outcome(false, reason, None)).await;
+            return HttpResponse::Ok().json(CheckQrResponse {
+                exists: false,
+                employee_id,
+                first_name: None,
+                last_name: None,
+                reason: Some(reason.to_string()),
+                next_allowed_at: None,
+                visitor: None,
+            });
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "database_error"}));
+        }
+    }
+
+    match data.db.get_employee_by_id(employee_id).await {
+        Ok(Some((_, first_name, last_name))) => {
+            event.employee_name = Some(format!("{} {}", first_name, last_name));
+            match entry_denied(
+                &data,
+                employee_id,
+                req.station.as_deref(),
+                &req.direction,
+                mode,
+            )
+            .await
+            {
+                Ok(Some((reason, next_allowed_at))) => {
+                    publish_event(&data, event.outcome(false, reason, None)).await;
+                    return HttpResponse::Ok().json(CheckQrResponse {
+                        exists: false,
+                        employee_id,
+                        first_name: None,
+                        last_name: None,
+                        reason: Some(reason.to_string()),
+                        next_allowed_at,
+                        visitor: None,
+                    });
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Database error: {}", e);
+                    return HttpResponse::InternalServerError()
+                        .json(serde_json::json!({"error": "database_error"}));
+                }
+            }
+            publish_event(&data, event.outcome(true, "employee_found", None)).await;
+            HttpResponse::Ok().json(CheckQrResponse {
+                exists: true,
+                employee_id,
+                first_name: Some(first_name),
+                last_name: Some(last_name),
+                reason: None,
+                next_allowed_at: None,
+                visitor: None,
+            })
+        }
+        Ok(None) => {
+            publish_event(&data, event.outcome(false, "employee_not_found", None)).await;
+            HttpResponse::Ok().json(CheckQrResponse {
+                exists: false,
+                employee_id,
+                first_name: None,
+                last_name: None,
+                reason: None,
+                next_allowed_at: None,
+                visitor: None,
+            })
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "database_error"}))
+        }
+    }
+}
+
+/// Whether a known employee may pass `station` right now: `lockdown`, `zone_not_permitted`,
+/// `passback` in a hard anti-passback zone, or `outside_access_hours` together with the
+/// start of the next allowed window.
+async fn entry_denied(
+    data: &web::Data<AppState>,
+    employee_id: i32,
+    station: Option<&str>,
+    direction: &str,
+    mode: SiteMode,
+) -> Result<Option<(&'static str, Option<DateTime<Utc>>)>, sqlx::Error> {
+    if modes::lockdown_denies(data, mode, employee_id).await? {
+        return Ok(Some(("lockdown", None)));
+    }
+    if let Some(reason) = zones::check_zone(data, employee_id, station, direction).await? {
+        return Ok(Some((reason, None)));
+    }
+    if let Some((_, AntiPassback::Hard)) =
+        passback::check_passback(data, employee_id, station, direction).await?
+    {
+        return Ok(Some(("passback", None)));
+    }
+    match schedule::check_schedule(data, employee_id, station, direction).await? {
+        ScheduleCheck::Allowed => Ok(None),
+        ScheduleCheck::Outside { next_window } => Ok(Some(("outside_access_hours", next_window))),
+    }
+}
+
+/// Checks a scanned badge code against its signature and the `badges` table. The inner
+/// error is the reason for the station plus the employee the code claims to be for.
+async fn verify_badge(
+    data: &web::Data<AppState>,
+    code: &str,
+) -> Result<Result<i32, (&'static str, Option<i32>)>, sqlx::Error> {
+    let secret = match badge::secret() {
+        Some(secret) => secret,
+        None => {
+            eprintln!("BADGE_SECRET is not set, can't verify badges");
+            return Ok(Err(("invalid_badge", None)));
+        }
+    };
+    let claims = match badge::verify(code, &secret, Utc::now()) {
+        Ok(claims) => claims,
+        Err(reason) => return Ok(Err((reason, None))),
+    };
+    let claimed = Some(claims.employee_id);
+
+    match data.db.get_badge(claims.badge_id).await? {
+        Some(b) if b.id_employee != claims.employee_id => Ok(Err(("invalid_badge", claimed))),
+        Some(b) if b.revoked_at.is_some() => Ok(Err(("badge_revoked", claimed))),
+        Some(b) if b.expires_at <= Utc::now() => Ok(Err(("badge_expired", claimed))),
+        Some(_) => Ok(Ok(claims.employee_id)),
+        None => Ok(Err(("invalid_badge", claimed))),
+    }
+}
+
+/// Checks a rotating code from the phone app. With `single_use` every time step is
+/// accepted only once, so a screenshot of the code is useless after the first scan.
+async fn verify_totp(
+    data: &web::Data<AppState>,
+    code: &str,
+    single_use: bool,
+) -> Result<Result<i32, (&'static str, Option<i32>)>, sqlx::Error> {
+    let (employee_id, code) = match totp::parse(code) {
+        Some(parsed) => parsed,
+        None => return Ok(Err(("invalid_code", None))),
+    };
+    let claimed = Some(employee_id);
+
+    let secret = match data.db.get_totp_secret(employee_id).await? {
+        Some(secret) => secret,
+        None => return Ok(Err(("totp_not_enrolled", claimed))),
+    };
+    let step = match totp::verify(&secret, code, Utc::now()) {
+        Some(step) => step,
+        None => return Ok(Err(("invalid_code", claimed))),
+    };
+    if single_use && !data.db.use_totp_step(employee_id, step).await? {
+        return Ok(Err(("code_reused", claimed)));
+    }
+    Ok(Ok(employee_id))
+}
+
+/// Turns a bad badge away. It counts as a failed attempt at the station, so someone
+/// trying forged codes gets the station locked like repeated face mismatches would.
+async fn reject_badge(
+    data: &web::Data<AppState>,
+    req: &CheckQrRequest,
+    reason: &str,
+    claimed: Option<i32>,
+) -> HttpResponse {
+    let employee_id = claimed.unwrap_or(req.employee_id);
+    log_bad_badge(
+        data,
+        "check_qr",
+        req.station.clone(),
+        &req.direction,
+        reason,
+        claimed,
+    )
+    .await;
+
+    HttpResponse::Ok().json(CheckQrResponse {
+        exists: false,
+        employee_id,
+        first_name: None,
+        last_name: None,
+        reason: Some(reason.to_string()),
+        next_allowed_at: None,
+        visitor: None,
+    })
+}
+
+/// Publishes and stores a rejected badge. Only the station counts the failure, the
+/// claimed employee didn't necessarily hold the code.
+async fn log_bad_badge(
+    data: &web::Data<AppState>,
+    kind: &str,
+    station: Option<String>,
+    direction: &str,
+    reason: &str,
+    claimed: Option<i32>,
+) {
+    let mut event = AccessEvent::new(kind, claimed);
+    event.station = station.clone();
+    event.direction = Some(direction.to_string());
+    publish_event(data, event.outcome(false, reason, None)).await;
+
+    let attempt = NewFailedAttempt {
+        id_employee: claimed,
+        reason: reason.to_string(),
+        similarity: None,
+        station: station.clone(),
+        direction: Some(direction.to_string()),
+        image_path: None,
+        timestamp: Utc::now(),
+    };
+    if let Err(e) = data.db.add_failed_attempt(attempt).await {
+        eprintln!("Failed to log failed attempt: {}", e);
+    } else if let Err(e) =
+        lockout::register_failure(data, None, station.as_deref().unwrap_or_default()).await
+    {
+        eprintln!("Failed to update lockouts: {}", e);
+    }
+}
+
+/// Check of a scanned visitor pass. Nothing is used up yet, that happens once the
+/// face (or, without an enrolled photo, just the pass) is verified.
+async fn check_visitor_pass(
+    data: &web::Data<AppState>,
+    req: &CheckQrRequest,
+    code: &str,
+) -> HttpResponse {
+    let secret = match badge::secret() {
+        Some(secret) => secret,
+        None => {
+            eprintln!("BADGE_SECRET is not set, can't verify visitor passes");
+            return reject_badge(data, req, "invalid_pass", None).await;
+        }
+    };
+    let claims = match badge::verify_pass(code, &secret, Utc::now()) {
+        Ok(claims) => claims,
+        Err(reason) => return reject_badge(data, req, reason, None).await,
+    };
+
+    let mut event = AccessEvent::new("check_qr", None);
+    event.station = req.station.clone();
+    event.direction = Some(req.direction.clone());
+
+    match visitors::check_pass(
+        data,
+        claims.pass_id,
+        Some(claims.visitor_id),
+        req.station.as_deref(),
+        &req.direction,
+    )
+    .await
+    {
+        Ok(Ok((visitor, pass))) => {
+            event.employee_name = Some(format!(
+                "{} {} ({})",
+                visitor.first_name, visitor.last_name, visitor.kind
+            ));
+            publish_event(data, event.outcome(true, "visitor_found", None)).await;
+            HttpResponse::Ok().json(CheckQrResponse {
+                exists: true,
+                employee_id: 0,
+                first_name: Some(visitor.first_name),
+                last_name: Some(visitor.last_name),
+                reason: None,
+                next_allowed_at: None,
+                visitor: Some(VisitorEntry {
+                    visitor_id: visitor.id_visitor,
+                    pass_id: pass.id_pass,
+                    kind: visitor.kind,
+                    face_enrolled: visitor.face_enrolled,
+                }),
+            })
+        }
+        Ok(Err("zone_not_permitted")) => {
+            publish_event(data, event.outcome(false, "zone_not_permitted", None)).await;
+            HttpResponse::Ok().json(CheckQrResponse {
+                exists: false,
+                employee_id: 0,
+                first_name: None,
+                last_name: None,
+                reason: Some("zone_not_permitted".to_string()),
+                next_allowed_at: None,
+                visitor: None,
+            })
+        }
+        Ok(Err(reason)) => reject_badge(data, req, reason, None).await,
         Err(e) => {
             eprintln!("Database error: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({"error": "database_error"}))
@@ -148,9 +818,22 @@ pub async fn check_qr(data: web::Data<AppState>, req: web::Json<CheckQrRequest>)
     }
 }
 
-pub async fn verify_face(data: web::Data<AppState>, mut payload: Multipart) -> impl Responder {
+pub async fn verify_face(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    mut payload: Multipart,
+) -> impl Responder {
+    let station_row = match authenticated_station(&data, &http_req).await {
+        Ok(station) => station,
+        Err(resp) => return resp,
+    };
+    let (station, dir) = (
+        Some(station_row.name.clone()),
+        station_row.direction.clone(),
+    );
     let mut employee_id: Option<i32> = None;
-    let mut direction: Option<String> = None;
+    let mut code: Option<String> = None;
+    let mut pass_code: Option<String> = None;
     let mut photo_path: Option<String> = None;
 
     while let Ok(Some(mut field)) = payload.try_next().await {
@@ -166,12 +849,17 @@ pub async fn verify_face(data: web::Data<AppState>, mut payload: Multipart) -> i
             if let Ok(id) = value_str.trim().parse::<i32>() {
                 employee_id = Some(id);
             }
-        } else if field_name == "direction" {
+        } else if field_name == "code" || field_name == "pass_code" {
+            let target = if field_name == "code" {
+                &mut code
+            } else {
+                &mut pass_code
+            };
             let mut value_bytes = Vec::new();
             while let Some(chunk) = field.next().await {
                 value_bytes.extend_from_slice(&chunk.unwrap_or_default());
             }
-            direction = Some(String::from_utf8_lossy(&value_bytes).trim().to_string());
+            *target = Some(String::from_utf8_lossy(&value_bytes).trim().to_string());
         } else if field_name == "photo" {
             let filename = format!("/tmp/{}.jpg", Uuid::new_v4());
             let mut f = match fs::File::create(&filename) {
@@ -185,32 +873,143 @@ pub async fn verify_face(data: web::Data<AppState>, mut payload: Multipart) -> i
         }
     }
 
-    if employee_id.is_none() || direction.is_none() || photo_path.is_none() {
+    if (employee_id.is_none() && code.is_none() && pass_code.is_none()) || photo_path.is_none() {
         return HttpResponse::BadRequest().body("Missing fields");
     }
 
-    let emp_id = employee_id.unwrap();
-    let _dir = direction.unwrap();
     let p_path = photo_path.unwrap();
 
-    if !std::path::Path::new("arcface.onnx").exists() || std::env::var("MOCK_MODEL").is_ok() {
-        eprintln!("Model arcface.onnx not found or MOCK_MODEL set. Returning MOCK response.");
+    let mode = match modes::current_mode(&data, station.as_deref()).await {
+        Ok(mode) => mode,
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            let _ = fs::remove_file(p_path);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "database_error"}));
+        }
+    };
+    // evacuation opens the exits, a lockdown keeps out visitors (employees in entry_denied)
+    if mode.opens_exit(&dir) || (pass_code.is_some() && mode == SiteMode::Lockdown) {
         let _ = fs::remove_file(p_path);
+        let granted = mode == SiteMode::Evacuation;
+        let mut event =
+            AccessEvent::new("verify_face", employee_id.filter(|_| pass_code.is_none()));
+        event.station = station;
+        event.direction = Some(dir);
+        publish_event(&data, event.outcome(granted, mode.as_str(), None)).await;
         return HttpResponse::Ok().json(VerifyFaceResponse {
-            access_granted: true,
-            reason: "mock_mode_no_model".to_string(),
+            access_granted: granted,
+            reason: mode.as_str().to_string(),
             similarity: None,
+            next_allowed_at: None,
+            authorization_id: None,
         });
     }
-
+    if let Some(code) = pass_code {
+        return verify_visitor_face(&data, &code, &station_row, p_path).await;
+    }
+    // the same code as at check_qr, a TOTP step is spent there already
+    let verified = match code.as_deref() {
+        Some(code) if totp::is_totp_code(code) => verify_totp(&data, code, false).await,
+        Some(code) => verify_badge(&data, code).await,
+        None if !data.allow_plain_badges => Ok(Err(("invalid_badge", None))),
+        None => Ok(Ok(employee_id.unwrap_or_default())),
+    };
+    let emp_id = match verified {
+        Ok(Ok(emp_id)) => emp_id,
+        Ok(Err((reason, claimed))) => {
+            let _ = fs::remove_file(p_path);
+            log_bad_badge(&data, "verify_face", station, &dir, reason, claimed).await;
+            return HttpResponse::Ok().json(VerifyFaceResponse {
+                access_granted: false,
+                reason: reason.to_string(),
+                similarity: None,
+                next_allowed_at: None,
+                authorization_id: None,
+            });
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            let _ = fs::remove_file(p_path);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "database_error"}));
+        }
+    };
+
+    let mut event = AccessEvent::new("verify_face", Some(emp_id));
+    event.station = station.clone();
+    event.direction = Some(dir.clone());
+
+    let denied = match lockout::check_blocked(&data, emp_id, &station_row.name).await {
+        Ok(None) => entry_denied(&data, emp_id, station.as_deref(), &dir, mode).await,
+        Ok(Some(reason)) => Ok(Some((reason, None))),
+        Err(e) => Err(e),
+    };
+    match denied {
+        Ok(Some((reason, next_allowed_at))) => {
+            let _ = fs::remove_file(p_path);
+            publish_event(&data, event.outcome(false, reason, None)).await;
+            return HttpResponse::Ok().json(VerifyFaceResponse {
+                access_granted: false,
+                reason: reason.to_string(),
+                similarity: None,
+                next_allowed_at,
+                authorization_id: None,
+            });
+        }
+        Ok(None) => {}
+        Err(_) => {
+            let _ = fs::remove_file(p_path);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "database_error"}));
+        }
+    }
+
+    if !std::path::Path::new("arcface.onnx").exists() || std::env::var("MOCK_MODEL").is_ok() {
+        eprintln!("Model arcface.onnx not found or MOCK_MODEL set. Returning MOCK response.");
+        let _ = fs::remove_file(p_path);
+        if let Some(resp) = escort_check(
+            &data,
+            Subject::Employee(emp_id),
+            &station,
+            &dir,
+            &event,
+            None,
+        )
+        .await
+        {
+            return resp;
+        }
+        publish_event(&data, event.outcome(true, "mock_mode_no_model", None)).await;
+        return HttpResponse::Ok().json(VerifyFaceResponse {
+            access_granted: true,
+            reason: "mock_mode_no_model".to_string(),
+            similarity: None,
+            next_allowed_at: None,
+            authorization_id: None,
+        });
+    }
+
     let stored_embedding = match data.db.get_employee_embedding(emp_id).await {
         Ok(Some(emb)) => emb,
         Ok(None) => {
-            log_failed_attempt(emp_id, "employee_not_found", &p_path);
+            log_failed_attempt(
+                &data,
+                Some(emp_id),
+                "employee_not_found",
+                None,
+                station,
+                &dir,
+                &p_path,
+            )
+            .await;
+            publish_event(&data, event.outcome(false, "employee_not_found", None)).await;
             return HttpResponse::Ok().json(VerifyFaceResponse {
                 access_granted: false,
                 reason: "employee_not_found".to_string(),
                 similarity: None,
+                next_allowed_at: None,
+                authorization_id: None,
             });
         }
         Err(_) => {
@@ -221,11 +1020,23 @@ pub async fn verify_face(data: web::Data<AppState>, mut payload: Multipart) -> i
     };
 
     if stored_embedding.is_empty() {
-        log_failed_attempt(emp_id, "no_face_data_registered", &p_path);
+        log_failed_attempt(
+            &data,
+            Some(emp_id),
+            "no_face_data_registered",
+            None,
+            station,
+            &dir,
+            &p_path,
+        )
+        .await;
+        publish_event(&data, event.outcome(false, "no_face_data_registered", None)).await;
         return HttpResponse::Ok().json(VerifyFaceResponse {
             access_granted: false,
             reason: "no_face_data_registered".to_string(),
             similarity: None,
+            next_allowed_at: None,
+            authorization_id: None,
         });
     }
 
@@ -239,33 +1050,294 @@ pub async fn verify_face(data: web::Data<AppState>, mut payload: Multipart) -> i
         }
     };
 
-    let stored_floats: Vec<f32> = stored_embedding
-        .chunks_exact(4)
-        .map(|chunk| {
-            let b: [u8; 4] = chunk.try_into().unwrap();
-            f32::from_le_bytes(b)
-        })
-        .collect();
-
-    let similarity = cosine_similarity(&new_embedding, &stored_floats);
-    let threshold = 0.95;
+    let similarity = cosine_similarity(&new_embedding, &decode_embedding(&stored_embedding));
+    let threshold = face_threshold(&data, &station_row).await;
 
     println!("Similarity: {}, Threshold: {}", similarity, threshold);
 
     if similarity > threshold {
         let _ = fs::remove_file(p_path);
+        if let Some(resp) = escort_check(
+            &data,
+            Subject::Employee(emp_id),
+            &station,
+            &dir,
+            &event,
+            Some(similarity),
+        )
+        .await
+        {
+            return resp;
+        }
+        publish_event(&data, event.outcome(true, "face_matched", Some(similarity))).await;
         HttpResponse::Ok().json(VerifyFaceResponse {
             access_granted: true,
             reason: "face_matched".to_string(),
             similarity: Some(similarity),
+            next_allowed_at: None,
+            authorization_id: None,
         })
     } else {
-        log_failed_attempt(emp_id, "face_mismatched", &p_path);
+        log_failed_attempt(
+            &data,
+            Some(emp_id),
+            "face_mismatched",
+            Some(similarity),
+            station,
+            &dir,
+            &p_path,
+        )
+        .await;
+        publish_event(
+            &data,
+            event.outcome(false, "face_mismatched", Some(similarity)),
+        )
+        .await;
         HttpResponse::Ok().json(VerifyFaceResponse {
             access_granted: false,
             reason: "face_mismatched".to_string(),
             similarity: Some(similarity),
+            next_allowed_at: None,
+            authorization_id: None,
+        })
+    }
+}
+
+/// Verification of a visitor holding a pass, by its signed code. The host's lockout and
+/// access hours apply to the visitor too. With an enrolled photo the face has to match,
+/// without one the pass is enough. Entering uses the pass up by one.
+async fn verify_visitor_face(
+    data: &web::Data<AppState>,
+    code: &str,
+    station_row: &Station,
+    p_path: String,
+) -> HttpResponse {
+    let (station, dir) = (
+        Some(station_row.name.clone()),
+        station_row.direction.clone(),
+    );
+    let mut event = AccessEvent::new("verify_face", None);
+    event.station = station.clone();
+    event.direction = Some(dir.clone());
+    let denied = |reason: &str, similarity: Option<f32>| VerifyFaceResponse {
+        access_granted: false,
+        reason: reason.to_string(),
+        similarity,
+        next_allowed_at: None,
+        authorization_id: None,
+    };
+
+    let claims = match badge::secret().map(|secret| badge::verify_pass(code, &secret, Utc::now())) {
+        Some(Ok(claims)) => claims,
+        Some(Err(reason)) => {
+            let _ = fs::remove_file(p_path);
+            publish_event(data, event.outcome(false, reason, None)).await;
+            return HttpResponse::Ok().json(denied(reason, None));
+        }
+        None => {
+            eprintln!("BADGE_SECRET is not set, can't verify visitor passes");
+            let _ = fs::remove_file(p_path);
+            publish_event(data, event.outcome(false, "invalid_pass", None)).await;
+            return HttpResponse::Ok().json(denied("invalid_pass", None));
+        }
+    };
+    let pass_id = claims.pass_id;
+    let visitor = match visitors::check_pass(
+        data,
+        pass_id,
+        Some(claims.visitor_id),
+        station.as_deref(),
+        &dir,
+    )
+    .await
+    {
+        Ok(Ok((visitor, _))) => visitor,
+        Ok(Err(reason)) => {
+            let _ = fs::remove_file(p_path);
+            publish_event(data, event.outcome(false, reason, None)).await;
+            return HttpResponse::Ok().json(denied(reason, None));
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            let _ = fs::remove_file(p_path);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "database_error"}));
+        }
+    };
+    event.employee_name = Some(format!(
+        "{} {} ({})",
+        visitor.first_name, visitor.last_name, visitor.kind
+    ));
+
+    let blocked = match lockout::check_blocked(data, visitor.id_host, &station_row.name).await {
+        Ok(None) => schedule::check_schedule(data, visitor.id_host, station.as_deref(), &dir)
+            .await
+            .map(|check| match check {
+                ScheduleCheck::Allowed => None,
+                ScheduleCheck::Outside { next_window } => {
+                    Some(("outside_access_hours", next_window))
+                }
+            }),
+        Ok(Some(reason)) => Ok(Some((reason, None))),
+        Err(e) => Err(e),
+    };
+    match blocked {
+        Ok(Some((reason, next_allowed_at))) => {
+            let _ = fs::remove_file(p_path);
+            publish_event(data, event.outcome(false, reason, None)).await;
+            return HttpResponse::Ok().json(VerifyFaceResponse {
+                next_allowed_at,
+                ..denied(reason, None)
+            });
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            let _ = fs::remove_file(p_path);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "database_error"}));
+        }
+    }
+
+    let mock =
+        !std::path::Path::new("arcface.onnx").exists() || std::env::var("MOCK_MODEL").is_ok();
+    let (reason, similarity) = if !visitor.face_enrolled {
+        ("pass_only", None)
+    } else if mock {
+        ("mock_mode_no_model", None)
+    } else {
+        let stored_embedding = match data.db.get_visitor_embedding(visitor.id_visitor).await {
+            Ok(embedding) => embedding.unwrap_or_default(),
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                let _ = fs::remove_file(p_path);
+                return HttpResponse::InternalServerError()
+                    .json(serde_json::json!({"error": "database_error"}));
+            }
+        };
+        let new_embedding = match image_processor::face_embedding(&p_path, "arcface.onnx") {
+            Ok(emb) => emb,
+            Err(e) => {
+                eprintln!("Face embedding failed: {}", e);
+                let _ = fs::remove_file(p_path);
+                return HttpResponse::InternalServerError()
+                    .json(serde_json::json!({"error": "face_processing_error"}));
+            }
+        };
+        let similarity = cosine_similarity(&new_embedding, &decode_embedding(&stored_embedding));
+        if similarity <= face_threshold(data, station_row).await {
+            log_failed_attempt(
+                data,
+                None,
+                "face_mismatched",
+                Some(similarity),
+                station,
+                &dir,
+                &p_path,
+            )
+            .await;
+            publish_event(
+                data,
+                event.outcome(false, "face_mismatched", Some(similarity)),
+            )
+            .await;
+            return HttpResponse::Ok().json(denied("face_mismatched", Some(similarity)));
+        }
+        ("face_matched", Some(similarity))
+    };
+    let _ = fs::remove_file(p_path);
+
+    // a held entry doesn't use the pass up yet, the escort's confirmation does
+    if let Some(resp) = escort_check(
+        data,
+        Subject::Visitor(pass_id),
+        &station,
+        &dir,
+        &event,
+        similarity,
+    )
+    .await
+    {
+        return resp;
+    }
+    if dir == "IN" {
+        match data.db.use_visitor_pass(pass_id).await {
+            Ok(true) => {}
+            // another station let them in with the last use meanwhile
+            Ok(false) => {
+                publish_event(data, event.outcome(false, "pass_used_up", similarity)).await;
+                return HttpResponse::Ok().json(denied("pass_used_up", similarity));
+            }
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                return HttpResponse::InternalServerError()
+                    .json(serde_json::json!({"error": "database_error"}));
+            }
+        }
+    }
+
+    publish_event(data, event.outcome(true, reason, similarity)).await;
+    HttpResponse::Ok().json(VerifyFaceResponse {
+        access_granted: true,
+        reason: reason.to_string(),
+        similarity,
+        next_allowed_at: None,
+        authorization_id: None,
+    })
+}
+
+/// Two-person rule for a face that was just verified. Holds the entry with
+/// `escort_required`, or grants it with `escort_confirmed` when this person escorts
+/// someone waiting at the station. None when it goes ahead as usual.
+async fn escort_check(
+    data: &web::Data<AppState>,
+    subject: Subject,
+    station: &Option<String>,
+    dir: &str,
+    event: &AccessEvent,
+    similarity: Option<f32>,
+) -> Option<HttpResponse> {
+    let (granted, reason, id) =
+        match escort::check_escort(data, subject, station.as_deref(), dir).await {
+            Ok(Escort::Clear) => return None,
+            Ok(Escort::Required(id)) => (false, "escort_required", id),
+            Ok(Escort::Confirmed(id)) => (true, "escort_confirmed", id),
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                return Some(
+                    HttpResponse::InternalServerError()
+                        .json(serde_json::json!({"error": "database_error"})),
+                );
+            }
+        };
+    publish_event(data, event.clone().outcome(granted, reason, similarity)).await;
+    Some(HttpResponse::Ok().json(VerifyFaceResponse {
+        access_granted: granted,
+        reason: reason.to_string(),
+        similarity,
+        next_allowed_at: None,
+        authorization_id: Some(id),
+    }))
+}
+
+fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| {
+            let b: [u8; 4] = chunk.try_into().unwrap();
+            f32::from_le_bytes(b)
         })
+        .collect()
+}
+
+/// Threshold from the config of the station the token belongs to.
+async fn face_threshold(data: &web::Data<AppState>, station: &Station) -> f32 {
+    match data.db.get_face_threshold(station.id_station).await {
+        Ok(threshold) => threshold.unwrap_or(DEFAULT_FACE_THRESHOLD),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            DEFAULT_FACE_THRESHOLD
+        }
     }
 }
 
@@ -279,19 +1351,226 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     dot_product / (magnitude_a * magnitude_b)
 }
 
+/// How far an ack's timestamp may be from server time, `ACK_MAX_SKEW_SECONDS` (default 300).
+fn ack_max_skew() -> chrono::Duration {
+    let seconds = std::env::var("ACK_MAX_SKEW_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_ACK_MAX_SKEW_SECONDS);
+    chrono::Duration::seconds(seconds)
+}
+
+fn ack_rejected(reason: &str) -> AccessAckResponse {
+    AccessAckResponse {
+        status: "rejected".to_string(),
+        reason: Some(reason.to_string()),
+        id_log: None,
+    }
+}
+
+/// Upload of events a station queued while the backend was unreachable. Events are
+/// stored one by one in the given order (oldest first) and each gets its own result;
+/// re-sending the whole batch is safe.
+pub async fn sync_station_events(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<SyncRequest>,
+) -> impl Responder {
+    let station = match authenticated_station(&data, &http_req).await {
+        Ok(station) => station,
+        Err(resp) => return resp,
+    };
+    let secret = match sync::secret() {
+        Some(secret) => secret,
+        None => return HttpResponse::ServiceUnavailable().body("STATION_SECRET is not set"),
+    };
+    if req.events.len() > sync::MAX_BATCH {
+        return HttpResponse::PayloadTooLarge()
+            .body(format!("At most {} events per batch", sync::MAX_BATCH));
+    }
+
+    let now = Utc::now();
+    let mut results = Vec::with_capacity(req.events.len());
+    for event in &req.events {
+        results
+            .push(sync::process_event(&data, &station, event, &secret, ack_max_skew(), now).await);
+    }
+
+    HttpResponse::Ok().json(SyncResponse { results })
+}
+
+/// Logs a station's decision. Stations send an `event_id` (or `Idempotency-Key` header)
+/// and reuse it when retrying, a repeated ack gets the original answer and logs nothing.
 pub async fn access_ack(
     data: web::Data<AppState>,
+    http_req: HttpRequest,
     req: web::Json<AccessAckRequest>,
 ) -> impl Responder {
+    let station = match authenticated_station(&data, &http_req).await {
+        Ok(station) => station,
+        Err(resp) => return resp,
+    };
+    let mut req = req.into_inner();
+    req.station = Some(station.name);
+    req.direction = station.direction;
+
+    let header_key = match http_req.headers().get("Idempotency-Key") {
+        Some(value) => match value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<Uuid>().ok())
+        {
+            Some(key) => Some(key),
+            None => {
+                return HttpResponse::BadRequest().json(ack_rejected("invalid_idempotency_key"))
+            }
+        },
+        None => None,
+    };
+    let event_id = req.event_id.or(header_key);
+
+    if (req.timestamp - Utc::now()).abs() > ack_max_skew() {
+        return HttpResponse::UnprocessableEntity().json(ack_rejected("timestamp_out_of_range"));
+    }
+    if let Some(id) = req.authorization_id {
+        let subject = match req.pass_id {
+            Some(pass_id) => (None, Some(pass_id)),
+            None => (Some(req.employee_id), None),
+        };
+        let station = req.station.clone().unwrap_or_default();
+        match data
+            .db
+            .complete_escort(id, station, subject.0, subject.1, event_id)
+            .await
+        {
+            Ok(Some(_)) => {}
+            Ok(None) => return HttpResponse::Conflict().json(ack_rejected("escort_not_confirmed")),
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                return HttpResponse::InternalServerError()
+                    .json(serde_json::json!({"error": "access_log_unavailable"}));
+            }
+        }
+    }
+    if let Some(pass_id) = req.pass_id {
+        return visitor_ack(&data, event_id, pass_id, &req).await;
+    }
+    // checked before logging, against the passes that came before this one
+    let passback = passback::check_passback(
+        &data,
+        req.employee_id,
+        req.station.as_deref(),
+        &req.direction,
+    )
+    .await
+    .unwrap_or_else(|e| {
+        eprintln!("Failed to check passback: {}", e);
+        None
+    });
+
     match data
         .db
-        .add_access_log(req.employee_id, req.direction.clone(), req.timestamp)
+        .add_access_log(
+            event_id,
+            req.employee_id,
+            req.direction.clone(),
+            req.station.clone(),
+            req.timestamp,
+        )
         .await
     {
-        Ok(_) => HttpResponse::Ok().json(AccessAckResponse {
-            status: "acknowledged".to_string(),
-            reason: None,
-        }),
+        Ok((original, false)) => {
+            if original.id_employee != req.employee_id
+                || original.direction != req.direction
+                || original.timestamp != req.timestamp
+            {
+                return HttpResponse::Conflict().json(ack_rejected("event_id_conflict"));
+            }
+            HttpResponse::Ok()
+                .insert_header(("Idempotent-Replayed", "true"))
+                .json(AccessAckResponse {
+                    status: "acknowledged".to_string(),
+                    reason: None,
+                    id_log: Some(original.id_log),
+                })
+        }
+        Ok((log, true)) => {
+            if std::env::var("BREAKS_FROM_STATION").is_ok() {
+                track_break_from_station(&data, req.employee_id, &req.direction).await;
+            }
+            let mut reason = "acknowledged";
+            if let Some((id_zone, _)) = passback {
+                reason = "passback";
+                if let Err(e) = data.db.add_passback_violation(log.id_log, id_zone).await {
+                    eprintln!("Failed to record passback violation: {}", e);
+                }
+            }
+            let mut event = AccessEvent::new("access_ack", Some(req.employee_id));
+            event.station = req.station.clone();
+            event.direction = Some(req.direction.clone());
+            event.timestamp = req.timestamp;
+            publish_event(&data, event.outcome(true, reason, None)).await;
+            HttpResponse::Ok().json(AccessAckResponse {
+                status: "acknowledged".to_string(),
+                reason: None,
+                id_log: Some(log.id_log),
+            })
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "access_log_unavailable"}))
+        }
+    }
+}
+
+/// Ack of a visitor's pass, logged to `visitor_logs` with the same replay rules.
+async fn visitor_ack(
+    data: &web::Data<AppState>,
+    event_id: Option<Uuid>,
+    pass_id: i32,
+    req: &AccessAckRequest,
+) -> HttpResponse {
+    match data
+        .db
+        .add_visitor_log(
+            event_id,
+            pass_id,
+            req.direction.clone(),
+            req.station.clone(),
+            req.timestamp,
+        )
+        .await
+    {
+        Ok(Some((original, false))) => {
+            if original.id_pass != pass_id
+                || original.direction != req.direction
+                || original.timestamp != req.timestamp
+            {
+                return HttpResponse::Conflict().json(ack_rejected("event_id_conflict"));
+            }
+            HttpResponse::Ok()
+                .insert_header(("Idempotent-Replayed", "true"))
+                .json(AccessAckResponse {
+                    status: "acknowledged".to_string(),
+                    reason: None,
+                    id_log: Some(original.id_log),
+                })
+        }
+        Ok(Some((log, true))) => {
+            let mut event = AccessEvent::new("access_ack", None);
+            event.station = req.station.clone();
+            event.direction = Some(req.direction.clone());
+            event.timestamp = req.timestamp;
+            publish_event(data, event.outcome(true, "acknowledged", None)).await;
+            HttpResponse::Ok().json(AccessAckResponse {
+                status: "acknowledged".to_string(),
+                reason: None,
+                id_log: Some(log.id_log),
+            })
+        }
+        Ok(None) => HttpResponse::NotFound().json(ack_rejected("unknown_pass")),
         Err(e) => {
             eprintln!("Database error: {}", e);
             HttpResponse::InternalServerError()
@@ -300,12 +1579,73 @@ pub async fn access_ack(
     }
 }
 
+/// With BREAKS_FROM_STATION set, going OUT during an open shift starts a break and
+/// coming back IN ends it. Employees without an open shift are left alone.
+async fn track_break_from_station(data: &web::Data<AppState>, employee_id: i32, direction: &str) {
+    let result = match direction {
+        "OUT" => data.db.start_break(employee_id).await,
+        "IN" => data.db.end_break(employee_id).await,
+        _ => return,
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to track break: {}", e);
+    }
+}
+
+/// Sends an event to connected dashboards, looking up the employee name only when
+/// someone is actually listening.
+async fn publish_event(data: &web::Data<AppState>, mut event: AccessEvent) {
+    if !data.events.has_listeners() {
+        return;
+    }
+    if let (Some(id), None) = (event.employee_id, &event.employee_name) {
+        if let Ok(Some((_, first_name, last_name))) = data.db.get_employee_by_id(id).await {
+            event.employee_name = Some(format!("{} {}", first_name, last_name));
+        }
+    }
+    data.events.publish(event);
+}
+
+pub async fn event_stream(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    let token = stations::bearer_token(&http_req).or_else(|| query_token(&http_req));
+    let actor = match session_actor(&data, token).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    if let Err(resp) = check_role(&data, actor, SECURITY_ROLES).await {
+        return resp;
+    }
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(data.events.sse_stream())
+}
+
+/// The employee as the audit log sees them, None if they don't exist (or can't be read).
+async fn employee_snapshot(data: &web::Data<AppState>, id: i32) -> Option<serde_json::Value> {
+    match data.db.get_employee(id).await {
+        Ok(employee) => employee.and_then(|e| serde_json::to_value(e).ok()),
+        Err(e) => {
+            eprintln!("Failed to read employee {} for the audit log: {}", id, e);
+            None
+        }
+    }
+}
+
 pub async fn create_employee(
     data: web::Data<AppState>,
+    http_req: HttpRequest,
     req: web::Json<CreateEmployeeRequest>,
 ) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
     match data.db.create_employee(req.into_inner()).await {
         Ok(id) => {
+            let after = employee_snapshot(&data, id).await;
+            let entry = NewAuditEntry::new(&http_req, actor, "create", "employee", Some(id));
+            audit::record(&data, entry.changes(None, after)).await;
             HttpResponse::Ok().json(serde_json::json!({"status": "success", "id_person": id}))
         }
         Err(e) => {
@@ -317,15 +1657,34 @@ pub async fn create_employee(
 
 pub async fn update_employee(
     data: web::Data<AppState>,
+    http_req: HttpRequest,
     path: web::Path<i32>,
     req: web::Json<UpdateEmployeeRequest>,
 ) -> impl Responder {
-    match data
-        .db
-        .update_employee(path.into_inner(), req.into_inner())
-        .await
-    {
-        Ok(_) => HttpResponse::Ok().body("Employee updated"),
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let id = path.into_inner();
+    let mut req = req.into_inner();
+    let password_changed = req.password.is_some();
+    req.password = req.password.map(|password| auth::hash_password(&password));
+    let before = employee_snapshot(&data, id).await;
+
+    match data.db.update_employee(id, req).await {
+        Ok(_) => {
+            if before.is_some() {
+                let after = employee_snapshot(&data, id).await.map(|mut after| {
+                    if password_changed {
+                        after["password"] = audit::REDACTED.into();
+                    }
+                    after
+                });
+                let entry = NewAuditEntry::new(&http_req, actor, "update", "employee", Some(id));
+                audit::record(&data, entry.changes(before, after)).await;
+            }
+            HttpResponse::Ok().body("Employee updated")
+        }
         Err(e) => {
             eprintln!("Failed to update employee: {}", e);
             HttpResponse::InternalServerError().body("Failed to update employee")
@@ -333,25 +1692,67 @@ pub async fn update_employee(
     }
 }
 
-pub async fn delete_employee(data: web::Data<AppState>, path: web::Path<i32>) -> impl Responder {
-    match data.db.delete_employee(path.into_inner()).await {
+/// Archives instead of deleting, hours and access logs have to outlive the person.
+pub async fn archive_employee(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let id = path.into_inner();
+    let before = employee_snapshot(&data, id).await;
+
+    match data.db.archive_employee(id, actor, Utc::now()).await {
         Ok(count) => {
             if count > 0 {
-                HttpResponse::Ok().body("Employee deleted")
+                // the face embedding is already gone, the enrollment photo goes with it
+                if let Some(photo_path) = before.as_ref().and_then(|b| b["photo_path"].as_str()) {
+                    if let Err(e) = fs::remove_file(photo_path) {
+                        eprintln!("Failed to delete {}: {}", photo_path, e);
+                    }
+                }
+                let after = employee_snapshot(&data, id).await;
+                let entry = NewAuditEntry::new(&http_req, actor, "archive", "employee", Some(id));
+                audit::record(&data, entry.changes(before, after)).await;
+                HttpResponse::Ok().body("Employee archived")
             } else {
-                HttpResponse::NotFound().body("Employee not found")
+                HttpResponse::NotFound().body("Employee not found or already archived")
             }
         }
         Err(e) => {
-            eprintln!("Failed to delete employee: {}", e);
-            HttpResponse::InternalServerError().body("Failed to delete employee")
+            eprintln!("Failed to archive employee: {}", e);
+            HttpResponse::InternalServerError().body("Failed to archive employee")
         }
     }
 }
 
-pub async fn get_employees(data: web::Data<AppState>) -> impl Responder {
-    match data.db.get_employees().await {
-        Ok(employees) => HttpResponse::Ok().json(employees),
+/// Face data, badges and TOTP were revoked on archiving and have to be enrolled again.
+pub async fn restore_employee(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let id = path.into_inner();
+    let before = employee_snapshot(&data, id).await;
+
+    match data.db.restore_employee(id).await {
+        Ok(count) => {
+            if count > 0 {
+                let after = employee_snapshot(&data, id).await;
+                let entry = NewAuditEntry::new(&http_req, actor, "restore", "employee", Some(id));
+                audit::record(&data, entry.changes(before, after)).await;
+                HttpResponse::Ok().body("Employee restored")
+            } else {
+                HttpResponse::NotFound().body("Employee not found or not archived")
+            }
+        }
         Err(e) => {
             eprintln!("Database error: {}", e);
             HttpResponse::InternalServerError().body("Database error")
@@ -359,30 +1760,165 @@ pub async fn get_employees(data: web::Data<AppState>) -> impl Responder {
     }
 }
 
-pub async fn get_work_hours(data: web::Data<AppState>) -> impl Responder {
-    match data.db.get_work_hours().await {
-        Ok(hours) => HttpResponse::Ok().json(hours),
+/// Subject access export, everything stored about one employee as a ZIP of JSON files
+/// and the images they reference.
+pub async fn export_employee(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    let id = path.into_inner();
+
+    let records = match data.db.get_subject_records(id).await {
+        Ok(records) => records,
         Err(e) => {
             eprintln!("Database error: {}", e);
-            HttpResponse::InternalServerError().body("Database error")
+            return HttpResponse::InternalServerError().body("Database error");
         }
+    };
+    let known = records
+        .iter()
+        .any(|(name, rows)| name == "employee" && rows.as_array().is_some_and(|r| !r.is_empty()));
+    if !known {
+        return HttpResponse::NotFound().body("Employee not found");
     }
-}
 
-pub async fn start_shift(
-    data: web::Data<AppState>,
-    req: web::Json<EmployeeIdRequest>,
-) -> impl Responder {
-    match data.db.start_shift(req.id_employee).await {
-        Ok(_) => HttpResponse::Ok().body("Shift started"),
+    let images: Vec<_> = gdpr::image_files(&records)
+        .into_iter()
+        .filter_map(|(name, path)| match fs::read(&path) {
+            Ok(bytes) => Some((name, bytes)),
+            Err(e) => {
+                eprintln!("Skipping {} in export: {}", path, e);
+                None
+            }
+        })
+        .collect();
+
+    match gdpr::export_zip(&records, &images) {
+        Ok(zip) => {
+            let entry = NewAuditEntry::new(&http_req, actor, "export", "employee", Some(id));
+            audit::record(&data, entry).await;
+            HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header((
+                    "Content-Disposition",
+                    format!("attachment; filename=\"employee_{}_export.zip\"", id),
+                ))
+                .body(zip)
+        }
         Err(e) => {
-            eprintln!("Database error: {}", e);
-            HttpResponse::InternalServerError().body("Failed to start shift")
+            eprintln!("Failed to build export: {}", e);
+            HttpResponse::InternalServerError().body("Failed to build export")
         }
     }
 }
 
-pub async fn end_shift(
+/// Right to erasure for an archived employee. Face data, photos, failed attempt frames
+/// and credentials go, hours and access logs stay with the name for payroll.
+pub async fn erase_employee(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    let id = path.into_inner();
+
+    match data.db.get_employee(id).await {
+        Ok(Some(employee)) if employee.archived_at.is_some() => {}
+        Ok(Some(_)) => return HttpResponse::Conflict().body("Archive the employee first"),
+        Ok(None) => return HttpResponse::NotFound().body("Employee not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
+    match data.db.erase_employee(id, actor, Utc::now()).await {
+        Ok(Some(files)) => {
+            // frame paths can come from /api/log_error, only the archive is ours to delete
+            let paths: Vec<String> = files
+                .photo_path
+                .into_iter()
+                .chain(
+                    files
+                        .frames
+                        .into_iter()
+                        .filter(|p| is_archived_attempt_photo(p)),
+                )
+                .collect();
+            let left = gdpr::remove_files(&paths);
+            // no snapshots, they would keep what was just erased
+            let entry = NewAuditEntry::new(&http_req, actor, "erase", "employee", Some(id));
+            audit::record(&data, entry).await;
+            if left.is_empty() {
+                HttpResponse::Ok().body("Employee data erased")
+            } else {
+                // the records are gone already, so a retry wouldn't find these again
+                HttpResponse::InternalServerError().body(format!(
+                    "Employee records erased, but these files could not be deleted: {}",
+                    left.join(", ")
+                ))
+            }
+        }
+        Ok(None) => HttpResponse::Conflict().body("Archive the employee first"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_employees(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<EmployeesQuery>,
+) -> impl Responder {
+    // former staff are kept for history, not for anyone to list
+    if query.include_archived {
+        if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+            return resp;
+        }
+    }
+    match data.db.get_employees(query.include_archived).await {
+        Ok(employees) => HttpResponse::Ok().json(employees),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_work_hours(data: web::Data<AppState>) -> impl Responder {
+    match data.db.get_work_hours().await {
+        Ok(hours) => HttpResponse::Ok().json(hours),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn start_shift(
+    data: web::Data<AppState>,
+    req: web::Json<EmployeeIdRequest>,
+) -> impl Responder {
+    match data.db.start_shift(req.id_employee).await {
+        Ok(_) => HttpResponse::Ok().body("Shift started"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to start shift")
+        }
+    }
+}
+
+pub async fn end_shift(
     data: web::Data<AppState>,
     req: web::Json<EmployeeIdRequest>,
 ) -> impl Responder {
@@ -399,130 +1935,4194 @@ pub async fn end_shift(
             HttpResponse::InternalServerError().body("Failed to end shift")
         }
     }
-}
+}
+
+pub async fn start_break(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<EmployeeIdRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_station_or_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.start_break(req.id_employee).await {
+        Ok(count) => {
+            if count > 0 {
+                HttpResponse::Ok().body("Break started")
+            } else {
+                HttpResponse::BadRequest().body("No active shift or break already started")
+            }
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to start break")
+        }
+    }
+}
+
+pub async fn end_break(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<EmployeeIdRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_station_or_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.end_break(req.id_employee).await {
+        Ok(count) => {
+            if count > 0 {
+                HttpResponse::Ok().body("Break ended")
+            } else {
+                HttpResponse::BadRequest().body("No active break found")
+            }
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to end break")
+        }
+    }
+}
+
+pub async fn get_work_hours_report(data: web::Data<AppState>) -> impl Responder {
+    let hours = data.db.get_work_hours().await;
+    let breaks = data.db.get_breaks().await;
+    let rules = data.db.get_break_rules().await;
+
+    match (hours, breaks, rules) {
+        (Ok(hours), Ok(breaks), Ok(rules)) => {
+            HttpResponse::Ok().json(work_time::build_report(&hours, &breaks, &rules))
+        }
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_break_rules(data: web::Data<AppState>) -> impl Responder {
+    match data.db.get_break_rules().await {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn set_break_rules(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<Vec<BreakRule>>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    let rules = req.into_inner();
+
+    if rules
+        .iter()
+        .any(|r| r.min_shift_minutes <= 0 || r.required_break_minutes < 0)
+    {
+        return HttpResponse::BadRequest()
+            .body("min_shift_minutes must be positive and required_break_minutes not negative");
+    }
+    let mut thresholds: Vec<i32> = rules.iter().map(|r| r.min_shift_minutes).collect();
+    thresholds.sort_unstable();
+    thresholds.dedup();
+    if thresholds.len() != rules.len() {
+        return HttpResponse::BadRequest().body("Duplicate min_shift_minutes");
+    }
+
+    match data.db.set_break_rules(rules).await {
+        Ok(_) => HttpResponse::Ok().body("Break rules updated"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to update break rules")
+        }
+    }
+}
+
+fn validate_work_hours(
+    reason: &str,
+    time_start: DateTime<Utc>,
+    time_end: Option<DateTime<Utc>>,
+) -> Result<(), HttpResponse> {
+    if reason.trim().is_empty() {
+        return Err(HttpResponse::BadRequest().body("Reason is required"));
+    }
+    if let Some(end) = time_end {
+        if end <= time_start {
+            return Err(HttpResponse::BadRequest().body("time_end must be after time_start"));
+        }
+    }
+    Ok(())
+}
+
+pub async fn create_work_hours(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<CreateWorkHoursRequest>,
+) -> impl Responder {
+    let changed_by = match require_role(&data, &http_req, SECURITY_ROLES).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let req = req.into_inner();
+
+    if let Err(resp) = validate_work_hours(&req.reason, req.time_start, req.time_end) {
+        return resp;
+    }
+
+    match data.db.get_employee_by_id(req.id_employee).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("Employee not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
+    match data
+        .db
+        .create_work_hours(
+            req.id_employee,
+            req.time_start,
+            req.time_end,
+            changed_by,
+            req.reason.trim().to_string(),
+        )
+        .await
+    {
+        Ok(Some(id)) => {
+            HttpResponse::Ok().json(serde_json::json!({"status": "success", "id_record": id}))
+        }
+        Ok(None) => HttpResponse::Conflict().body("Overlaps an existing work hours record"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to create work hours")
+        }
+    }
+}
+
+pub async fn update_work_hours(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<UpdateWorkHoursRequest>,
+) -> impl Responder {
+    let changed_by = match require_role(&data, &http_req, SECURITY_ROLES).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let id = path.into_inner();
+    let req = req.into_inner();
+
+    let existing = match data.db.get_work_hours_record(id).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return HttpResponse::NotFound().body("Work hours record not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    let time_start = req.time_start.unwrap_or(existing.time_start);
+    let time_end = req.time_end.unwrap_or(existing.time_end);
+
+    if let Err(resp) = validate_work_hours(&req.reason, time_start, time_end) {
+        return resp;
+    }
+
+    match data
+        .db
+        .update_work_hours(
+            id,
+            time_start,
+            time_end,
+            changed_by,
+            req.reason.trim().to_string(),
+        )
+        .await
+    {
+        Ok(Some(count)) if count > 0 => HttpResponse::Ok().body("Work hours updated"),
+        Ok(Some(_)) => HttpResponse::NotFound().body("Work hours record not found"),
+        Ok(None) => HttpResponse::Conflict().body("Overlaps an existing work hours record"),
+        Err(e) => {
+            eprintln!("Failed to update work hours: {}", e);
+            HttpResponse::InternalServerError().body("Failed to update work hours")
+        }
+    }
+}
+
+pub async fn delete_work_hours(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<DeleteWorkHoursRequest>,
+) -> impl Responder {
+    let changed_by = match require_role(&data, &http_req, SECURITY_ROLES).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    if req.reason.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Reason is required");
+    }
+
+    match data
+        .db
+        .delete_work_hours(path.into_inner(), changed_by, req.reason.trim().to_string())
+        .await
+    {
+        Ok(count) if count > 0 => HttpResponse::Ok().body("Work hours deleted"),
+        Ok(_) => HttpResponse::NotFound().body("Work hours record not found"),
+        Err(e) => {
+            eprintln!("Failed to delete work hours: {}", e);
+            HttpResponse::InternalServerError().body("Failed to delete work hours")
+        }
+    }
+}
+
+pub async fn get_work_hours_history(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_work_hours_history(path.into_inner()).await {
+        Ok(history) => HttpResponse::Ok().json(history),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_failed_attempts(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<FailedAttemptQuery>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    let filter = query.into_inner();
+
+    if !matches!(
+        filter.sort_by.as_deref(),
+        None | Some("timestamp" | "employee" | "reason" | "similarity" | "station")
+    ) {
+        return HttpResponse::BadRequest().body("Invalid sort_by");
+    }
+    if !matches!(filter.order.as_deref(), None | Some("asc" | "desc")) {
+        return HttpResponse::BadRequest().body("Invalid order");
+    }
+
+    let page = filter.page.unwrap_or(1).max(1);
+    let page_size = filter.page_size.unwrap_or(50).clamp(1, 500);
+    if (page - 1).checked_mul(page_size).is_none() {
+        return HttpResponse::BadRequest().body("Invalid page");
+    }
+
+    match data.db.get_failed_attempts(filter, page, page_size).await {
+        Ok((items, total)) => HttpResponse::Ok().json(FailedAttemptPage {
+            items,
+            total,
+            page,
+            page_size,
+        }),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_failed_attempt_image(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+
+    let image_path = match data.db.get_failed_attempt(path.into_inner()).await {
+        Ok(Some(attempt)) => attempt.image_path,
+        Ok(None) => return HttpResponse::NotFound().body("Failed attempt not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    // image paths can come from /api/log_error, never serve anything outside the archive
+    let image_path = match image_path.filter(|p| is_archived_attempt_photo(p)) {
+        Some(p) => p,
+        None => return HttpResponse::NotFound().body("No image for this attempt"),
+    };
+
+    match fs::read(&image_path) {
+        Ok(bytes) => HttpResponse::Ok().content_type("image/jpeg").body(bytes),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", image_path, e);
+            HttpResponse::NotFound().body("No image for this attempt")
+        }
+    }
+}
+
+pub async fn get_retention_rules(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    match data.db.get_retention_rules().await {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn set_retention_rules(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<Vec<RetentionRule>>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    let rules = req.into_inner();
+
+    if rules
+        .iter()
+        .any(|r| r.reason.trim().is_empty() || r.retention_days < 0)
+    {
+        return HttpResponse::BadRequest()
+            .body("reason can't be empty and retention_days can't be negative");
+    }
+
+    match data.db.set_retention_rules(rules).await {
+        Ok(_) => HttpResponse::Ok().body("Retention rules updated"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to update retention rules")
+        }
+    }
+}
+
+pub async fn purge_dry_run(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    match retention::purge_expired_photos(data.db.as_ref(), true).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_lockouts(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_active_lockouts(Utc::now()).await {
+        Ok(lockouts) => HttpResponse::Ok().json(lockouts),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Lifts active lockouts of an employee and/or a station before they run out.
+/// Failures from before the unlock no longer count towards the next lockout.
+pub async fn unlock(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<UnlockRequest>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+
+    let mut subjects = Vec::new();
+    if let Some(employee_id) = req.employee_id {
+        subjects.push((lockout::EMPLOYEE, employee_id.to_string()));
+    }
+    if let Some(station) = req.station.as_deref().filter(|s| !s.trim().is_empty()) {
+        subjects.push((lockout::STATION, station.trim().to_string()));
+    }
+    if subjects.is_empty() {
+        return HttpResponse::BadRequest().body("employee_id or station is required");
+    }
+
+    let now = Utc::now();
+    let mut unlocked = 0;
+    for (subject_type, subject) in subjects {
+        match data
+            .db
+            .unlock(subject_type.to_string(), subject, actor, now)
+            .await
+        {
+            Ok(count) => unlocked += count,
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to unlock");
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "unlocked": unlocked }))
+}
+
+pub async fn issue_badge(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<IssueBadgeRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    let employee_id = path.into_inner();
+    let valid_days = req.valid_days.unwrap_or(DEFAULT_BADGE_VALID_DAYS);
+    if !(1..=MAX_BADGE_VALID_DAYS).contains(&valid_days) {
+        return HttpResponse::BadRequest().body(format!(
+            "valid_days must be between 1 and {}",
+            MAX_BADGE_VALID_DAYS
+        ));
+    }
+    let secret = match badge::secret() {
+        Some(secret) => secret,
+        None => return HttpResponse::InternalServerError().body("BADGE_SECRET is not set"),
+    };
+
+    match data.db.get_employee_by_id(employee_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("Employee not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
+    // whole seconds, the code carries unix seconds
+    let issued_at = Utc::now().trunc_subsecs(0);
+    let expires_at = issued_at + chrono::Duration::days(valid_days);
+
+    match data
+        .db
+        .create_badge(employee_id, issued_at, expires_at)
+        .await
+    {
+        Ok(badge) => {
+            let code = badge::sign(
+                &BadgeClaims {
+                    employee_id,
+                    badge_id: badge.id_badge,
+                    issued_at,
+                    expires_at,
+                },
+                &secret,
+            );
+            HttpResponse::Created().json(IssuedBadge { badge, code })
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to issue badge")
+        }
+    }
+}
+
+pub async fn get_badges(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_badges(path.into_inner()).await {
+        Ok(badges) => HttpResponse::Ok().json(badges),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// QR of the employee's badge as PNG or SVG, or a printable PDF card with name and photo.
+/// Uses the newest active badge unless `badge_id` is given; without any active badge the
+/// plain id is encoded, as long as plain ids are still accepted.
+pub async fn get_badge_image(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    query: web::Query<BadgeImageQuery>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    let employee_id = path.into_inner();
+    let format = query.format.as_deref().unwrap_or("png");
+    if !matches!(format, "png" | "svg" | "pdf") {
+        return HttpResponse::BadRequest().body("format must be png, svg or pdf");
+    }
+
+    let employee = match data.db.get_employee(employee_id).await {
+        Ok(Some(employee)) => employee,
+        Ok(None) => return HttpResponse::NotFound().body("Employee not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+
+    let badges = match data.db.get_badges(employee_id).await {
+        Ok(badges) => badges,
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+    let now = Utc::now();
+    let active = badges.into_iter().find(|b| {
+        b.revoked_at.is_none()
+            && b.expires_at > now
+            && query.badge_id.is_none_or(|id| id == b.id_badge)
+    });
+
+    let content = match (active, query.badge_id) {
+        (Some(b), _) => match badge::secret() {
+            Some(secret) => badge::sign(
+                &BadgeClaims {
+                    employee_id,
+                    badge_id: b.id_badge,
+                    issued_at: b.issued_at,
+                    expires_at: b.expires_at,
+                },
+                &secret,
+            ),
+            None => return HttpResponse::InternalServerError().body("BADGE_SECRET is not set"),
+        },
+        (None, Some(_)) => return HttpResponse::NotFound().body("No such active badge"),
+        (None, None) if !data.allow_plain_badges => {
+            return HttpResponse::NotFound().body("No active badge, issue one first")
+        }
+        (None, None) => employee_id.to_string(),
+    };
+
+    let rendered = match format {
+        "svg" => badge_card::qr_svg(&content).map(|svg| ("image/svg+xml", svg.into_bytes())),
+        "pdf" => {
+            let photo = employee
+                .photo_path
+                .as_deref()
+                .and_then(|path| image::open(path).ok());
+            let name = format!("{} {}", employee.first_name, employee.last_name);
+            badge_card::card_pdf(&name, employee_id, photo.as_ref(), &content)
+                .map(|pdf| ("application/pdf", pdf))
+        }
+        _ => badge_card::qr_png(&content).map(|png| ("image/png", png)),
+    };
+
+    match rendered {
+        Ok((content_type, body)) => {
+            let mut resp = HttpResponse::Ok();
+            resp.content_type(content_type);
+            if format == "pdf" {
+                resp.insert_header((
+                    "Content-Disposition",
+                    format!("attachment; filename=\"badge_{}.pdf\"", employee_id),
+                ));
+            }
+            resp.body(body)
+        }
+        Err(e) => {
+            eprintln!("Failed to render badge: {}", e);
+            HttpResponse::InternalServerError().body("Failed to render badge")
+        }
+    }
+}
+
+/// Creates (or replaces) the secret for rotating QR codes. The secret is only returned
+/// here, the phone app has to store it. Only admins and managers enroll phones, a
+/// session of the employee alone would let whoever holds it take over the badge.
+pub async fn enroll_totp(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let employee_id = path.into_inner();
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+
+    match data.db.get_employee_by_id(employee_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("Employee not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    }
+
+    let secret = totp::generate_secret();
+    match data.db.set_totp_secret(employee_id, secret.clone()).await {
+        Ok(_) => HttpResponse::Created().json(TotpEnrollment {
+            employee_id,
+            secret: totp::encode_secret(&secret),
+            provisioning_uri: totp::provisioning_uri(&secret, employee_id),
+            period: totp::PERIOD_SECONDS,
+            digits: totp::DIGITS,
+            qr_format: format!("CT1.{}.{{code}}", employee_id),
+        }),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to enroll")
+        }
+    }
+}
+
+pub async fn delete_totp(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let employee_id = path.into_inner();
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.delete_totp(employee_id).await {
+        Ok(0) => HttpResponse::NotFound().body("Not enrolled"),
+        Ok(_) => HttpResponse::Ok().body("Phone badge removed"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// For lost badges. The code stops working at once, the employee needs a new one.
+pub async fn revoke_badge(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, SECURITY_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    match data
+        .db
+        .revoke_badge(path.into_inner(), actor, Utc::now())
+        .await
+    {
+        Ok(0) => HttpResponse::NotFound().body("Badge not found or already revoked"),
+        Ok(_) => HttpResponse::Ok().body("Badge revoked"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to revoke badge")
+        }
+    }
+}
+
+/// Archives the frame of a failed verification and records the attempt.
+async fn log_failed_attempt(
+    data: &web::Data<AppState>,
+    employee_id: Option<i32>,
+    reason: &str,
+    similarity: Option<f32>,
+    station: Option<String>,
+    direction: &str,
+    temp_photo_path: &str,
+) {
+    let image_path = archive_failed_attempt_photo(temp_photo_path);
+
+    let attempt = NewFailedAttempt {
+        id_employee: employee_id,
+        reason: reason.to_string(),
+        similarity,
+        station,
+        direction: Some(direction.to_string()),
+        image_path,
+        timestamp: Utc::now(),
+    };
+
+    let station = attempt.station.clone();
+    if let Err(e) = data.db.add_failed_attempt(attempt).await {
+        eprintln!("Failed to log failed attempt: {}", e);
+        return;
+    }
+    if let Err(e) =
+        lockout::register_failure(data, employee_id, station.as_deref().unwrap_or_default()).await
+    {
+        eprintln!("Failed to update lockouts: {}", e);
+    }
+}
+
+fn archive_failed_attempt_photo(temp_photo_path: &str) -> Option<String> {
+    if let Err(e) = fs::create_dir_all("uploads/failed_attempts") {
+        eprintln!("Failed to create directory: {}", e);
+        let _ = fs::remove_file(temp_photo_path);
+        return None;
+    }
+
+    let filename = std::path::Path::new(temp_photo_path)
+        .file_name()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default();
+    let new_path = format!("uploads/failed_attempts/{}", filename);
+
+    if fs::rename(temp_photo_path, &new_path).is_err() {
+        // Fallback copy, /tmp is often a different filesystem
+        if let Err(e) = fs::copy(temp_photo_path, &new_path) {
+            eprintln!("Failed to copy failed attempt photo: {}", e);
+            let _ = fs::remove_file(temp_photo_path);
+            return None;
+        }
+        let _ = fs::remove_file(temp_photo_path);
+    }
+
+    Some(new_path)
+}
+
+/// Registers a station and returns its token, which it sends as `Authorization: Bearer`.
+pub async fn register_station(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<RegisterStationRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    let req = req.into_inner();
+    if req.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("name is required");
+    }
+    if req.direction != "IN" && req.direction != "OUT" {
+        return HttpResponse::BadRequest().body("direction must be IN or OUT");
+    }
+
+    let token = stations::generate_token();
+    match data
+        .db
+        .create_station(req, stations::hash_token(&token))
+        .await
+    {
+        Ok(Some(station)) => HttpResponse::Created().json(StationCredentials { station, token }),
+        Ok(None) => HttpResponse::Conflict().body("Station name already registered"),
+        Err(e) if foreign_key_violation(&e) => HttpResponse::BadRequest().body("Door not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to register station")
+        }
+    }
+}
+
+/// Replaces the token of a station, the old one stops working right away.
+pub async fn reissue_station_credentials(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+
+    let token = stations::generate_token();
+    match data
+        .db
+        .set_station_token(path.into_inner(), stations::hash_token(&token))
+        .await
+    {
+        Ok(Some(station)) => HttpResponse::Ok().json(StationCredentials { station, token }),
+        Ok(None) => HttpResponse::NotFound().body("Station not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn station_heartbeat(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<HeartbeatRequest>,
+) -> impl Responder {
+    let token = match stations::bearer_token(&http_req) {
+        Some(token) => token,
+        None => return HttpResponse::Unauthorized().body("Missing station token"),
+    };
+
+    match data
+        .db
+        .station_heartbeat(stations::hash_token(&token), req.into_inner(), Utc::now())
+        .await
+    {
+        Ok(Some(mut station)) => {
+            station.online = true;
+            // how stations learn about a lockdown or evacuation
+            match modes::current_mode(&data, Some(&station.name)).await {
+                Ok(mode) => station.mode = Some(mode.as_str().to_string()),
+                Err(e) => eprintln!("Database error: {}", e),
+            }
+            HttpResponse::Ok().json(station)
+        }
+        Ok(None) => HttpResponse::Unauthorized().body("Unknown station token"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_stations(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_stations().await {
+        Ok(mut list) => {
+            let now = Utc::now();
+            for station in &mut list {
+                station.online = stations::is_online(station.last_seen_at, now);
+            }
+            HttpResponse::Ok().json(list)
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+fn config_etag(config: &StationConfig) -> String {
+    format!("\"{}\"", config.version)
+}
+
+/// Whether an `If-None-Match` / `If-Match` header lists `etag` (or is `*`).
+fn etag_matches(http_req: &HttpRequest, header: &str, etag: &str) -> Option<bool> {
+    let value = http_req.headers().get(header)?.to_str().ok()?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag),
+    )
+}
+
+/// Config of a station, readable by the station itself (with its token) and by security staff.
+/// Answers 304 while the station's `If-None-Match` is still current.
+pub async fn get_station_config(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let id = path.into_inner();
+    // the station's own token, otherwise an admin panel session
+    let station = match stations::bearer_token(&http_req) {
+        Some(token) => match data
+            .db
+            .get_station_by_token(stations::hash_token(&token))
+            .await
+        {
+            Ok(station) => station,
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                return HttpResponse::InternalServerError().body("Database error");
+            }
+        },
+        None => None,
+    };
+    match station {
+        Some(station) if station.id_station == id => {}
+        Some(_) => return HttpResponse::Forbidden().body("Not allowed"),
+        None => {
+            if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+                return resp;
+            }
+        }
+    }
+
+    match data.db.get_station_config(id).await {
+        Ok(Some(config)) => {
+            let etag = config_etag(&config);
+            if etag_matches(&http_req, "If-None-Match", &etag) == Some(true) {
+                return HttpResponse::NotModified()
+                    .insert_header(("ETag", etag))
+                    .finish();
+            }
+            HttpResponse::Ok()
+                .insert_header(("ETag", etag))
+                .json(config)
+        }
+        Ok(None) => HttpResponse::NotFound().body("Station not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+fn validate_station_config(config: &StationConfig) -> Result<(), String> {
+    if config.direction != "IN" && config.direction != "OUT" {
+        return Err("direction must be IN or OUT".to_string());
+    }
+    let ranges = [
+        ("fps", config.fps, 1, 60),
+        ("countdown_seconds", config.countdown_seconds, 0, 10),
+        (
+            "request_timeout_seconds",
+            config.request_timeout_seconds,
+            1,
+            60,
+        ),
+        ("message_seconds", config.message_seconds, 1, 30),
+    ];
+    for (name, value, min, max) in ranges {
+        if !(min..=max).contains(&value) {
+            return Err(format!("{} must be between {} and {}", name, min, max));
+        }
+    }
+    if !(config.face_threshold > 0.0 && config.face_threshold < 1.0) {
+        return Err("face_threshold must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+/// Changes a station's config, it picks the new version up with its next heartbeat.
+/// With `If-Match` the change is refused (412) if someone else changed the config first.
+pub async fn update_station_config(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<StationConfigUpdate>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+
+    let mut config = match data.db.get_station_config(path.into_inner()).await {
+        Ok(Some(config)) => config,
+        Ok(None) => return HttpResponse::NotFound().body("Station not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+    if etag_matches(&http_req, "If-Match", &config_etag(&config)) == Some(false) {
+        return HttpResponse::PreconditionFailed().body("Config was changed in the meantime");
+    }
+
+    let req = req.into_inner();
+    config.direction = req.direction.unwrap_or(config.direction);
+    config.fps = req.fps.unwrap_or(config.fps);
+    config.countdown_seconds = req.countdown_seconds.unwrap_or(config.countdown_seconds);
+    config.request_timeout_seconds = req
+        .request_timeout_seconds
+        .unwrap_or(config.request_timeout_seconds);
+    config.message_seconds = req.message_seconds.unwrap_or(config.message_seconds);
+    config.face_threshold = req.face_threshold.unwrap_or(config.face_threshold);
+    if let Err(msg) = validate_station_config(&config) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+
+    match data.db.update_station_config(config).await {
+        Ok(Some(config)) => HttpResponse::Ok()
+            .insert_header(("ETag", config_etag(&config)))
+            .json(config),
+        Ok(None) => HttpResponse::PreconditionFailed().body("Config was changed in the meantime"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Insert pointing at a row that doesn't exist (door, zone, site or employee).
+fn foreign_key_violation(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::Database(db) if db.is_foreign_key_violation())
+}
+
+/// Binds a station to a door (or unbinds it with `null`). From then on only employees
+/// allowed into the door's zone get through there.
+pub async fn set_station_door(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<StationDoorRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    match data
+        .db
+        .set_station_door(path.into_inner(), req.id_door)
+        .await
+    {
+        Ok(Some(station)) => HttpResponse::Ok().json(station),
+        Ok(None) => HttpResponse::NotFound().body("Station not found"),
+        Err(e) if foreign_key_violation(&e) => HttpResponse::BadRequest().body("Door not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_sites(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_sites().await {
+        Ok(sites) => HttpResponse::Ok().json(sites),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn create_site(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<CreateSiteRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    if req.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("name is required");
+    }
+    match data.db.create_site(req.into_inner()).await {
+        Ok(Some(site)) => HttpResponse::Created().json(site),
+        Ok(None) => HttpResponse::Conflict().body("Site already exists"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn create_zone(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<NameRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    if req.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("name is required");
+    }
+    match data
+        .db
+        .create_zone(path.into_inner(), req.into_inner().name)
+        .await
+    {
+        Ok(Some(zone)) => HttpResponse::Created().json(zone),
+        Ok(None) => HttpResponse::Conflict().body("Zone already exists on this site"),
+        Err(e) if foreign_key_violation(&e) => HttpResponse::NotFound().body("Site not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_zones(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_zones().await {
+        Ok(zones) => HttpResponse::Ok().json(zones),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn create_door(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<NameRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    if req.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("name is required");
+    }
+    match data
+        .db
+        .create_door(path.into_inner(), req.into_inner().name)
+        .await
+    {
+        Ok(Some(door)) => HttpResponse::Created().json(door),
+        Ok(None) => HttpResponse::Conflict().body("Door already exists in this zone"),
+        Err(e) if foreign_key_violation(&e) => HttpResponse::NotFound().body("Zone not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_doors(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_doors().await {
+        Ok(doors) => HttpResponse::Ok().json(doors),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_zone_permissions(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_zone_permissions(path.into_inner()).await {
+        Ok(permissions) => HttpResponse::Ok().json(permissions),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Lets one employee, or everyone with a role, into a zone.
+pub async fn grant_zone_permission(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<GrantZoneRequest>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    match (&req.employee_id, req.role.as_deref()) {
+        (Some(_), None) | (None, Some("admin" | "manager" | "employee")) => {}
+        (None, Some(_)) => {
+            return HttpResponse::BadRequest().body("role must be admin, manager or employee")
+        }
+        _ => return HttpResponse::BadRequest().body("Give either employee_id or role"),
+    }
+
+    match data
+        .db
+        .grant_zone_permission(path.into_inner(), req.into_inner(), actor)
+        .await
+    {
+        Ok(Some(permission)) => HttpResponse::Created().json(permission),
+        Ok(None) => HttpResponse::Conflict().body("Already granted"),
+        Err(e) if foreign_key_violation(&e) => {
+            HttpResponse::NotFound().body("Zone or employee not found")
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn revoke_zone_permission(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<(i32, i32)>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    let (id_zone, id_permission) = path.into_inner();
+    match data.db.revoke_zone_permission(id_zone, id_permission).await {
+        Ok(0) => HttpResponse::NotFound().body("Permission not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_schedules(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_schedules().await {
+        Ok(schedules) => HttpResponse::Ok().json(schedules),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Adds an access window. Once an employee, their role or a zone has any schedule,
+/// entering is only possible inside one of its windows.
+pub async fn create_schedule(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<CreateScheduleRequest>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    let targets = [
+        req.employee_id.is_some(),
+        req.role.is_some(),
+        req.id_zone.is_some(),
+    ];
+    if targets.iter().filter(|t| **t).count() != 1 {
+        return HttpResponse::BadRequest().body("Give exactly one of employee_id, role or id_zone");
+    }
+    if req
+        .role
+        .as_deref()
+        .is_some_and(|r| !["admin", "manager", "employee"].contains(&r))
+    {
+        return HttpResponse::BadRequest().body("role must be admin, manager or employee");
+    }
+    if req.weekdays.is_empty() || req.weekdays.iter().any(|d| !(1..=7).contains(d)) {
+        return HttpResponse::BadRequest().body("weekdays must be 1 (Monday) to 7 (Sunday)");
+    }
+    if let (Some(from), Some(until)) = (req.valid_from, req.valid_until) {
+        if until < from {
+            return HttpResponse::BadRequest().body("valid_until is before valid_from");
+        }
+    }
+
+    match data.db.create_schedule(req.into_inner(), actor).await {
+        Ok(schedule) => HttpResponse::Created().json(schedule),
+        Err(e) if foreign_key_violation(&e) => {
+            HttpResponse::NotFound().body("Employee or zone not found")
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn delete_schedule(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    match data.db.delete_schedule(path.into_inner()).await {
+        Ok(0) => HttpResponse::NotFound().body("Schedule not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_holidays(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_holidays(None, None).await {
+        Ok(holidays) => HttpResponse::Ok().json(holidays),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Replaces the whole list of holidays. Schedule windows don't open on them unless the
+/// schedule has `on_holidays`.
+pub async fn set_holidays(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<Vec<Holiday>>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    let holidays = req.into_inner();
+    let mut days: Vec<chrono::NaiveDate> = holidays.iter().map(|h| h.day).collect();
+    days.sort_unstable();
+    days.dedup();
+    if days.len() != holidays.len() {
+        return HttpResponse::BadRequest().body("Each day can only be listed once");
+    }
+
+    match data.db.set_holidays(holidays).await {
+        Ok(()) => HttpResponse::Ok().body("Holidays updated"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn create_visitor(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<CreateVisitorRequest>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, SECURITY_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    if req.first_name.trim().is_empty() || req.last_name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("first_name and last_name are required");
+    }
+    if !matches!(
+        req.kind.as_deref(),
+        None | Some("visitor") | Some("contractor")
+    ) {
+        return HttpResponse::BadRequest().body("kind must be visitor or contractor");
+    }
+    if req.valid_until <= req.valid_from {
+        return HttpResponse::BadRequest().body("valid_until must be after valid_from");
+    }
+    match data.db.create_visitor(req.into_inner(), actor).await {
+        Ok(visitor) => HttpResponse::Created().json(visitor),
+        Err(e) if foreign_key_violation(&e) => {
+            HttpResponse::BadRequest().body("Host employee not found")
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_visitors(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_visitors().await {
+        Ok(visitors) => HttpResponse::Ok().json(visitors),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Optional enrollment photo, once there is one the visitor's face is checked at stations.
+pub async fn upload_visitor_photo(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    mut payload: Multipart,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    let id_visitor = path.into_inner();
+    let p_path = match save_photo_upload(&mut payload, "uploads/visitors", id_visitor).await {
+        Ok(Some(p_path)) => p_path,
+        Ok(None) => return HttpResponse::BadRequest().body("Missing photo field"),
+        Err(resp) => return resp,
+    };
+    let bytes = match enrollment_embedding(&p_path) {
+        Ok(bytes) => bytes,
+        Err(resp) => return resp,
+    };
+
+    match data
+        .db
+        .update_visitor_photo(id_visitor, bytes, p_path.clone())
+        .await
+    {
+        Ok(0) => {
+            let _ = fs::remove_file(p_path);
+            HttpResponse::NotFound().body("Visitor not found")
+        }
+        Ok(_) => HttpResponse::Ok().body("Photo uploaded and processed"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Issues a pass valid until the end of the visit. Without `max_uses` it lets the
+/// visitor in any number of times within that period.
+pub async fn issue_visitor_pass(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<IssuePassRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    let id_visitor = path.into_inner();
+    if req.max_uses.is_some_and(|max| max < 1) {
+        return HttpResponse::BadRequest().body("max_uses must be at least 1");
+    }
+    let secret = match badge::secret() {
+        Some(secret) => secret,
+        None => return HttpResponse::InternalServerError().body("BADGE_SECRET is not set"),
+    };
+
+    let visitor = match data.db.get_visitor(id_visitor).await {
+        Ok(Some(visitor)) => visitor,
+        Ok(None) => return HttpResponse::NotFound().body("Visitor not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+    // whole seconds, the code carries unix seconds
+    let issued_at = Utc::now().trunc_subsecs(0);
+    let expires_at = visitor.valid_until.trunc_subsecs(0);
+    if expires_at <= issued_at {
+        return HttpResponse::BadRequest().body("The visit is already over");
+    }
+
+    match data
+        .db
+        .create_visitor_pass(id_visitor, req.max_uses, issued_at, expires_at)
+        .await
+    {
+        Ok(pass) => {
+            let code = badge::sign_pass(
+                &PassClaims {
+                    visitor_id: id_visitor,
+                    pass_id: pass.id_pass,
+                    expires_at,
+                },
+                &secret,
+            );
+            HttpResponse::Created().json(IssuedPass { pass, code })
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to issue pass")
+        }
+    }
+}
+
+pub async fn get_visitor_passes(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_visitor_passes(path.into_inner()).await {
+        Ok(passes) => HttpResponse::Ok().json(passes),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// QR of a visitor pass as PNG or SVG, for printing or sending to the visitor.
+pub async fn get_visitor_pass_image(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    query: web::Query<PassImageQuery>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    let format = query.format.as_deref().unwrap_or("png");
+    if !matches!(format, "png" | "svg") {
+        return HttpResponse::BadRequest().body("format must be png or svg");
+    }
+    let secret = match badge::secret() {
+        Some(secret) => secret,
+        None => return HttpResponse::InternalServerError().body("BADGE_SECRET is not set"),
+    };
+
+    let pass = match data.db.get_visitor_pass(path.into_inner()).await {
+        Ok(Some(pass)) if pass.revoked_at.is_none() && pass.expires_at > Utc::now() => pass,
+        Ok(_) => return HttpResponse::NotFound().body("No such active pass"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+    let code = badge::sign_pass(
+        &PassClaims {
+            visitor_id: pass.id_visitor,
+            pass_id: pass.id_pass,
+            expires_at: pass.expires_at,
+        },
+        &secret,
+    );
+
+    let rendered = match format {
+        "svg" => badge_card::qr_svg(&code).map(|svg| ("image/svg+xml", svg.into_bytes())),
+        _ => badge_card::qr_png(&code).map(|png| ("image/png", png)),
+    };
+    match rendered {
+        Ok((content_type, body)) => HttpResponse::Ok().content_type(content_type).body(body),
+        Err(e) => {
+            eprintln!("Failed to render pass: {}", e);
+            HttpResponse::InternalServerError().body("Failed to render pass")
+        }
+    }
+}
+
+pub async fn revoke_visitor_pass(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, SECURITY_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    match data
+        .db
+        .revoke_visitor_pass(path.into_inner(), actor, Utc::now())
+        .await
+    {
+        Ok(0) => HttpResponse::NotFound().body("Pass not found or already revoked"),
+        Ok(_) => HttpResponse::Ok().body("Pass revoked"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Failed to revoke pass")
+        }
+    }
+}
+
+pub async fn get_visitor_logs(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_visitor_logs().await {
+        Ok(logs) => HttpResponse::Ok().json(logs),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Emergency modes in force, the site-wide one first (if it was ever set).
+pub async fn get_site_modes(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_site_modes().await {
+        Ok(modes) => HttpResponse::Ok().json(modes),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Switches all sites, or the one in `id_site`, to another mode. The latest change wins,
+/// so switching everything back to `normal` also ends a lockdown of a single site.
+pub async fn set_site_mode(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<SetModeRequest>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    if !modes::MODES.contains(&req.mode.as_str()) {
+        return HttpResponse::BadRequest().body("mode must be normal, lockdown or evacuation");
+    }
+
+    match data.db.set_site_mode(req.into_inner(), actor).await {
+        Ok(change) => {
+            println!(
+                "Site mode set to {} for {} by {}",
+                change.mode,
+                change
+                    .id_site
+                    .map_or("all sites".to_string(), |id| format!("site {}", id)),
+                actor
+            );
+            let event = AccessEvent::new("mode_changed", Some(actor));
+            publish_event(&data, event.outcome(true, &change.mode, None)).await;
+            HttpResponse::Ok().json(change)
+        }
+        Err(e) if foreign_key_violation(&e) => HttpResponse::NotFound().body("Site not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_site_mode_history(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_site_mode_history().await {
+        Ok(changes) => HttpResponse::Ok().json(changes),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn set_zone_anti_passback(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<SetPassbackRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    if !passback::PASSBACK_MODES.contains(&req.mode.as_str()) {
+        return HttpResponse::BadRequest().body("mode must be off, soft or hard");
+    }
+    match data
+        .db
+        .set_zone_anti_passback(path.into_inner(), req.into_inner().mode)
+        .await
+    {
+        Ok(Some(zone)) => HttpResponse::Ok().json(zone),
+        Ok(None) => HttpResponse::NotFound().body("Zone not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+/// Forgets the employee's passes so far, in one zone or in all of them, so whichever
+/// way they pass next is accepted. For badges that really did get out of step, like
+/// someone let out through a door that was held open.
+pub async fn reset_passback(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    query: web::Query<PassbackResetQuery>,
+) -> impl Responder {
+    let actor = match require_role(&data, &http_req, ADMIN_ROLES).await {
+        Ok(actor) => actor,
+        Err(resp) => return resp,
+    };
+    match data
+        .db
+        .reset_passback(path.into_inner(), query.id_zone, actor)
+        .await
+    {
+        Ok(reset) => {
+            println!(
+                "Passback of employee {} reset for {} by {}",
+                reset.id_employee,
+                reset
+                    .id_zone
+                    .map_or("all zones".to_string(), |id| format!("zone {}", id)),
+                actor
+            );
+            HttpResponse::Ok().json(reset)
+        }
+        Err(e) if foreign_key_violation(&e) => {
+            HttpResponse::NotFound().body("Employee or zone not found")
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_passback_violations(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_passback_violations().await {
+        Ok(violations) => HttpResponse::Ok().json(violations),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn set_zone_escort(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<i32>,
+    req: web::Json<SetEscortRequest>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    if req
+        .escort_role
+        .as_deref()
+        .is_some_and(|role| !escort::ESCORT_ROLES.contains(&role))
+    {
+        return HttpResponse::BadRequest().body("escort_role must be admin, manager or employee");
+    }
+    match data
+        .db
+        .set_zone_escort(path.into_inner(), req.into_inner().escort_role)
+        .await
+    {
+        Ok(Some(zone)) => HttpResponse::Ok().json(zone),
+        Ok(None) => HttpResponse::NotFound().body("Zone not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_escort_authorizations(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match data.db.get_escort_authorizations().await {
+        Ok(authorizations) => HttpResponse::Ok().json(authorizations),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+pub async fn get_audit_log(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, ADMIN_ROLES).await {
+        return resp;
+    }
+    let filter = query.into_inner();
+    let page = filter.page.unwrap_or(1).max(1);
+    let page_size = filter.page_size.unwrap_or(50).clamp(1, 500);
+    if (page - 1).checked_mul(page_size).is_none() {
+        return HttpResponse::BadRequest().body("Invalid page");
+    }
+
+    match data.db.get_audit_entries(filter, page, page_size).await {
+        Ok((items, total)) => HttpResponse::Ok().json(AuditPage {
+            items,
+            total,
+            page,
+            page_size,
+        }),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Database error")
+        }
+    }
+}
+
+async fn current_presence(data: &web::Data<AppState>) -> Result<Presence, HttpResponse> {
+    match data.db.get_presence().await {
+        Ok(people) => Ok(presence::summarize(people, Utc::now())),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err(HttpResponse::InternalServerError().body("Database error"))
+        }
+    }
+}
+
+/// Everyone whose latest pass was IN, staff and visitors, with counts per zone.
+pub async fn get_presence(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    match current_presence(&data).await {
+        Ok(presence) => HttpResponse::Ok().json(presence),
+        Err(resp) => resp,
+    }
+}
+
+/// The same as a PDF with photos, to print and tick off at the assembly point.
+pub async fn get_muster_list(data: web::Data<AppState>, http_req: HttpRequest) -> impl Responder {
+    if let Err(resp) = require_role(&data, &http_req, SECURITY_ROLES).await {
+        return resp;
+    }
+    let presence = match current_presence(&data).await {
+        Ok(presence) => presence,
+        Err(resp) => return resp,
+    };
+    let photos: Vec<_> = presence
+        .people
+        .iter()
+        .map(|p| {
+            p.photo_path
+                .as_deref()
+                .and_then(|path| image::open(path).ok())
+        })
+        .collect();
+
+    match presence::muster_pdf(&presence, &photos) {
+        Ok(pdf) => HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header((
+                "Content-Disposition",
+                format!(
+                    "inline; filename=\"muster_{}.pdf\"",
+                    presence
+                        .as_of
+                        .with_timezone(&crate::timezone::site_tz())
+                        .format("%Y%m%d_%H%M")
+                ),
+            ))
+            .body(pdf),
+        Err(e) => {
+            eprintln!("Failed to render muster list: {}", e);
+            HttpResponse::InternalServerError().body("Failed to render muster list")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MockDatabaseRepository;
+    use actix_web::{test, App};
+
+    /// Test sessions are `session-<id>`, sent with `session_header`.
+    fn expect_sessions(mock_repo: &mut MockDatabaseRepository) {
+        mock_repo
+            .expect_get_session_employee()
+            .returning(|hash, _| {
+                Ok((1..100).find(|id| stations::hash_token(&format!("session-{}", id)) == hash))
+            });
+    }
+
+    fn session_header(id: i32) -> (&'static str, String) {
+        ("Authorization", format!("Bearer session-{}", id))
+    }
+
+    /// Test stations use `<name>/<direction>` as their token, sent with `station_header`.
+    fn expect_stations(mock_repo: &mut MockDatabaseRepository, names: &'static [&'static str]) {
+        mock_repo
+            .expect_get_station_by_token()
+            .returning(move |hash| {
+                let station = names
+                    .iter()
+                    .flat_map(|name| ["IN", "OUT"].map(|direction| (*name, direction)))
+                    .find(|(name, direction)| {
+                        stations::hash_token(&format!("{}/{}", name, direction)) == hash
+                    });
+                Ok(station.map(|(name, direction)| Station {
+                    id_station: 1,
+                    name: name.to_string(),
+                    location: None,
+                    direction: direction.to_string(),
+                    id_door: None,
+                    door: None,
+                    registered_at: Utc::now(),
+                    last_seen_at: None,
+                    version: None,
+                    camera_status: None,
+                    config_version: 1,
+                    online: true,
+                    mode: None,
+                }))
+            });
+    }
+
+    fn station_header(name: &str, direction: &str) -> (&'static str, String) {
+        ("Authorization", format!("Bearer {}/{}", name, direction))
+    }
+
+    #[actix_web::test]
+    async fn test_login_rehashes_legacy_password() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        mock_repo
+            .expect_get_login_credentials()
+            .withf(|login| login == "jdoe")
+            .returning(|_| Ok(Some((4, Some("hunter2".to_string())))));
+        mock_repo
+            .expect_set_password_hash()
+            .withf(|id, hash| *id == 4 && hash.starts_with("pbkdf2-sha256$"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("manager".to_string())));
+        mock_repo
+            .expect_create_session()
+            .withf(|id, _, _| *id == 4)
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/login", web::post().to(login)),
+        )
+        .await;
+
+        let wrong = test::TestRequest::post()
+            .uri("/login")
+            .set_json(serde_json::json!({"login": "jdoe", "password": "hunter3"}))
+            .to_request();
+        let resp = test::call_service(&app, wrong).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .set_json(serde_json::json!({"login": "jdoe", "password": "hunter2"}))
+            .to_request();
+        let session: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(session["id_person"], 4);
+        assert_eq!(session["role"], "manager");
+        assert!(session["token"].as_str().is_some_and(|t| !t.is_empty()));
+    }
+
+    #[actix_web::test]
+    async fn test_get_employees() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        mock_repo
+            .expect_get_employees()
+            .withf(|include_archived| !include_archived)
+            .returning(|_| Ok(vec![])); // Return empty list
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employees", web::get().to(get_employees)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/employees").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_found() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo.expect_zone_permitted().returning(|_, _| Ok(None));
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_active_lockout()
+            .returning(|_, _, _| Ok(None));
+        mock_repo
+            .expect_get_applicable_schedules()
+            .returning(|_, _| Ok(vec![]));
+        mock_repo
+            .expect_get_employee_by_id()
+            .with(mockall::predicate::eq(123))
+            .returning(|_| Ok(Some((123, "John".to_string(), "Doe".to_string()))));
+
+        let app_data = web::Data::new(AppState {
+            allow_plain_badges: true,
+            ..AppState::new(Box::new(mock_repo))
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employee/check_qr")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(CheckQrRequest {
+                employee_id: 123,
+                direction: "IN".to_string(),
+                code: None,
+                station: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(body.exists);
+        assert_eq!(body.first_name, Some("John".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_zone_not_permitted() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["server_room"]);
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_active_lockout()
+            .returning(|_, _, _| Ok(None));
+        mock_repo
+            .expect_get_employee_by_id()
+            .returning(|_| Ok(Some((123, "John".to_string(), "Doe".to_string()))));
+        mock_repo
+            .expect_zone_permitted()
+            .withf(|employee, station| *employee == 123 && station == "server_room")
+            .times(1)
+            .returning(|_, _| Ok(Some(false)));
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+
+        let app_data = web::Data::new(AppState {
+            allow_plain_badges: true,
+            ..AppState::new(Box::new(mock_repo))
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let scan = |direction: &str| {
+            test::TestRequest::post()
+                .uri("/employee/check_qr")
+                .insert_header(station_header("server_room", direction))
+                .set_json(CheckQrRequest {
+                    employee_id: 123,
+                    direction: direction.to_string(),
+                    code: None,
+                    station: Some("server_room".to_string()),
+                })
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, scan("IN")).await;
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(!body.exists);
+        assert_eq!(body.reason.as_deref(), Some("zone_not_permitted"));
+
+        // leaving is never blocked, zone_permitted isn't even asked
+        let resp = test::call_service(&app, scan("OUT")).await;
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(body.exists);
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_outside_access_hours() {
+        // a schedule that only starts the day after tomorrow
+        let today = Utc::now()
+            .with_timezone(&crate::timezone::site_tz())
+            .date_naive();
+        let starts = today + chrono::Duration::days(2);
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo.expect_zone_permitted().returning(|_, _| Ok(None));
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_active_lockout()
+            .returning(|_, _, _| Ok(None));
+        mock_repo
+            .expect_get_employee_by_id()
+            .returning(|_| Ok(Some((123, "John".to_string(), "Doe".to_string()))));
+        mock_repo
+            .expect_get_applicable_schedules()
+            .returning(move |_, _| {
+                Ok(vec![crate::models::AccessSchedule {
+                    id_schedule: 1,
+                    id_employee: Some(123),
+                    role: None,
+                    id_zone: None,
+                    weekdays: vec![1, 2, 3, 4, 5, 6, 7],
+                    start_time: chrono::NaiveTime::MIN,
+                    end_time: chrono::NaiveTime::MIN,
+                    valid_from: Some(starts),
+                    valid_until: None,
+                    on_holidays: true,
+                    created_by: 1,
+                    created_at: Utc::now(),
+                }])
+            });
+        mock_repo.expect_get_holidays().returning(|_, _| Ok(vec![]));
+
+        let app_data = web::Data::new(AppState {
+            allow_plain_badges: true,
+            ..AppState::new(Box::new(mock_repo))
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employee/check_qr")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(CheckQrRequest {
+                employee_id: 123,
+                direction: "IN".to_string(),
+                code: None,
+                station: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(!body.exists);
+        assert_eq!(body.reason.as_deref(), Some("outside_access_hours"));
+        assert_eq!(
+            body.next_allowed_at,
+            crate::timezone::from_site_local(starts.and_time(chrono::NaiveTime::MIN))
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_not_found() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_active_lockout()
+            .returning(|_, _, _| Ok(None));
+        mock_repo
+            .expect_get_employee_by_id()
+            .with(mockall::predicate::eq(999))
+            .returning(|_| Ok(None));
+
+        let app_data = web::Data::new(AppState {
+            allow_plain_badges: true,
+            ..AppState::new(Box::new(mock_repo))
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employee/check_qr")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(CheckQrRequest {
+                employee_id: 999,
+                direction: "IN".to_string(),
+                code: None,
+                station: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(!body.exists);
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_takes_station_from_token() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["server_room"]);
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_active_lockout()
+            .returning(|_, _, _| Ok(None));
+        mock_repo
+            .expect_get_employee_by_id()
+            .returning(|_| Ok(Some((123, "John".to_string(), "Doe".to_string()))));
+        mock_repo
+            .expect_zone_permitted()
+            .withf(|_, station| station == "server_room")
+            .times(1)
+            .returning(|_, _| Ok(Some(false)));
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+
+        let app_data = web::Data::new(AppState {
+            allow_plain_badges: true,
+            ..AppState::new(Box::new(mock_repo))
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let scan = |token: Option<(&'static str, String)>| {
+            let req = test::TestRequest::post().uri("/employee/check_qr");
+            let req = match token {
+                Some(header) => req.insert_header(header),
+                None => req,
+            };
+            req.set_json(CheckQrRequest {
+                employee_id: 123,
+                direction: "OUT".to_string(),
+                code: None,
+                station: Some("lobby".to_string()),
+            })
+            .to_request()
+        };
+
+        let resp = test::call_service(&app, scan(None)).await;
+        assert_eq!(resp.status(), 401);
+        let resp = test::call_service(
+            &app,
+            scan(Some(("Authorization", "Bearer lobby/OUT".to_string()))),
+        )
+        .await;
+        assert_eq!(resp.status(), 401);
+
+        // the claimed "lobby" is ignored, the token says server_room
+        let resp = test::call_service(&app, scan(Some(station_header("server_room", "IN")))).await;
+        assert!(resp.status().is_success());
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(!body.exists);
+        assert_eq!(body.reason.as_deref(), Some("zone_not_permitted"));
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_rejects_plain_id_by_default() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo
+            .expect_count_failures_since()
+            .returning(|_, _, _| Ok(1));
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo.expect_get_employee_by_id().never();
+        mock_repo
+            .expect_add_failed_attempt()
+            .withf(|attempt| attempt.reason == "invalid_badge")
+            .times(1)
+            .returning(|_| Ok(1));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employee/check_qr")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(CheckQrRequest {
+                employee_id: 1,
+                direction: "IN".to_string(),
+                code: None,
+                station: None,
+            })
+            .to_request();
+        let body: CheckQrResponse = test::call_and_read_body_json(&app, req).await;
+
+        assert!(!body.exists);
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_locked_out_station() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["station_in"]);
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_active_lockout()
+            .returning(|subject_type, subject, now| {
+                if subject_type != "station" {
+                    return Ok(None);
+                }
+                Ok(Some(crate::models::Lockout {
+                    id_lockout: 1,
+                    subject_type,
+                    subject,
+                    locked_at: now,
+                    locked_until: now + chrono::Duration::minutes(15),
+                    unlocked_by: None,
+                    unlocked_at: None,
+                }))
+            });
+        mock_repo.expect_get_employee_by_id().never();
+
+        let app_data = web::Data::new(AppState {
+            allow_plain_badges: true,
+            ..AppState::new(Box::new(mock_repo))
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employee/check_qr")
+            .insert_header(station_header("station_in", "IN"))
+            .set_json(CheckQrRequest {
+                employee_id: 123,
+                direction: "IN".to_string(),
+                code: None,
+                station: Some("station_in".to_string()),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(!body.exists);
+        assert_eq!(body.reason.as_deref(), Some("locked_out"));
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_revoked_badge() {
+        std::env::set_var("BADGE_SECRET", "test-secret");
+        let code = badge::sign(
+            &BadgeClaims {
+                employee_id: 123,
+                badge_id: 4,
+                issued_at: "2026-01-01T00:00:00Z".parse().unwrap(),
+                expires_at: Utc::now() + chrono::Duration::days(30),
+            },
+            b"test-secret",
+        );
+
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["station_in"]);
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_get_badge()
+            .with(mockall::predicate::eq(4))
+            .returning(|_| {
+                Ok(Some(crate::models::Badge {
+                    id_badge: 4,
+                    id_employee: 123,
+                    issued_at: "2026-01-01T00:00:00Z".parse().unwrap(),
+                    expires_at: Utc::now() + chrono::Duration::days(30),
+                    revoked_by: Some(1),
+                    revoked_at: Some("2026-02-01T00:00:00Z".parse().unwrap()),
+                }))
+            });
+        mock_repo
+            .expect_add_failed_attempt()
+            .withf(|attempt| attempt.reason == "badge_revoked")
+            .times(1)
+            .returning(|_| Ok(1));
+        mock_repo
+            .expect_count_failures_since()
+            .returning(|_, _, _| Ok(1));
+        mock_repo.expect_get_employee_by_id().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employee/check_qr")
+            .insert_header(station_header("station_in", "IN"))
+            .set_json(CheckQrRequest {
+                employee_id: 0,
+                direction: "IN".to_string(),
+                code: Some(code),
+                station: Some("station_in".to_string()),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(!body.exists);
+        assert_eq!(body.employee_id, 123);
+        assert_eq!(body.reason.as_deref(), Some("badge_revoked"));
+    }
+
+    #[actix_web::test]
+    async fn test_totp_enrollment_needs_security_role_even_for_self() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("employee".to_string())));
+        mock_repo.expect_set_totp_secret().never();
+        mock_repo.expect_delete_totp().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employees/{id}/totp", web::post().to(enroll_totp))
+                .route("/employees/{id}/totp", web::delete().to(delete_totp)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employees/7/totp")
+            .insert_header(session_header(7))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::delete()
+            .uri("/employees/7/totp")
+            .insert_header(session_header(7))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_totp_replay() {
+        let secret = b"12345678901234567890".to_vec();
+        let step = totp::step_at(Utc::now());
+        let code = format!("CT1.123.{:06}", totp::code_at(&secret, step));
+
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo
+            .expect_count_failures_since()
+            .returning(|_, _, _| Ok(1));
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_get_totp_secret()
+            .with(mockall::predicate::eq(123))
+            .returning(move |_| Ok(Some(secret.clone())));
+        // the step was already used by an earlier scan
+        mock_repo
+            .expect_use_totp_step()
+            .with(mockall::predicate::eq(123), mockall::predicate::eq(step))
+            .returning(|_, _| Ok(false));
+        mock_repo.expect_add_failed_attempt().returning(|_| Ok(1));
+        mock_repo.expect_get_employee_by_id().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employee/check_qr")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(CheckQrRequest {
+                employee_id: 0,
+                direction: "IN".to_string(),
+                code: Some(code),
+                station: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        let body: CheckQrResponse = test::read_body_json(resp).await;
+        assert!(!body.exists);
+        assert_eq!(body.reason.as_deref(), Some("code_reused"));
+    }
+
+    #[actix_web::test]
+    async fn test_access_ack_retry_returns_original() {
+        let event_id: Uuid = "6f1c1a52-3b8e-4c55-9a4e-0d2b7c9e8f10".parse().unwrap();
+        let timestamp = Utc::now().trunc_subsecs(0);
+
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+        mock_repo
+            .expect_add_access_log()
+            .withf(move |id, employee, _, _, _| *id == Some(event_id) && *employee == 7)
+            .times(1)
+            .returning(move |_, _, _, _, _| {
+                Ok((
+                    crate::models::AccessLog {
+                        id_log: 41,
+                        id_employee: 7,
+                        direction: "IN".to_string(),
+                        timestamp,
+                        event_id: Some(event_id),
+                        station: None,
+                        id_door: None,
+                    },
+                    false,
+                ))
+            });
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/access/ack", web::post().to(access_ack)),
+        )
+        .await;
+
+        let ack = |timestamp: DateTime<Utc>| AccessAckRequest {
+            event_id: Some(event_id),
+            employee_id: 7,
+            pass_id: None,
+            authorization_id: None,
+            direction: "IN".to_string(),
+            station: None,
+            timestamp,
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/access/ack")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(ack(timestamp))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("Idempotent-Replayed").unwrap(), "true");
+        let body: AccessAckResponse = test::read_body_json(resp).await;
+        assert_eq!(body.id_log, Some(41));
+
+        // a day old, never reaches the database
+        let req = test::TestRequest::post()
+            .uri("/access/ack")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(ack(timestamp - chrono::Duration::days(1)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_station_sync_batch_results() {
+        std::env::set_var("STATION_SECRET", "station-secret");
+        let event = |id: &str, employee_id: i32| {
+            let mut event = crate::models::SyncEvent {
+                event_id: id.parse().unwrap(),
+                kind: "access_ack".to_string(),
+                employee_id: Some(employee_id),
+                direction: "IN".to_string(),
+                timestamp: "2026-01-29T08:00:00Z".parse().unwrap(),
+                reason: None,
+                similarity: None,
+                signature: String::new(),
+            };
+            event.signature = sync::sign("station_in", &event, b"station-secret");
+            event
+        };
+        let fresh = event("00000000-0000-4000-8000-000000000001", 7);
+        let mut tampered = event("00000000-0000-4000-8000-000000000002", 7);
+        tampered.employee_id = Some(1);
+        let resent = event("00000000-0000-4000-8000-000000000003", 7);
+
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["station_in"]);
+        mock_repo.expect_add_access_log().times(2).returning(
+            |event_id, employee, direction, station, timestamp| {
+                let log = crate::models::AccessLog {
+                    id_log: 10,
+                    id_employee: employee,
+                    direction,
+                    timestamp,
+                    event_id,
+                    station,
+                    id_door: Some(2),
+                };
+                // the third event was already uploaded before the connection dropped
+                let inserted =
+                    event_id != Some("00000000-0000-4000-8000-000000000003".parse().unwrap());
+                Ok((log, inserted))
+            },
+        );
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/stations/sync", web::post().to(sync_station_events)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/stations/sync")
+            .insert_header(station_header("station_in", "IN"))
+            .set_json(SyncRequest {
+                station: "station_in".to_string(),
+                events: vec![fresh, tampered, resent],
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: SyncResponse = test::read_body_json(resp).await;
+        let statuses: Vec<&str> = body.results.iter().map(|r| r.status.as_str()).collect();
+        assert_eq!(statuses, ["accepted", "rejected", "duplicate"]);
+        assert_eq!(body.results[1].reason.as_deref(), Some("invalid_signature"));
+    }
+
+    #[actix_web::test]
+    async fn test_station_heartbeat_requires_known_token() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_station_heartbeat()
+            .times(2)
+            .returning(|token_hash, req, now| {
+                if token_hash != stations::hash_token("good-token") {
+                    return Ok(None);
+                }
+                Ok(Some(crate::models::Station {
+                    id_station: 1,
+                    name: "station_in".to_string(),
+                    location: Some("Main entrance".to_string()),
+                    direction: "IN".to_string(),
+                    id_door: None,
+                    door: None,
+                    registered_at: now,
+                    last_seen_at: Some(now),
+                    version: req.version,
+                    camera_status: req.camera_status,
+                    config_version: 1,
+                    online: false,
+                    mode: None,
+                }))
+            });
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/stations/heartbeat", web::post().to(station_heartbeat)),
+        )
+        .await;
+
+        let beat = |token: Option<&str>| {
+            let req = test::TestRequest::post()
+                .uri("/stations/heartbeat")
+                .set_json(HeartbeatRequest {
+                    version: Some("1.4.0".to_string()),
+                    camera_status: Some("ok".to_string()),
+                });
+            match token {
+                Some(token) => req
+                    .insert_header(("Authorization", format!("Bearer {}", token)))
+                    .to_request(),
+                None => req.to_request(),
+            }
+        };
+
+        let resp = test::call_service(&app, beat(None)).await;
+        assert_eq!(resp.status(), 401);
+        let resp = test::call_service(&app, beat(Some("stolen-token"))).await;
+        assert_eq!(resp.status(), 401);
+
+        let resp = test::call_service(&app, beat(Some("good-token"))).await;
+        assert!(resp.status().is_success());
+        let station: crate::models::Station = test::read_body_json(resp).await;
+        assert!(station.online);
+        assert_eq!(station.version.as_deref(), Some("1.4.0"));
+    }
+
+    #[actix_web::test]
+    async fn test_station_config_etag() {
+        let config = StationConfig {
+            id_station: 3,
+            version: 7,
+            direction: "IN".to_string(),
+            fps: 30,
+            countdown_seconds: 3,
+            request_timeout_seconds: 5,
+            message_seconds: 2,
+            face_threshold: 0.95,
+        };
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo.expect_get_station_by_token().returning(|_| {
+            Ok(Some(crate::models::Station {
+                id_station: 3,
+                name: "station_in".to_string(),
+                location: None,
+                direction: "IN".to_string(),
+                id_door: None,
+                door: None,
+                registered_at: Utc::now(),
+                last_seen_at: None,
+                version: None,
+                camera_status: None,
+                config_version: 7,
+                online: false,
+                mode: None,
+            }))
+        });
+        let stored = config.clone();
+        mock_repo
+            .expect_get_station_config()
+            .returning(move |_| Ok(Some(stored.clone())));
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/stations/{id}/config", web::get().to(get_station_config))
+                .route(
+                    "/stations/{id}/config",
+                    web::patch().to(update_station_config),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/stations/3/config")
+            .insert_header(("Authorization", "Bearer token"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("ETag").unwrap(), "\"7\"");
+
+        let req = test::TestRequest::get()
+            .uri("/stations/3/config")
+            .insert_header(("Authorization", "Bearer token"))
+            .insert_header(("If-None-Match", "\"7\""))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 304);
+
+        // a station can't read another station's config
+        let req = test::TestRequest::get()
+            .uri("/stations/4/config")
+            .insert_header(("Authorization", "Bearer token"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        // edited from a stale copy of the config
+        let req = test::TestRequest::patch()
+            .uri("/stations/3/config")
+            .insert_header(session_header(1))
+            .insert_header(("If-Match", "\"6\""))
+            .set_json(StationConfigUpdate {
+                fps: Some(15),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 412);
+    }
+
+    #[actix_web::test]
+    async fn test_visitor_pass_checked_and_logged_apart() {
+        std::env::set_var("BADGE_SECRET", "test-secret");
+        let valid_until = (Utc::now() + chrono::Duration::hours(4)).trunc_subsecs(0);
+        let code = badge::sign_pass(
+            &PassClaims {
+                visitor_id: 3,
+                pass_id: 9,
+                expires_at: valid_until,
+            },
+            b"test-secret",
+        );
+
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo.expect_zone_permitted().returning(|_, _| Ok(None));
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_get_visitor_pass()
+            .with(mockall::predicate::eq(9))
+            .returning(move |_| {
+                Ok(Some(crate::models::VisitorPass {
+                    id_pass: 9,
+                    id_visitor: 3,
+                    max_uses: Some(1),
+                    uses: 0,
+                    issued_at: Utc::now() - chrono::Duration::hours(1),
+                    expires_at: valid_until,
+                    revoked_by: None,
+                    revoked_at: None,
+                }))
+            });
+        mock_repo.expect_get_visitor().returning(move |_| {
+            Ok(Some(crate::models::Visitor {
+                id_visitor: 3,
+                first_name: "Jan".to_string(),
+                last_name: "Kowalski".to_string(),
+                company: None,
+                kind: "contractor".to_string(),
+                id_host: 1,
+                valid_from: Utc::now() - chrono::Duration::hours(1),
+                valid_until,
+                photo_path: None,
+                face_enrolled: false,
+                created_by: 1,
+                created_at: Utc::now(),
+            }))
+        });
+        mock_repo
+            .expect_add_visitor_log()
+            .withf(|_, pass_id, direction, _, _| *pass_id == 9 && direction == "IN")
+            .times(1)
+            .returning(|event_id, id_pass, direction, station, timestamp| {
+                Ok(Some((
+                    crate::models::VisitorLog {
+                        id_log: 1,
+                        id_visitor: 3,
+                        id_pass,
+                        direction,
+                        timestamp,
+                        event_id,
+                        station,
+                        id_door: None,
+                    },
+                    true,
+                )))
+            });
+        mock_repo.expect_add_access_log().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr))
+                .route("/access/ack", web::post().to(access_ack)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employee/check_qr")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(CheckQrRequest {
+                employee_id: 0,
+                direction: "IN".to_string(),
+                code: Some(code),
+                station: None,
+            })
+            .to_request();
+        let body: CheckQrResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(body.exists);
+        assert_eq!(body.employee_id, 0);
+        let visitor = body.visitor.expect("visitor entry");
+        assert_eq!((visitor.visitor_id, visitor.pass_id), (3, 9));
+        assert!(!visitor.face_enrolled);
+
+        let req = test::TestRequest::post()
+            .uri("/access/ack")
+            .insert_header(station_header("gate", "IN"))
+            .set_json(AccessAckRequest {
+                event_id: None,
+                employee_id: 0,
+                pass_id: Some(9),
+                authorization_id: None,
+                direction: "IN".to_string(),
+                station: None,
+                timestamp: Utc::now(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_visitor_face_needs_signed_pass_and_host_not_locked_out() {
+        std::env::set_var("BADGE_SECRET", "test-secret");
+        let valid_until = (Utc::now() + chrono::Duration::hours(4)).trunc_subsecs(0);
+        let code = badge::sign_pass(
+            &PassClaims {
+                visitor_id: 3,
+                pass_id: 9,
+                expires_at: valid_until,
+            },
+            b"test-secret",
+        );
+
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo.expect_zone_permitted().returning(|_, _| Ok(None));
+        mock_repo
+            .expect_get_visitor_pass()
+            .returning(move |id_pass| {
+                Ok(Some(crate::models::VisitorPass {
+                    id_pass,
+                    id_visitor: 3,
+                    max_uses: None,
+                    uses: 0,
+                    issued_at: Utc::now() - chrono::Duration::hours(1),
+                    expires_at: valid_until,
+                    revoked_by: None,
+                    revoked_at: None,
+                }))
+            });
+        mock_repo.expect_get_visitor().returning(move |_| {
+            Ok(Some(crate::models::Visitor {
+                id_visitor: 3,
+                first_name: "Jan".to_string(),
+                last_name: "Kowalski".to_string(),
+                company: None,
+                kind: "contractor".to_string(),
+                id_host: 1,
+                valid_from: Utc::now() - chrono::Duration::hours(1),
+                valid_until,
+                photo_path: None,
+                face_enrolled: false,
+                created_by: 1,
+                created_at: Utc::now(),
+            }))
+        });
+        // the host is locked out, so their visitor is too
+        mock_repo
+            .expect_active_lockout()
+            .returning(|subject_type, subject, now| {
+                if subject_type != "employee" || subject != "1" {
+                    return Ok(None);
+                }
+                Ok(Some(crate::models::Lockout {
+                    id_lockout: 1,
+                    subject_type,
+                    subject,
+                    locked_at: now,
+                    locked_until: now + chrono::Duration::minutes(15),
+                    unlocked_by: None,
+                    unlocked_at: None,
+                }))
+            });
+        mock_repo.expect_use_visitor_pass().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/face/verify", web::post().to(verify_face)),
+        )
+        .await;
+
+        let verify = |field: &str, value: &str| {
+            let body = format!(
+                "--XB\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n\
+                 --XB\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"f.jpg\"\r\n\
+                 Content-Type: image/jpeg\r\n\r\njpeg\r\n--XB--\r\n",
+                field, value
+            );
+            test::TestRequest::post()
+                .uri("/face/verify")
+                .insert_header(station_header("gate", "IN"))
+                .insert_header(("Content-Type", "multipart/form-data; boundary=XB"))
+                .set_payload(body)
+                .to_request()
+        };
+
+        // a bare pass number is no longer enough
+        let resp = test::call_service(&app, verify("pass_id", "9")).await;
+        assert_eq!(resp.status(), 400);
+
+        let forged = code.replace(".9.", ".8.");
+        let body: VerifyFaceResponse =
+            test::call_and_read_body_json(&app, verify("pass_code", &forged)).await;
+        assert_eq!(body.reason, "invalid_pass");
+
+        let body: VerifyFaceResponse =
+            test::call_and_read_body_json(&app, verify("pass_code", &code)).await;
+        assert!(!body.access_granted);
+        assert_eq!(body.reason, "locked_out");
+    }
+
+    #[actix_web::test]
+    async fn test_employee_face_needs_the_scanned_badge() {
+        std::env::set_var("BADGE_SECRET", "test-secret");
+        let expires_at = Utc::now() + chrono::Duration::days(30);
+        let code = badge::sign(
+            &BadgeClaims {
+                employee_id: 123,
+                badge_id: 4,
+                issued_at: "2026-01-01T00:00:00Z".parse().unwrap(),
+                expires_at,
+            },
+            b"test-secret",
+        );
+        let secret = b"12345678901234567890".to_vec();
+        let totp_code = format!(
+            "CT1.123.{:06}",
+            totp::code_at(&secret, totp::step_at(Utc::now()))
+        );
+
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo.expect_get_badge().returning(move |id_badge| {
+            Ok(Some(crate::models::Badge {
+                id_badge,
+                id_employee: 123,
+                issued_at: "2026-01-01T00:00:00Z".parse().unwrap(),
+                expires_at,
+                revoked_by: None,
+                revoked_at: None,
+            }))
+        });
+        mock_repo
+            .expect_get_totp_secret()
+            .returning(move |_| Ok(Some(secret.clone())));
+        // spent by the check_qr scan just before
+        mock_repo.expect_use_totp_step().never();
+        mock_repo
+            .expect_add_failed_attempt()
+            .withf(|attempt| attempt.reason == "invalid_badge")
+            .times(2)
+            .returning(|_| Ok(1));
+        mock_repo
+            .expect_count_failures_since()
+            .returning(|_, _, _| Ok(1));
+        mock_repo
+            .expect_active_lockout()
+            .returning(|_, _, _| Ok(None));
+        mock_repo.expect_zone_permitted().returning(|_, _| Ok(None));
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+        mock_repo
+            .expect_get_applicable_schedules()
+            .returning(|_, _| Ok(vec![]));
+        mock_repo.expect_station_zone().returning(|_| Ok(None));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/face/verify", web::post().to(verify_face)),
+        )
+        .await;
+
+        let verify = |field: &str, value: &str| {
+            let body = format!(
+                "--XB\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n\
+                 --XB\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"f.jpg\"\r\n\
+                 Content-Type: image/jpeg\r\n\r\njpeg\r\n--XB--\r\n",
+                field, value
+            );
+            test::TestRequest::post()
+                .uri("/face/verify")
+                .insert_header(station_header("gate", "IN"))
+                .insert_header(("Content-Type", "multipart/form-data; boundary=XB"))
+                .set_payload(body)
+                .to_request()
+        };
+
+        // a bare id would reach the face match (and MOCK_MODEL would let it in)
+        let body: VerifyFaceResponse =
+            test::call_and_read_body_json(&app, verify("employee_id", "123")).await;
+        assert!(!body.access_granted);
+        assert_eq!(body.reason, "invalid_badge");
+
+        let forged = format!("{}x", code);
+        let body: VerifyFaceResponse =
+            test::call_and_read_body_json(&app, verify("code", &forged)).await;
+        assert!(!body.access_granted);
+        assert_eq!(body.reason, "invalid_badge");
+
+        let body: VerifyFaceResponse =
+            test::call_and_read_body_json(&app, verify("code", &code)).await;
+        assert!(body.access_granted);
+
+        let body: VerifyFaceResponse =
+            test::call_and_read_body_json(&app, verify("code", &totp_code)).await;
+        assert!(body.access_granted);
+    }
+
+    #[actix_web::test]
+    async fn test_check_qr_lockdown_and_evacuation() {
+        let mode = std::sync::Arc::new(std::sync::Mutex::new("lockdown"));
+        let current = mode.clone();
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate"]);
+        mock_repo.expect_zone_permitted().returning(|_, _| Ok(None));
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+        mock_repo.expect_station_mode().returning(move |_| {
+            Ok(Some(crate::models::SiteModeChange {
+                id_change: 1,
+                id_site: None,
+                mode: current.lock().unwrap().to_string(),
+                reason: Some("drill".to_string()),
+                changed_by: 1,
+                changed_at: Utc::now(),
+            }))
+        });
+        mock_repo
+            .expect_active_lockout()
+            .returning(|_, _, _| Ok(None));
+        mock_repo
+            .expect_get_employee_by_id()
+            .returning(|id| Ok(Some((id, "John".to_string(), "Doe".to_string()))));
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|id| Ok(Some(if id == 1 { "admin" } else { "manager" }.to_string())));
+        mock_repo
+            .expect_get_applicable_schedules()
+            .returning(|_, _| Ok(vec![]));
+
+        let app_data = web::Data::new(AppState {
+            allow_plain_badges: true,
+            ..AppState::new(Box::new(mock_repo))
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr)),
+        )
+        .await;
+        let scan = |employee_id: i32, direction: &str| {
+            test::TestRequest::post()
+                .uri("/employee/check_qr")
+                .insert_header(station_header("gate", direction))
+                .set_json(CheckQrRequest {
+                    employee_id,
+                    direction: direction.to_string(),
+                    code: None,
+                    station: None,
+                })
+                .to_request()
+        };
+
+        let body: CheckQrResponse = test::call_and_read_body_json(&app, scan(2, "OUT")).await;
+        assert!(!body.exists);
+        assert_eq!(body.reason.as_deref(), Some("lockdown"));
+        let body: CheckQrResponse = test::call_and_read_body_json(&app, scan(1, "IN")).await;
+        assert!(body.exists);
+
+        *mode.lock().unwrap() = "evacuation";
+        // unknown or not, everyone gets out
+        let body: CheckQrResponse = test::call_and_read_body_json(&app, scan(999, "OUT")).await;
+        assert!(body.exists);
+        assert_eq!(body.reason.as_deref(), Some("evacuation"));
+    }
+
+    #[actix_web::test]
+    async fn test_presence_counts_per_zone() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo.expect_get_employee_role().returning(|id| {
+            Ok(Some(
+                if id == 1 { "manager" } else { "employee" }.to_string(),
+            ))
+        });
+        mock_repo.expect_get_presence().times(1).returning(|| {
+            let entry =
+                |kind: &str, id: i32, zone: Option<(i32, &str)>| crate::models::PresenceEntry {
+                    kind: kind.to_string(),
+                    id,
+                    first_name: "Anna".to_string(),
+                    last_name: format!("Nowak{}", id),
+                    role: (kind == "employee").then(|| "employee".to_string()),
+                    id_host: (kind != "employee").then_some(1),
+                    photo_path: None,
+                    entered_at: Utc::now() - chrono::Duration::minutes(90),
+                    station: Some("station_in".to_string()),
+                    id_zone: zone.map(|z| z.0),
+                    zone: zone.map(|z| z.1.to_string()),
+                    inside_minutes: 0,
+                };
+            Ok(vec![
+                entry("employee", 2, Some((1, "Hall"))),
+                entry("visitor", 7, Some((1, "Hall"))),
+                entry("employee", 3, None),
+            ])
+        });
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/presence", web::get().to(get_presence)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/presence")
+            .insert_header(session_header(2))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get()
+            .uri("/presence")
+            .insert_header(session_header(1))
+            .to_request();
+        let body: Presence = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body.total, 3);
+        let counts: Vec<(Option<i32>, usize)> =
+            body.zones.iter().map(|z| (z.id_zone, z.count)).collect();
+        assert_eq!(counts, [(Some(1), 2), (None, 1)]);
+        assert!(body.people.iter().all(|p| p.inside_minutes >= 89));
+    }
+
+    #[actix_web::test]
+    async fn test_passback_hard_denies_soft_records() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["office", "server_room"]);
+        mock_repo.expect_station_mode().returning(|_| Ok(None));
+        mock_repo
+            .expect_active_lockout()
+            .returning(|_, _, _| Ok(None));
+        mock_repo
+            .expect_get_employee_by_id()
+            .returning(|_| Ok(Some((7, "John".to_string(), "Doe".to_string()))));
+        mock_repo
+            .expect_zone_permitted()
+            .returning(|_, _| Ok(Some(true)));
+        mock_repo
+            .expect_get_applicable_schedules()
+            .returning(|_, _| Ok(vec![]));
+        // already inside both zones, the badge went back out through the door
+        mock_repo.expect_passback_state().returning(|_, station| {
+            Ok(Some(crate::models::PassbackState {
+                id_zone: if station == "server_room" { 3 } else { 4 },
+                anti_passback: if station == "server_room" {
+                    "hard"
+                } else {
+                    "soft"
+                }
+                .to_string(),
+                last_direction: Some("IN".to_string()),
+            }))
+        });
+        mock_repo.expect_add_access_log().times(1).returning(
+            |event_id, employee, direction, station, timestamp| {
+                Ok((
+                    crate::models::AccessLog {
+                        id_log: 12,
+                        id_employee: employee,
+                        direction,
+                        timestamp,
+                        event_id,
+                        station,
+                        id_door: Some(5),
+                    },
+                    true,
+                ))
+            },
+        );
+        mock_repo
+            .expect_add_passback_violation()
+            .with(mockall::predicate::eq(12), mockall::predicate::eq(4))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let app_data = web::Data::new(AppState {
+            allow_plain_badges: true,
+            ..AppState::new(Box::new(mock_repo))
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employee/check_qr", web::post().to(check_qr))
+                .route("/access/ack", web::post().to(access_ack)),
+        )
+        .await;
+        let scan = |station: &str, direction: &str| {
+            test::TestRequest::post()
+                .uri("/employee/check_qr")
+                .insert_header(station_header(station, direction))
+                .set_json(CheckQrRequest {
+                    employee_id: 7,
+                    direction: direction.to_string(),
+                    code: None,
+                    station: Some(station.to_string()),
+                })
+                .to_request()
+        };
+
+        let body: CheckQrResponse =
+            test::call_and_read_body_json(&app, scan("server_room", "IN")).await;
+        assert!(!body.exists);
+        assert_eq!(body.reason.as_deref(), Some("passback"));
+        let body: CheckQrResponse =
+            test::call_and_read_body_json(&app, scan("server_room", "OUT")).await;
+        assert!(body.exists);
+        let body: CheckQrResponse = test::call_and_read_body_json(&app, scan("office", "IN")).await;
+        assert!(body.exists);
+
+        let req = test::TestRequest::post()
+            .uri("/access/ack")
+            .insert_header(station_header("office", "IN"))
+            .set_json(AccessAckRequest {
+                event_id: None,
+                employee_id: 7,
+                pass_id: None,
+                authorization_id: None,
+                direction: "IN".to_string(),
+                station: Some("office".to_string()),
+                timestamp: Utc::now(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_escort_ack_needs_confirmation() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["server_room"]);
+        mock_repo
+            .expect_complete_escort()
+            .withf(|_, station, employee, pass, _| {
+                station == "server_room" && *employee == Some(7) && pass.is_none()
+            })
+            .returning(|id, _, employee, _, _| {
+                // only the second one was confirmed by an escort
+                Ok((id == 6).then(|| crate::models::EscortAuthorization {
+                    id_authorization: id,
+                    station: "server_room".to_string(),
+                    id_zone: 3,
+                    id_employee: employee,
+                    id_pass: None,
+                    requested_at: Utc::now(),
+                    expires_at: Utc::now() + chrono::Duration::minutes(1),
+                    confirmed_by: Some(1),
+                    confirmed_at: Some(Utc::now()),
+                    completed_at: Some(Utc::now()),
+                }))
+            });
+        mock_repo.expect_passback_state().returning(|_, _| Ok(None));
+        mock_repo.expect_add_access_log().times(1).returning(
+            |event_id, employee, direction, station, timestamp| {
+                Ok((
+                    crate::models::AccessLog {
+                        id_log: 30,
+                        id_employee: employee,
+                        direction,
+                        timestamp,
+                        event_id,
+                        station,
+                        id_door: Some(5),
+                    },
+                    true,
+                ))
+            },
+        );
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/access/ack", web::post().to(access_ack)),
+        )
+        .await;
+        let ack = |authorization_id: i32| {
+            test::TestRequest::post()
+                .uri("/access/ack")
+                .insert_header(station_header("server_room", "IN"))
+                .set_json(AccessAckRequest {
+                    event_id: None,
+                    employee_id: 7,
+                    pass_id: None,
+                    authorization_id: Some(authorization_id),
+                    direction: "IN".to_string(),
+                    station: Some("server_room".to_string()),
+                    timestamp: Utc::now(),
+                })
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, ack(5)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        let body: AccessAckResponse = test::read_body_json(resp).await;
+        assert_eq!(body.reason.as_deref(), Some("escort_not_confirmed"));
+
+        let body: AccessAckResponse = test::call_and_read_body_json(&app, ack(6)).await;
+        assert_eq!(body.id_log, Some(30));
+    }
+
+    #[actix_web::test]
+    async fn test_update_employee_is_audited() {
+        let updated = std::sync::Arc::new(std::sync::Mutex::new(false));
+        let read_updated = updated.clone();
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|id| Ok(Some(if id == 1 { "admin" } else { "manager" }.to_string())));
+        mock_repo.expect_get_employee().returning(move |id| {
+            let role = if *read_updated.lock().unwrap() {
+                "manager"
+            } else {
+                "employee"
+            };
+            Ok(Some(crate::models::Employee {
+                id_person: id,
+                first_name: "Anna".to_string(),
+                last_name: "Nowak".to_string(),
+                role: role.to_string(),
+                date_of_termination: None,
+                photo_path: None,
+                account_number: None,
+                login: Some("anna".to_string()),
+                archived_at: None,
+            }))
+        });
+        mock_repo
+            .expect_update_employee()
+            .withf(|_, req| {
+                req.password
+                    .as_deref()
+                    .is_some_and(|hash| hash.starts_with("pbkdf2-sha256$"))
+            })
+            .times(1)
+            .returning(move |_, _| {
+                *updated.lock().unwrap() = true;
+                Ok(())
+            });
+        mock_repo
+            .expect_add_audit_entry()
+            .withf(|entry| {
+                entry.actor == 1
+                    && entry.action == "update"
+                    && entry.target_id == Some(4)
+                    && entry.method == "PATCH"
+                    && entry.before == Some(serde_json::json!({"role": "employee"}))
+                    && entry.after
+                        == Some(serde_json::json!({"role": "manager", "password": "[redacted]"}))
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employees/{id}", web::patch().to(update_employee)),
+        )
+        .await;
+        let update = serde_json::json!({"role": "manager", "password": "hunter2"});
+
+        let req = test::TestRequest::patch()
+            .uri("/employees/4")
+            .set_json(&update)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        // only admins change employee records
+        let req = test::TestRequest::patch()
+            .uri("/employees/4")
+            .insert_header(session_header(2))
+            .set_json(&update)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::patch()
+            .uri("/employees/4")
+            .insert_header(session_header(1))
+            .set_json(&update)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_archive_employee_keeps_record() {
+        let archived = std::sync::Arc::new(std::sync::Mutex::new(false));
+        let read_archived = archived.clone();
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+        mock_repo.expect_get_employee().returning(move |id| {
+            let archived_at = read_archived.lock().unwrap().then(Utc::now);
+            Ok(Some(crate::models::Employee {
+                id_person: id,
+                first_name: "Anna".to_string(),
+                last_name: "Nowak".to_string(),
+                role: "employee".to_string(),
+                date_of_termination: None,
+                photo_path: None,
+                account_number: None,
+                login: None,
+                archived_at,
+            }))
+        });
+        mock_repo
+            .expect_archive_employee()
+            .withf(|id, archived_by, _| *id == 4 && *archived_by == 1)
+            .returning(move |_, _, _| {
+                let mut archived = archived.lock().unwrap();
+                let count = if *archived { 0 } else { 1 };
+                *archived = true;
+                Ok(count)
+            });
+        mock_repo
+            .expect_add_audit_entry()
+            .withf(|entry| {
+                entry.action == "archive"
+                    && entry.target_id == Some(4)
+                    && entry.before == Some(serde_json::json!({"archived_at": null}))
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+        mock_repo.expect_update_employee_photo().never();
+        mock_repo
+            .expect_get_employees()
+            .withf(|include_archived| *include_archived)
+            .times(1)
+            .returning(|_| Ok(vec![]));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employees", web::get().to(get_employees))
+                .route("/employees/{id}", web::delete().to(archive_employee))
+                .route(
+                    "/employees/{id}/photo",
+                    web::post().to(upload_employee_photo),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/employees/4")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+
+        let req = test::TestRequest::delete()
+            .uri("/employees/4")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        // no new face data for someone who is archived
+        let req = test::TestRequest::post()
+            .uri("/employees/4/photo")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+
+        let req = test::TestRequest::get()
+            .uri("/employees?include_archived=true")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/employees?include_archived=true")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_erase_only_archived_employee() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+        mock_repo.expect_get_employee().returning(|id| {
+            Ok(Some(crate::models::Employee {
+                id_person: id,
+                first_name: "Anna".to_string(),
+                last_name: "Nowak".to_string(),
+                role: "employee".to_string(),
+                date_of_termination: None,
+                photo_path: None,
+                account_number: None,
+                login: None,
+                archived_at: (id == 5).then(Utc::now),
+            }))
+        });
+        mock_repo
+            .expect_erase_employee()
+            .withf(|id, erased_by, _| *id == 5 && *erased_by == 1)
+            .times(1)
+            .returning(|_, _, _| Ok(Some(Default::default())));
+        mock_repo
+            .expect_add_audit_entry()
+            .withf(|entry| {
+                entry.action == "erase"
+                    && entry.target_id == Some(5)
+                    && entry.before.is_none()
+                    && entry.after.is_none()
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employees/{id}/erase", web::post().to(erase_employee)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/employees/4/erase")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+
+        let req = test::TestRequest::post()
+            .uri("/employees/5/erase")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+    }
 
-fn log_failed_attempt(employee_id: i32, reason: &str, temp_photo_path: &str) {
-    if let Err(e) = fs::create_dir_all("uploads/failed_attempts") {
-        eprintln!("Failed to create directory: {}", e);
-        return;
+    #[actix_web::test]
+    async fn test_export_and_erase_leave_files_outside_archive_alone() {
+        let outside = std::env::temp_dir().join(format!("outside-{}.jpg", Uuid::new_v4()));
+        fs::write(&outside, b"not a frame").unwrap();
+        let outside_path = outside.to_string_lossy().to_string();
+
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+        let path = outside_path.clone();
+        mock_repo.expect_get_subject_records().returning(move |id| {
+            Ok(vec![
+                (
+                    "employee".to_string(),
+                    serde_json::json!([{"id_person": id, "photo_path": null}]),
+                ),
+                (
+                    "failed_attempts".to_string(),
+                    serde_json::json!([{"id_attempt": 1, "image_path": path}]),
+                ),
+            ])
+        });
+        mock_repo.expect_get_employee().returning(|id| {
+            Ok(Some(crate::models::Employee {
+                id_person: id,
+                first_name: "Anna".to_string(),
+                last_name: "Nowak".to_string(),
+                role: "employee".to_string(),
+                date_of_termination: None,
+                photo_path: None,
+                account_number: None,
+                login: None,
+                archived_at: Some(Utc::now()),
+            }))
+        });
+        let path = outside_path.clone();
+        mock_repo.expect_erase_employee().returning(move |_, _, _| {
+            Ok(Some(crate::models::ErasedFiles {
+                photo_path: None,
+                frames: vec![path.clone()],
+            }))
+        });
+        mock_repo.expect_add_audit_entry().returning(|_| Ok(()));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/employees/{id}/export", web::get().to(export_employee))
+                .route("/employees/{id}/erase", web::post().to(erase_employee)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/employees/5/export")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body = test::read_body(resp).await;
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(body.to_vec())).unwrap();
+        assert!(!archive.file_names().any(|name| name.starts_with("images/")));
+
+        let req = test::TestRequest::post()
+            .uri("/employees/5/erase")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(outside.exists());
+        fs::remove_file(&outside).unwrap();
     }
 
-    let filename = std::path::Path::new(temp_photo_path)
-        .file_name()
-        .unwrap_or_default()
-        .to_str()
-        .unwrap_or_default();
-    let new_path = format!("uploads/failed_attempts/{}", filename);
+    #[actix_web::test]
+    async fn test_report_error_needs_station_and_archived_image() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_stations(&mut mock_repo, &["gate-1"]);
+        mock_repo
+            .expect_add_failed_attempt()
+            .withf(|attempt| {
+                attempt.id_employee == Some(5)
+                    && attempt.station.as_deref() == Some("gate-1")
+                    && attempt.direction.as_deref() == Some("OUT")
+                    && attempt.image_path.as_deref() == Some("uploads/failed_attempts/a.jpg")
+            })
+            .times(1)
+            .returning(|_| Ok(1));
 
-    if let Err(e) = fs::rename(temp_photo_path, &new_path) {
-        // Fallback copy
-        if let Err(e) = fs::copy(temp_photo_path, &new_path) {
-            eprintln!("Failed to copy failed attempt photo: {}", e);
-            return;
-        }
-        let _ = fs::remove_file(temp_photo_path);
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/log_error", web::post().to(report_error)),
+        )
+        .await;
+
+        let body = |image: &str| serde_json::json!({"employee": "5", "error_description": "camera_error", "image": image});
+        let req = test::TestRequest::post()
+            .uri("/log_error")
+            .set_json(body("uploads/failed_attempts/a.jpg"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/log_error")
+            .insert_header(station_header("gate-1", "OUT"))
+            .set_json(body("../../backend/init.sql"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/log_error")
+            .insert_header(station_header("gate-1", "OUT"))
+            .set_json(body("uploads/failed_attempts/a.jpg"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
     }
 
-    let req = CreateErrorLogRequest {
-        employee: employee_id.to_string(),
-        error_description: reason.to_string(),
-        image: Some(new_path),
-    };
+    #[actix_web::test]
+    async fn test_update_work_hours_rejects_end_before_start() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("manager".to_string())));
+        mock_repo
+            .expect_get_work_hours_record()
+            .with(mockall::predicate::eq(7))
+            .returning(|_| {
+                Ok(Some(crate::models::WorkHours {
+                    id_record: 7,
+                    id_employee: 1,
+                    time_start: "2026-01-29T08:00:00Z".parse().unwrap(),
+                    time_end: Some("2026-01-29T16:00:00Z".parse().unwrap()),
+                }))
+            });
+        mock_repo.expect_update_work_hours().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/hours/{id}", web::patch().to(update_work_hours)),
+        )
+        .await;
+
+        let req = test::TestRequest::patch()
+            .uri("/hours/7")
+            .insert_header(session_header(1))
+            .set_json(UpdateWorkHoursRequest {
+                time_start: None,
+                time_end: Some(Some("2026-01-29T07:00:00Z".parse().unwrap())),
+                reason: "forgot to clock out".to_string(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
 
-    if let Err(e) = logger::log_error(req) {
-        eprintln!("Failed to log error: {}", e);
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::db::MockDatabaseRepository;
-    use actix_web::{test, App};
+    #[actix_web::test]
+    async fn test_update_work_hours_null_end_reopens_shift() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("manager".to_string())));
+        mock_repo.expect_get_work_hours_record().returning(|_| {
+            Ok(Some(crate::models::WorkHours {
+                id_record: 7,
+                id_employee: 1,
+                time_start: "2026-01-29T08:00:00Z".parse().unwrap(),
+                time_end: Some("2026-01-29T16:00:00Z".parse().unwrap()),
+            }))
+        });
+        mock_repo
+            .expect_update_work_hours()
+            .withf(|id, _, time_end, _, _| *id == 7 && time_end.is_none())
+            .returning(|_, _, _, _, _| Ok(Some(1)));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/hours/{id}", web::patch().to(update_work_hours)),
+        )
+        .await;
+
+        let req = test::TestRequest::patch()
+            .uri("/hours/7")
+            .insert_header(session_header(1))
+            .set_json(serde_json::json!({"time_end": null, "reason": "still on shift"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+    }
 
     #[actix_web::test]
-    async fn test_get_employees() {
+    async fn test_work_hours_require_role() {
         let mut mock_repo = MockDatabaseRepository::new();
-        mock_repo.expect_get_employees().returning(|| Ok(vec![])); // Return empty list
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("employee".to_string())));
+        mock_repo.expect_delete_work_hours().never();
+        mock_repo.expect_get_work_hours_history().never();
 
-        let app_data = web::Data::new(AppState {
-            db: Box::new(mock_repo),
-        });
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
         let app = test::init_service(
             App::new()
                 .app_data(app_data)
-                .route("/employees", web::get().to(get_employees)),
+                .route("/hours/{id}", web::delete().to(delete_work_hours))
+                .route("/hours/{id}/history", web::get().to(get_work_hours_history)),
         )
         .await;
 
-        let req = test::TestRequest::get().uri("/employees").to_request();
+        let req = test::TestRequest::delete()
+            .uri("/hours/7")
+            .insert_header(session_header(3))
+            .set_json(DeleteWorkHoursRequest {
+                reason: "duplicate".to_string(),
+            })
+            .to_request();
         let resp = test::call_service(&app, req).await;
 
-        assert!(resp.status().is_success());
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get()
+            .uri("/hours/7/history")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/hours/7/history")
+            .insert_header(session_header(3))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
     }
 
     #[actix_web::test]
-    async fn test_check_qr_found() {
+    async fn test_create_work_hours_overlap_conflict() {
         let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
         mock_repo
             .expect_get_employee_by_id()
-            .with(mockall::predicate::eq(123))
-            .returning(|_| Ok(Some((123, "John".to_string(), "Doe".to_string()))));
+            .returning(|_| Ok(Some((1, "John".to_string(), "Doe".to_string()))));
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+        mock_repo
+            .expect_create_work_hours()
+            .returning(|_, _, _, _, _| Ok(None));
 
-        let app_data = web::Data::new(AppState {
-            db: Box::new(mock_repo),
-        });
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
         let app = test::init_service(
             App::new()
                 .app_data(app_data)
-                .route("/employee/check_qr", web::post().to(check_qr)),
+                .route("/hours", web::post().to(create_work_hours)),
         )
         .await;
 
         let req = test::TestRequest::post()
-            .uri("/employee/check_qr")
-            .set_json(CheckQrRequest {
-                employee_id: 123,
-                direction: "IN".to_string(),
+            .uri("/hours")
+            .insert_header(session_header(2))
+            .set_json(CreateWorkHoursRequest {
+                id_employee: 1,
+                time_start: "2026-01-29T08:00:00Z".parse().unwrap(),
+                time_end: Some("2026-01-29T16:00:00Z".parse().unwrap()),
+                reason: "station was offline".to_string(),
             })
             .to_request();
         let resp = test::call_service(&app, req).await;
 
-        assert!(resp.status().is_success());
-        let body: CheckQrResponse = test::read_body_json(resp).await;
-        assert!(body.exists);
-        assert_eq!(body.first_name, Some("John".to_string()));
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
     }
 
     #[actix_web::test]
-    async fn test_check_qr_not_found() {
+    async fn test_delete_work_hours_requires_reason() {
         let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
         mock_repo
-            .expect_get_employee_by_id()
-            .with(mockall::predicate::eq(999))
-            .returning(|_| Ok(None));
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+        mock_repo.expect_delete_work_hours().never();
 
-        let app_data = web::Data::new(AppState {
-            db: Box::new(mock_repo),
-        });
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
         let app = test::init_service(
             App::new()
                 .app_data(app_data)
-                .route("/employee/check_qr", web::post().to(check_qr)),
+                .route("/hours/{id}", web::delete().to(delete_work_hours)),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/hours/7")
+            .insert_header(session_header(2))
+            .set_json(DeleteWorkHoursRequest {
+                reason: "   ".to_string(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_set_break_rules_rejects_duplicates() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|id| Ok(Some(if id == 1 { "admin" } else { "manager" }.to_string())));
+        mock_repo.expect_set_break_rules().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/hours/break_rules", web::put().to(set_break_rules)),
+        )
+        .await;
+
+        let rule = BreakRule {
+            min_shift_minutes: 360,
+            required_break_minutes: 15,
+        };
+        let req = test::TestRequest::put()
+            .uri("/hours/break_rules")
+            .insert_header(session_header(2))
+            .set_json(vec![rule.clone()])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::put()
+            .uri("/hours/break_rules")
+            .insert_header(session_header(1))
+            .set_json(vec![rule.clone(), rule])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_breaks_need_station_or_security_session() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        expect_stations(&mut mock_repo, &["gate-1"]);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("employee".to_string())));
+        mock_repo
+            .expect_start_break()
+            .withf(|id| *id == 5)
+            .times(1)
+            .returning(|_| Ok(1));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/hours/break/start", web::post().to(start_break)),
         )
         .await;
 
+        let body = EmployeeIdRequest { id_employee: 5 };
         let req = test::TestRequest::post()
-            .uri("/employee/check_qr")
-            .set_json(CheckQrRequest {
-                employee_id: 999,
-                direction: "IN".to_string(),
+            .uri("/hours/break/start")
+            .set_json(&body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/hours/break/start")
+            .insert_header(session_header(5))
+            .set_json(&body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::post()
+            .uri("/hours/break/start")
+            .insert_header(station_header("gate-1", "OUT"))
+            .set_json(&body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_failed_attempts_forbidden_for_employee_role() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .with(mockall::predicate::eq(5))
+            .returning(|_| Ok(Some("employee".to_string())));
+        mock_repo.expect_get_failed_attempts().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(App::new().app_data(app_data).route(
+            "/security/failed_attempts",
+            web::get().to(get_failed_attempts),
+        ))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/security/failed_attempts?reason=face_mismatched")
+            .insert_header(session_header(5))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_token_query_parameter_and_actor_header_are_not_credentials() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo.expect_get_employee_role().never();
+        mock_repo.expect_get_failed_attempts().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(App::new().app_data(app_data).route(
+            "/security/failed_attempts",
+            web::get().to(get_failed_attempts),
+        ))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/security/failed_attempts?token=session-1")
+            .insert_header(("X-Actor-Id", "1"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_failed_attempts_rejects_page_past_offset_range() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+        mock_repo.expect_get_failed_attempts().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(App::new().app_data(app_data).route(
+            "/security/failed_attempts",
+            web::get().to(get_failed_attempts),
+        ))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/security/failed_attempts?page=9223372036854775807&page_size=500")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_audit_log_rejects_page_past_offset_range() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+        mock_repo.expect_get_audit_entries().never();
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(
+            App::new()
+                .app_data(app_data)
+                .route("/audit", web::get().to(get_audit_log)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/audit?page=9223372036854775807&page_size=500")
+            .insert_header(session_header(1))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_failed_attempts_filters_and_paging() {
+        let mut mock_repo = MockDatabaseRepository::new();
+        expect_sessions(&mut mock_repo);
+        mock_repo
+            .expect_get_employee_role()
+            .returning(|_| Ok(Some("admin".to_string())));
+        mock_repo
+            .expect_get_failed_attempts()
+            .withf(|filter, page, page_size| {
+                filter.employee_id == Some(7)
+                    && filter.from == Some("2026-01-29T00:00:00Z".parse().unwrap())
+                    && *page == 2
+                    && *page_size == 500
             })
+            .returning(|_, _, _| Ok((vec![], 0)));
+
+        let app_data = web::Data::new(AppState::new(Box::new(mock_repo)));
+        let app = test::init_service(App::new().app_data(app_data).route(
+            "/security/failed_attempts",
+            web::get().to(get_failed_attempts),
+        ))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/security/failed_attempts?employee_id=7&from=2026-01-29T00:00:00Z&page=2&page_size=9999")
+            .insert_header(session_header(1))
             .to_request();
         let resp = test::call_service(&app, req).await;
 
         assert!(resp.status().is_success());
-        let body: CheckQrResponse = test::read_body_json(resp).await;
-        assert!(!body.exists);
+        let body: FailedAttemptPage = test::read_body_json(resp).await;
+        assert_eq!(body.page_size, 500);
     }
 }
@@ -0,0 +1,274 @@
+use crate::models::AccessSchedule;
+use crate::routes::AppState;
+use crate::timezone::site_tz;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+/// How far ahead the next allowed window is looked for.
+const LOOKAHEAD_DAYS: i64 = 14;
+
+type Window = (DateTime<Utc>, DateTime<Utc>);
+
+#[derive(Debug, PartialEq)]
+pub enum ScheduleCheck {
+    Allowed,
+    /// `next_window` is None if nothing opens within the lookahead
+    Outside {
+        next_window: Option<DateTime<Utc>>,
+    },
+}
+
+/// A time skipped when the clocks go forward becomes the first minute after the gap, so a
+/// window starting or ending in it is moved rather than dropped.
+fn local(tz: Tz, day: NaiveDate, time: chrono::NaiveTime) -> Option<DateTime<Utc>> {
+    let mut naive = day.and_time(time);
+    for _ in 0..24 * 60 {
+        if let Some(dt) = tz.from_local_datetime(&naive).earliest() {
+            return Some(dt.with_timezone(&Utc));
+        }
+        naive = naive.with_second(0).unwrap_or(naive) + Duration::minutes(1);
+    }
+    None
+}
+
+/// Windows opened by `schedules` on the days from `from` on, in UTC. A window ending at
+/// or before its start runs past midnight, 00:00-00:00 is the whole day.
+fn windows(
+    schedules: &[&AccessSchedule],
+    holidays: &[NaiveDate],
+    from: NaiveDate,
+    days: i64,
+    tz: Tz,
+) -> Vec<Window> {
+    let mut result = Vec::new();
+    for offset in 0..days {
+        let day = from + Duration::days(offset);
+        let weekday = day.weekday().number_from_monday() as i32;
+        for s in schedules {
+            if !s.weekdays.contains(&weekday)
+                || s.valid_from.is_some_and(|d| day < d)
+                || s.valid_until.is_some_and(|d| day > d)
+                || (holidays.contains(&day) && !s.on_holidays)
+            {
+                continue;
+            }
+            let end_day = if s.end_time <= s.start_time {
+                day + Duration::days(1)
+            } else {
+                day
+            };
+            if let (Some(start), Some(end)) =
+                (local(tz, day, s.start_time), local(tz, end_day, s.end_time))
+            {
+                result.push((start, end));
+            }
+        }
+    }
+    merge(result)
+}
+
+fn merge(mut windows: Vec<Window>) -> Vec<Window> {
+    windows.sort();
+    let mut merged: Vec<Window> = Vec::new();
+    for (start, end) in windows {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn intersect(a: &[Window], b: &[Window]) -> Vec<Window> {
+    let mut result = Vec::new();
+    for &(a_start, a_end) in a {
+        for &(b_start, b_end) in b {
+            let (start, end) = (a_start.max(b_start), a_end.min(b_end));
+            if start < end {
+                result.push((start, end));
+            }
+        }
+    }
+    merge(result)
+}
+
+/// Decides whether `employee_id` may enter at `now`. Schedules come in two layers that
+/// both have to allow it: the employee's own schedules (or, if they have none, their
+/// role's) and the schedules of the zone. A layer without schedules doesn't restrict.
+pub fn evaluate(
+    schedules: &[AccessSchedule],
+    employee_id: i32,
+    holidays: &[NaiveDate],
+    now: DateTime<Utc>,
+    tz: Tz,
+) -> ScheduleCheck {
+    let own: Vec<&AccessSchedule> = schedules
+        .iter()
+        .filter(|s| s.id_employee == Some(employee_id))
+        .collect();
+    let person = if own.is_empty() {
+        schedules.iter().filter(|s| s.role.is_some()).collect()
+    } else {
+        own
+    };
+    let zone: Vec<&AccessSchedule> = schedules.iter().filter(|s| s.id_zone.is_some()).collect();
+
+    // from yesterday, its overnight windows may still be open
+    let from = now.with_timezone(&tz).date_naive() - Duration::days(1);
+    let mut allowed: Option<Vec<Window>> = None;
+    for layer in [person, zone] {
+        if layer.is_empty() {
+            continue;
+        }
+        let layer = windows(&layer, holidays, from, LOOKAHEAD_DAYS + 1, tz);
+        allowed = Some(match allowed {
+            Some(previous) => intersect(&previous, &layer),
+            None => layer,
+        });
+    }
+
+    match allowed {
+        None => ScheduleCheck::Allowed,
+        Some(windows) if windows.iter().any(|(s, e)| *s <= now && now < *e) => {
+            ScheduleCheck::Allowed
+        }
+        Some(windows) => ScheduleCheck::Outside {
+            next_window: windows.iter().map(|(s, _)| *s).find(|s| *s > now),
+        },
+    }
+}
+
+/// Checks the schedules that apply to the employee at `station`. Only entering is
+/// restricted, leaving outside the allowed hours is always possible.
+pub async fn check_schedule(
+    data: &AppState,
+    employee_id: i32,
+    station: Option<&str>,
+    direction: &str,
+) -> Result<ScheduleCheck, sqlx::Error> {
+    if direction != "IN" {
+        return Ok(ScheduleCheck::Allowed);
+    }
+    let schedules = data
+        .db
+        .get_applicable_schedules(employee_id, station.map(str::to_string))
+        .await?;
+    if schedules.is_empty() {
+        return Ok(ScheduleCheck::Allowed);
+    }
+
+    let now = Utc::now();
+    let today = now.with_timezone(&site_tz()).date_naive();
+    let holidays = data
+        .db
+        .get_holidays(
+            Some(today - Duration::days(1)),
+            Some(today + Duration::days(LOOKAHEAD_DAYS + 1)),
+        )
+        .await?;
+    let holidays: Vec<NaiveDate> = holidays.into_iter().map(|h| h.day).collect();
+    Ok(evaluate(&schedules, employee_id, &holidays, now, site_tz()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(weekdays: Vec<i32>, start: &str, end: &str) -> AccessSchedule {
+        AccessSchedule {
+            id_schedule: 1,
+            id_employee: None,
+            role: Some("employee".to_string()),
+            id_zone: None,
+            weekdays,
+            start_time: start.parse().unwrap(),
+            end_time: end.parse().unwrap(),
+            valid_from: None,
+            valid_until: None,
+            on_holidays: false,
+            created_by: 1,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn at(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_weekday_hours_and_next_window() {
+        let tz: Tz = "Europe/Warsaw".parse().unwrap();
+        let office = vec![schedule(vec![1, 2, 3, 4, 5], "07:00:00", "19:00:00")];
+
+        // Wednesday 10:00 in Warsaw
+        assert_eq!(
+            evaluate(&office, 5, &[], at("2026-03-04T09:00:00Z"), tz),
+            ScheduleCheck::Allowed
+        );
+        // Sunday 03:00, next window Monday 07:00 local
+        assert_eq!(
+            evaluate(&office, 5, &[], at("2026-03-08T02:00:00Z"), tz),
+            ScheduleCheck::Outside {
+                next_window: Some(at("2026-03-09T06:00:00Z"))
+            }
+        );
+        // Monday is a holiday, so Tuesday it is
+        let holidays = ["2026-03-09".parse().unwrap()];
+        assert_eq!(
+            evaluate(&office, 5, &holidays, at("2026-03-08T02:00:00Z"), tz),
+            ScheduleCheck::Outside {
+                next_window: Some(at("2026-03-10T06:00:00Z"))
+            }
+        );
+    }
+
+    #[test]
+    fn test_window_starting_in_dst_gap_moves_forward() {
+        let tz: Tz = "Europe/Warsaw".parse().unwrap();
+        // 29 March 2026 the clocks jump from 02:00 to 03:00, so 02:30 never happens
+        let early = vec![schedule(vec![7], "02:30:00", "05:00:00")];
+
+        // 03:10 local, the window opened at 03:00
+        assert_eq!(
+            evaluate(&early, 5, &[], at("2026-03-29T01:10:00Z"), tz),
+            ScheduleCheck::Allowed
+        );
+        // 01:30 local, before the gap
+        assert_eq!(
+            evaluate(&early, 5, &[], at("2026-03-29T00:30:00Z"), tz),
+            ScheduleCheck::Outside {
+                next_window: Some(at("2026-03-29T01:00:00Z"))
+            }
+        );
+    }
+
+    #[test]
+    fn test_own_schedule_overrides_role_and_zone_restricts() {
+        let tz: Tz = "UTC".parse().unwrap();
+        let all_week = vec![1, 2, 3, 4, 5, 6, 7];
+        let mut night = schedule(all_week.clone(), "22:00:00", "06:00:00");
+        night.role = None;
+        night.id_employee = Some(5);
+        let mut zone = schedule(all_week.clone(), "00:00:00", "04:00:00");
+        zone.role = None;
+        zone.id_zone = Some(2);
+        let schedules = vec![schedule(all_week, "08:00:00", "16:00:00"), night, zone];
+
+        // overnight window of employee 5 combined with the zone's 00-04
+        assert_eq!(
+            evaluate(&schedules, 5, &[], at("2026-03-04T01:00:00Z"), tz),
+            ScheduleCheck::Allowed
+        );
+        assert_eq!(
+            evaluate(&schedules, 5, &[], at("2026-03-04T23:00:00Z"), tz),
+            ScheduleCheck::Outside {
+                next_window: Some(at("2026-03-05T00:00:00Z"))
+            }
+        );
+        // others only have the role's day shift, which never overlaps the zone's hours
+        assert_eq!(
+            evaluate(&schedules, 6, &[], at("2026-03-04T10:00:00Z"), tz),
+            ScheduleCheck::Outside { next_window: None }
+        );
+    }
+}
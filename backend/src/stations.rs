@@ -0,0 +1,54 @@
+use actix_web::HttpRequest;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// A station that hasn't sent a heartbeat for `STATION_OFFLINE_SECONDS` (default 90,
+/// three missed beats) is shown as offline.
+pub fn offline_after() -> Duration {
+    let seconds = std::env::var("STATION_OFFLINE_SECONDS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(90);
+    Duration::seconds(seconds)
+}
+
+pub fn is_online(last_seen_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    last_seen_at.is_some_and(|seen| now - seen < offline_after())
+}
+
+/// Random token the station authenticates with. Only its hash is stored,
+/// so a lost token can't be shown again, only replaced.
+pub fn generate_token() -> String {
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+pub fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.trim().as_bytes()).to_vec()
+}
+
+/// Token from `Authorization: Bearer <token>`, the header stations already send.
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get("Authorization")?.to_str().ok()?;
+    let token = header.strip_prefix("Bearer ")?.trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_online_until_heartbeats_stop() {
+        let now: DateTime<Utc> = "2026-03-01T12:00:00Z".parse().unwrap();
+
+        assert!(is_online(Some(now - Duration::seconds(30)), now));
+        assert!(!is_online(Some(now - Duration::seconds(90)), now));
+        assert!(!is_online(None, now));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::models::{AccessEvent, NewFailedAttempt, Station, SyncEvent, SyncResult};
+use crate::routes::AppState;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Upper limit of events in one upload.
+pub const MAX_BATCH: usize = 500;
+
+/// Key stations sign offline events with, `STATION_SECRET`.
+pub fn secret() -> Option<Vec<u8>> {
+    std::env::var("STATION_SECRET")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(String::into_bytes)
+}
+
+/// `event_id|kind|station|employee|direction|unix time|reason`, missing values empty.
+fn signing_payload(station: &str, event: &SyncEvent) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|{}|{}",
+        event.event_id,
+        event.kind,
+        station,
+        event
+            .employee_id
+            .map(|id| id.to_string())
+            .unwrap_or_default(),
+        event.direction,
+        event.timestamp.timestamp(),
+        event.reason.as_deref().unwrap_or_default()
+    )
+}
+
+fn mac(station: &str, event: &SyncEvent, secret: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(signing_payload(station, event).as_bytes());
+    mac
+}
+
+/// HMAC-SHA256 of the signing payload, unpadded base64url. Only stations sign.
+#[cfg(test)]
+pub fn sign(station: &str, event: &SyncEvent, secret: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(mac(station, event, secret).finalize().into_bytes())
+}
+
+pub fn verify(station: &str, event: &SyncEvent, secret: &[u8]) -> bool {
+    match URL_SAFE_NO_PAD.decode(&event.signature) {
+        Ok(tag) => mac(station, event, secret).verify_slice(&tag).is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn result(event: &SyncEvent, status: &str, reason: Option<&str>, id: Option<i32>) -> SyncResult {
+    SyncResult {
+        event_id: event.event_id,
+        status: status.to_string(),
+        reason: reason.map(str::to_string),
+        id,
+    }
+}
+
+/// Stores one offline event. A known `event_id` gives `duplicate` with the original id,
+/// so an upload cut off halfway can simply be sent again. Acks from the past skip the
+/// break tracking, which works on the current time. Events must have the direction of the
+/// station that uploads them.
+pub async fn process_event(
+    data: &AppState,
+    station: &Station,
+    event: &SyncEvent,
+    secret: &[u8],
+    max_skew: chrono::Duration,
+    now: DateTime<Utc>,
+) -> SyncResult {
+    if !verify(&station.name, event, secret) {
+        return result(event, "rejected", Some("invalid_signature"), None);
+    }
+    if event.timestamp - now > max_skew {
+        return result(event, "rejected", Some("timestamp_in_future"), None);
+    }
+    if event.direction != station.direction {
+        return result(event, "rejected", Some("invalid_direction"), None);
+    }
+
+    match event.kind.as_str() {
+        "access_ack" => {
+            let employee_id = match event.employee_id {
+                Some(id) => id,
+                None => return result(event, "rejected", Some("missing_employee_id"), None),
+            };
+            match data
+                .db
+                .add_access_log(
+                    Some(event.event_id),
+                    employee_id,
+                    event.direction.clone(),
+                    Some(station.name.clone()),
+                    event.timestamp,
+                )
+                .await
+            {
+                Ok((log, true)) => {
+                    if data.events.has_listeners() {
+                        let mut live = AccessEvent::new("access_ack", Some(employee_id));
+                        live.station = Some(station.name.clone());
+                        live.direction = Some(event.direction.clone());
+                        live.timestamp = event.timestamp;
+                        data.events
+                            .publish(live.outcome(true, "acknowledged_offline", None));
+                    }
+                    result(event, "accepted", None, Some(log.id_log))
+                }
+                Ok((log, false))
+                    if log.id_employee == employee_id
+                        && log.direction == event.direction
+                        && log.timestamp == event.timestamp =>
+                {
+                    result(event, "duplicate", None, Some(log.id_log))
+                }
+                Ok(_) => result(event, "rejected", Some("event_id_conflict"), None),
+                Err(e) => {
+                    eprintln!("Database error: {}", e);
+                    result(event, "error", Some("database_error"), None)
+                }
+            }
+        }
+        "failed_attempt" => {
+            let reason = match event.reason.as_deref().filter(|r| !r.is_empty()) {
+                Some(reason) => reason,
+                None => return result(event, "rejected", Some("missing_reason"), None),
+            };
+            let attempt = NewFailedAttempt {
+                id_employee: event.employee_id,
+                reason: reason.to_string(),
+                similarity: event.similarity,
+                station: Some(station.name.clone()),
+                direction: Some(event.direction.clone()),
+                image_path: None,
+                timestamp: event.timestamp,
+            };
+            match data
+                .db
+                .add_synced_failed_attempt(event.event_id, attempt)
+                .await
+            {
+                Ok((id, true)) => result(event, "accepted", None, Some(id)),
+                Ok((id, false)) => result(event, "duplicate", None, Some(id)),
+                Err(e) => {
+                    eprintln!("Database error: {}", e);
+                    result(event, "error", Some("database_error"), None)
+                }
+            }
+        }
+        _ => result(event, "rejected", Some("unknown_kind"), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_covers_station_and_fields() {
+        let mut event = SyncEvent {
+            event_id: "6f1c1a52-3b8e-4c55-9a4e-0d2b7c9e8f10".parse().unwrap(),
+            kind: "access_ack".to_string(),
+            employee_id: Some(7),
+            direction: "IN".to_string(),
+            timestamp: "2026-01-29T08:00:00Z".parse().unwrap(),
+            reason: None,
+            similarity: None,
+            signature: String::new(),
+        };
+        event.signature = sign("station_in", &event, b"secret");
+
+        assert!(verify("station_in", &event, b"secret"));
+        // replaying another station's upload
+        assert!(!verify("station_out", &event, b"secret"));
+
+        event.employee_id = Some(8);
+        assert!(!verify("station_in", &event, b"secret"));
+    }
+}
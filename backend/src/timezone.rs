@@ -0,0 +1,142 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+const DEFAULT_SITE_TIMEZONE: &str = "Europe/Warsaw";
+
+/// Timezone of the site, taken from `SITE_TIMEZONE` (IANA name, e.g. "Europe/Warsaw").
+/// Everything is stored in UTC, this is only used for input without an offset and for output.
+pub fn site_tz() -> Tz {
+    std::env::var("SITE_TIMEZONE")
+        .ok()
+        .and_then(|name| name.parse::<Tz>().ok())
+        .unwrap_or_else(|| DEFAULT_SITE_TIMEZONE.parse().unwrap())
+}
+
+/// Interprets a naive local time in the site timezone. During the autumn DST change the
+/// hour repeats and the earlier instant is taken; times skipped in spring don't exist.
+pub fn from_site_local(naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+    site_tz()
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Parses an RFC 3339 timestamp with an offset, or a naive one ("2001-09-11T08:46:44")
+/// which is then taken as site local time. The latter is what older stations send.
+pub fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    value
+        .parse::<NaiveDateTime>()
+        .ok()
+        .and_then(from_site_local)
+}
+
+/// Serde adapter for `DateTime<Utc>` fields: written out in the site timezone with its
+/// offset, read from anything `parse_timestamp` accepts.
+pub mod site_time {
+    use super::{parse_timestamp, site_tz};
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(
+            &value
+                .with_timezone(&site_tz())
+                .to_rfc3339_opts(SecondsFormat::Secs, false),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        parse_timestamp(&value)
+            .ok_or_else(|| de::Error::custom(format!("invalid timestamp: {}", value)))
+    }
+}
+
+/// Same as `site_time`, for optional fields.
+pub mod site_time_opt {
+    use super::site_time;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(dt) => site_time::serialize(dt, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "site_time")] DateTime<Utc>);
+
+        let value = Option::<Wrapper>::deserialize(deserializer)?;
+        Ok(value.map(|Wrapper(dt)| dt))
+    }
+}
+
+/// Same as `site_time_opt`, but tells a missing field (None) from an explicit null
+/// (Some(None)), for updates that may clear a value.
+pub mod site_time_nullable {
+    use super::site_time_opt;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Option<DateTime<Utc>>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        site_time_opt::serialize(&value.flatten(), serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Option<DateTime<Utc>>>, D::Error> {
+        site_time_opt::deserialize(deserializer).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp_with_offset_and_naive() {
+        let with_offset = parse_timestamp("2026-01-29T09:00:00+01:00").unwrap();
+        assert_eq!(
+            with_offset,
+            Utc.with_ymd_and_hms(2026, 1, 29, 8, 0, 0).unwrap()
+        );
+
+        // naive input is site local time (Europe/Warsaw by default, CEST in July)
+        let naive = parse_timestamp("2026-07-01T10:00:00").unwrap();
+        assert_eq!(naive, Utc.with_ymd_and_hms(2026, 7, 1, 8, 0, 0).unwrap());
+
+        assert!(parse_timestamp("yesterday").is_none());
+    }
+
+    #[test]
+    fn test_naive_local_time_across_dst() {
+        // 02:30 doesn't exist on 2026-03-29 in Warsaw, clocks jump from 02:00 to 03:00
+        assert!(from_site_local("2026-03-29T02:30:00".parse().unwrap()).is_none());
+
+        // 02:30 happens twice on 2026-10-25, the first (CEST) one is taken
+        let repeated = from_site_local("2026-10-25T02:30:00".parse().unwrap()).unwrap();
+        assert_eq!(
+            repeated,
+            Utc.with_ymd_and_hms(2026, 10, 25, 0, 30, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+/// Prefix of the code shown by the phone app: `CT1.<employee>.<6 digits>`.
+const PREFIX: &str = "CT1";
+const ISSUER: &str = "Cerberus";
+
+pub const PERIOD_SECONDS: i64 = 30;
+pub const DIGITS: u32 = 6;
+/// Codes from one step before or after are accepted too, phone clocks drift.
+const WINDOW_STEPS: i64 = 1;
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// `otpauth://` URI understood by authenticator apps, usually shown as a QR during enrollment.
+pub fn provisioning_uri(secret: &[u8], employee_id: i32) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{id}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = ISSUER,
+        id = employee_id,
+        secret = encode_secret(secret),
+        digits = DIGITS,
+        period = PERIOD_SECONDS
+    )
+}
+
+pub fn step_at(now: DateTime<Utc>) -> i64 {
+    now.timestamp().div_euclid(PERIOD_SECONDS)
+}
+
+/// RFC 6238 code for a time step (HMAC-SHA1, dynamic truncation).
+pub fn code_at(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+pub fn is_totp_code(qr: &str) -> bool {
+    qr.trim().starts_with("CT1.")
+}
+
+/// Splits a scanned `CT1.<employee>.<digits>` into the employee id and the code.
+pub fn parse(qr: &str) -> Option<(i32, &str)> {
+    let mut parts = qr.trim().split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(PREFIX), Some(employee_id), Some(code), None)
+            if code.len() == DIGITS as usize && code.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            Some((employee_id.parse().ok()?, code))
+        }
+        _ => None,
+    }
+}
+
+/// Time step the code belongs to, if it is valid around `now`. The caller still has to
+/// make sure the step wasn't used before.
+pub fn verify(secret: &[u8], code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let code: u32 = code.parse().ok()?;
+    let current = step_at(now);
+    (current - WINDOW_STEPS..=current + WINDOW_STEPS).find(|step| code_at(secret, *step) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B, SHA1 seed
+    const SEED: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // the RFC lists 8 digits, these are their last 6
+        assert_eq!(code_at(SEED, 59 / 30), 287082);
+        assert_eq!(code_at(SEED, 1111111109 / 30), 81804);
+        assert_eq!(code_at(SEED, 1234567890 / 30), 5924);
+    }
+
+    #[test]
+    fn test_verify_window_and_parse() {
+        let now = DateTime::from_timestamp(1111111109, 0).unwrap();
+        let previous = format!("{:06}", code_at(SEED, step_at(now) - 1));
+        let too_old = format!("{:06}", code_at(SEED, step_at(now) - 2));
+
+        assert_eq!(verify(SEED, "081804", now), Some(step_at(now)));
+        assert_eq!(verify(SEED, &previous, now), Some(step_at(now) - 1));
+        assert_eq!(verify(SEED, &too_old, now), None);
+
+        assert_eq!(parse("CT1.5.081804"), Some((5, "081804")));
+        assert_eq!(parse("CT1.5.81804"), None);
+        assert_eq!(parse("CB1.5.081804"), None);
+    }
+}
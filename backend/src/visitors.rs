@@ -0,0 +1,128 @@
+use crate::models::{Visitor, VisitorPass};
+use crate::routes::AppState;
+use crate::zones;
+use chrono::{DateTime, Utc};
+
+/// Reason a pass doesn't let its visitor in at `now`. The visit window is checked as
+/// well as the pass, a pass issued for a visit that was later shortened stops with it.
+/// Used up passes still let the visitor out.
+pub fn pass_denied(
+    visitor: &Visitor,
+    pass: &VisitorPass,
+    direction: &str,
+    now: DateTime<Utc>,
+) -> Option<&'static str> {
+    if pass.revoked_at.is_some() {
+        return Some("pass_revoked");
+    }
+    if pass.expires_at <= now || visitor.valid_until <= now {
+        return Some("pass_expired");
+    }
+    if visitor.valid_from > now {
+        return Some("pass_not_yet_valid");
+    }
+    if direction == "IN" && pass.max_uses.is_some_and(|max| pass.uses >= max) {
+        return Some("pass_used_up");
+    }
+    None
+}
+
+/// Loads the pass and its visitor and checks both. `visitor_id` is the one claimed by
+/// the scanned code, if any. Visitors may go wherever their host may.
+pub async fn check_pass(
+    data: &AppState,
+    pass_id: i32,
+    visitor_id: Option<i32>,
+    station: Option<&str>,
+    direction: &str,
+) -> Result<Result<(Visitor, VisitorPass), &'static str>, sqlx::Error> {
+    let pass = match data.db.get_visitor_pass(pass_id).await? {
+        Some(pass) if visitor_id.is_none_or(|id| id == pass.id_visitor) => pass,
+        _ => return Ok(Err("invalid_pass")),
+    };
+    let visitor = match data.db.get_visitor(pass.id_visitor).await? {
+        Some(visitor) => visitor,
+        None => return Ok(Err("invalid_pass")),
+    };
+    if let Some(reason) = pass_denied(&visitor, &pass, direction, Utc::now()) {
+        return Ok(Err(reason));
+    }
+    if let Some(reason) = zones::check_zone(data, visitor.id_host, station, direction).await? {
+        return Ok(Err(reason));
+    }
+    Ok(Ok((visitor, pass)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn visit() -> (Visitor, VisitorPass) {
+        let visitor = Visitor {
+            id_visitor: 3,
+            first_name: "Jan".to_string(),
+            last_name: "Kowalski".to_string(),
+            company: Some("Serwis Wind".to_string()),
+            kind: "contractor".to_string(),
+            id_host: 1,
+            valid_from: at("2026-05-04T07:00:00Z"),
+            valid_until: at("2026-05-04T15:00:00Z"),
+            photo_path: None,
+            face_enrolled: false,
+            created_by: 1,
+            created_at: at("2026-05-01T10:00:00Z"),
+        };
+        let pass = VisitorPass {
+            id_pass: 9,
+            id_visitor: 3,
+            max_uses: Some(1),
+            uses: 0,
+            issued_at: at("2026-05-01T10:00:00Z"),
+            expires_at: at("2026-05-04T15:00:00Z"),
+            revoked_by: None,
+            revoked_at: None,
+        };
+        (visitor, pass)
+    }
+
+    #[test]
+    fn test_pass_only_within_visit() {
+        let (visitor, pass) = visit();
+
+        assert_eq!(
+            pass_denied(&visitor, &pass, "IN", at("2026-05-04T06:59:00Z")),
+            Some("pass_not_yet_valid")
+        );
+        assert_eq!(
+            pass_denied(&visitor, &pass, "IN", at("2026-05-04T09:00:00Z")),
+            None
+        );
+        assert_eq!(
+            pass_denied(&visitor, &pass, "IN", at("2026-05-04T15:00:00Z")),
+            Some("pass_expired")
+        );
+    }
+
+    #[test]
+    fn test_single_use_pass_still_lets_out() {
+        let (visitor, mut pass) = visit();
+        pass.uses = 1;
+        let now = at("2026-05-04T09:00:00Z");
+
+        assert_eq!(
+            pass_denied(&visitor, &pass, "IN", now),
+            Some("pass_used_up")
+        );
+        assert_eq!(pass_denied(&visitor, &pass, "OUT", now), None);
+
+        pass.revoked_at = Some(now);
+        assert_eq!(
+            pass_denied(&visitor, &pass, "OUT", now),
+            Some("pass_revoked")
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::models::{BreakRule, WorkBreak, WorkHours, WorkHoursReport};
+use chrono::{DateTime, Utc};
+
+/// Minutes of break taken inside a shift. Breaks are clamped to the shift bounds,
+/// a break that was never closed counts until the end of the shift and overlapping
+/// breaks are merged so no minute is counted twice.
+fn break_minutes(
+    shift_start: DateTime<Utc>,
+    shift_end: DateTime<Utc>,
+    breaks: &[&WorkBreak],
+) -> i64 {
+    let mut intervals: Vec<(DateTime<Utc>, DateTime<Utc>)> = breaks
+        .iter()
+        .map(|b| {
+            let start = b.break_start.max(shift_start);
+            let end = b.break_end.unwrap_or(shift_end).min(shift_end);
+            (start, end)
+        })
+        .filter(|(start, end)| end > start)
+        .collect();
+    intervals.sort();
+
+    let mut total = 0;
+    let mut current: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    for (start, end) in intervals {
+        current = match current {
+            Some((cur_start, cur_end)) if start <= cur_end => Some((cur_start, cur_end.max(end))),
+            Some((cur_start, cur_end)) => {
+                total += (cur_end - cur_start).num_minutes();
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    if let Some((cur_start, cur_end)) = current {
+        total += (cur_end - cur_start).num_minutes();
+    }
+    total
+}
+
+fn required_break_minutes(shift_minutes: i64, rules: &[BreakRule]) -> i64 {
+    rules
+        .iter()
+        .filter(|r| shift_minutes >= r.min_shift_minutes as i64)
+        .map(|r| r.required_break_minutes as i64)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Worked time per shift with breaks subtracted. The breaks required by the rules count
+/// as working time (Kodeks pracy art. 134), so only break time beyond them is deducted.
+pub fn build_report(
+    hours: &[WorkHours],
+    breaks: &[WorkBreak],
+    rules: &[BreakRule],
+) -> Vec<WorkHoursReport> {
+    hours
+        .iter()
+        .map(|shift| {
+            let shift_breaks: Vec<&WorkBreak> = breaks
+                .iter()
+                .filter(|b| b.id_record == shift.id_record)
+                .collect();
+
+            let (taken, required, worked) = match shift.time_end {
+                Some(end) => {
+                    let total = (end - shift.time_start).num_minutes();
+                    let taken = break_minutes(shift.time_start, end, &shift_breaks);
+                    let required = required_break_minutes(total, rules);
+                    (
+                        taken,
+                        required,
+                        Some((total - (taken - required).max(0)).max(0)),
+                    )
+                }
+                None => (0, 0, None),
+            };
+
+            WorkHoursReport {
+                id_record: shift.id_record,
+                id_employee: shift.id_employee,
+                time_start: shift.time_start,
+                time_end: shift.time_end,
+                break_minutes: taken,
+                required_break_minutes: required,
+                worked_minutes: worked,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timezone::parse_timestamp;
+
+    // site local time, Europe/Warsaw unless SITE_TIMEZONE says otherwise
+    fn ts(s: &str) -> DateTime<Utc> {
+        parse_timestamp(s).unwrap()
+    }
+
+    fn shift(start: &str, end: &str) -> WorkHours {
+        WorkHours {
+            id_record: 1,
+            id_employee: 1,
+            time_start: ts(start),
+            time_end: Some(ts(end)),
+        }
+    }
+
+    fn rules() -> Vec<BreakRule> {
+        vec![
+            BreakRule {
+                min_shift_minutes: 360,
+                required_break_minutes: 15,
+            },
+            BreakRule {
+                min_shift_minutes: 540,
+                required_break_minutes: 30,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_breaks_are_subtracted_and_clamped_to_shift() {
+        let hours = vec![shift("2026-01-29T08:00:00", "2026-01-29T16:00:00")];
+        let breaks = vec![
+            WorkBreak {
+                id_break: 1,
+                id_record: 1,
+                break_start: ts("2026-01-29T12:00:00"),
+                break_end: Some(ts("2026-01-29T12:40:00")),
+            },
+            WorkBreak {
+                id_break: 2,
+                id_record: 1,
+                break_start: ts("2026-01-29T15:50:00"),
+                break_end: None,
+            },
+        ];
+
+        let report = build_report(&hours, &breaks, &rules());
+
+        assert_eq!(report[0].break_minutes, 50);
+        assert_eq!(report[0].required_break_minutes, 15);
+        assert_eq!(report[0].worked_minutes, Some(480 - (50 - 15)));
+    }
+
+    #[test]
+    fn test_mandatory_break_counts_as_working_time() {
+        let hours = vec![shift("2026-01-29T08:00:00", "2026-01-29T18:00:00")];
+        let breaks = vec![WorkBreak {
+            id_break: 1,
+            id_record: 1,
+            break_start: ts("2026-01-29T12:00:00"),
+            break_end: Some(ts("2026-01-29T12:20:00")),
+        }];
+
+        let report = build_report(&hours, &breaks, &rules());
+
+        assert_eq!(report[0].break_minutes, 20);
+        assert_eq!(report[0].required_break_minutes, 30);
+        assert_eq!(report[0].worked_minutes, Some(600));
+    }
+
+    #[test]
+    fn test_overlapping_breaks_are_counted_once() {
+        let hours = vec![shift("2026-01-29T08:00:00", "2026-01-29T16:00:00")];
+        let brk = |id, start, end| WorkBreak {
+            id_break: id,
+            id_record: 1,
+            break_start: ts(start),
+            break_end: Some(ts(end)),
+        };
+        let breaks = vec![
+            brk(1, "2026-01-29T12:00:00", "2026-01-29T12:30:00"),
+            brk(2, "2026-01-29T12:15:00", "2026-01-29T12:45:00"),
+            brk(3, "2026-01-29T12:45:00", "2026-01-29T13:00:00"),
+            brk(4, "2026-01-29T14:00:00", "2026-01-29T14:10:00"),
+        ];
+
+        let report = build_report(&hours, &breaks, &rules());
+
+        assert_eq!(report[0].break_minutes, 70);
+    }
+
+    #[test]
+    fn test_night_shift_across_dst_change() {
+        // clocks go back at 03:00 on 2026-10-25, so 22:00 -> 06:00 is 9 hours, not 8
+        let hours = vec![shift("2026-10-24T22:00:00", "2026-10-25T06:00:00")];
+
+        let report = build_report(&hours, &[], &rules());
+
+        assert_eq!(report[0].required_break_minutes, 30);
+        assert_eq!(report[0].worked_minutes, Some(540));
+    }
+}
@@ -0,0 +1,24 @@
+use crate::routes::AppState;
+
+/// Reason to turn the employee away at `station`, if it guards a door into a zone
+/// they have no permission for. Only entering is checked, nobody is kept from
+/// leaving a zone. Stations without a door let everyone through.
+pub async fn check_zone(
+    data: &AppState,
+    employee_id: i32,
+    station: Option<&str>,
+    direction: &str,
+) -> Result<Option<&'static str>, sqlx::Error> {
+    let station = match station {
+        Some(station) if direction == "IN" => station,
+        _ => return Ok(None),
+    };
+    match data
+        .db
+        .zone_permitted(employee_id, station.to_string())
+        .await?
+    {
+        Some(false) => Ok(Some("zone_not_permitted")),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,27 @@
+-- One-off upgrade for databases created before timestamps became TIMESTAMPTZ.
+-- Hours, breaks and their audit trail were written by the backend in UTC. Only
+-- access_logs.timestamp came from the stations in site local time, adjust its zone
+-- if the site isn't in Poland.
+-- psql "$DATABASE_URL" -f upgrade_timestamptz.sql
+
+BEGIN;
+
+ALTER TABLE hours
+  ALTER COLUMN time_start TYPE TIMESTAMPTZ USING time_start AT TIME ZONE 'UTC',
+  ALTER COLUMN time_end TYPE TIMESTAMPTZ USING time_end AT TIME ZONE 'UTC';
+
+ALTER TABLE breaks
+  ALTER COLUMN break_start TYPE TIMESTAMPTZ USING break_start AT TIME ZONE 'UTC',
+  ALTER COLUMN break_end TYPE TIMESTAMPTZ USING break_end AT TIME ZONE 'UTC';
+
+ALTER TABLE hours_audit
+  ALTER COLUMN old_time_start TYPE TIMESTAMPTZ USING old_time_start AT TIME ZONE 'UTC',
+  ALTER COLUMN old_time_end TYPE TIMESTAMPTZ USING old_time_end AT TIME ZONE 'UTC',
+  ALTER COLUMN new_time_start TYPE TIMESTAMPTZ USING new_time_start AT TIME ZONE 'UTC',
+  ALTER COLUMN new_time_end TYPE TIMESTAMPTZ USING new_time_end AT TIME ZONE 'UTC',
+  ALTER COLUMN changed_at TYPE TIMESTAMPTZ USING changed_at AT TIME ZONE 'UTC';
+
+ALTER TABLE access_logs
+  ALTER COLUMN timestamp TYPE TIMESTAMPTZ USING timestamp AT TIME ZONE 'Europe/Warsaw';
+
+COMMIT;
@@ -4,6 +4,7 @@ import './App.css';
 // Komponenty (upewnij się, że pliki istnieją w folderze components/)
 import EmployeeTable from './components/EmployeeTable';
 import LogTable from './components/LogTable';
+import StationTable from './components/StationTable';
 import AddEmployeeModal from './components/AddEmployeeModal';
 import Login from './components/Login';
 
@@ -18,15 +19,20 @@ function App() {
   const [employees, setEmployees] = useState([]);
   const [dbLogs, setDbLogs] = useState([]);       // Godziny pracy (WorkHours)
   const [securityLogs, setSecurityLogs] = useState([]); // Logi z bramek (AccessLogs)
+  const [stations, setStations] = useState([]);
   const [isLoading, setIsLoading] = useState(true);
-  const [isAuthenticated, setIsAuthenticated] = useState(false);
+  // Sesja z POST /login, token idzie w nagłówku Authorization
+  const [session, setSession] = useState(null);
+  const authHeaders = () => ({ 'Authorization': `Bearer ${session?.token}` });
 
-  // --- START APLIKACJI ---
+  // --- START APLIKACJI (po zalogowaniu) ---
   useEffect(() => {
+    if (!session) return;
     fetchEmployees();
     fetchWorkHours();
     fetchSecurityLogs();
-  }, []);
+    fetchStations();
+  }, [session]);
 
   // --- POBIERANIE DANYCH ---
 
@@ -61,6 +67,13 @@ function App() {
     }
   };
 
+  const fetchStations = async () => {
+    try {
+      const response = await fetch(`${API_URL}/stations`, { headers: authHeaders() });
+      if (response.ok) setStations(await response.json());
+    } catch (e) { console.error("Błąd stacji:", e); }
+  };
+
   // --- LOGIKA DO TABELI LOGÓW (Obliczenia i Formatowanie) ---
 
   const calculateDuration = (start, end) => {
@@ -159,7 +172,7 @@ function App() {
       // KROK 1: Wysyłamy dane tekstowe
       const response = await fetch(url, {
         method: method,
-        headers: { 'Content-Type': 'application/json' },
+        headers: { 'Content-Type': 'application/json', ...authHeaders() },
         body: JSON.stringify(jsonData)
       });
 
@@ -184,6 +197,7 @@ function App() {
 
         const photoResponse = await fetch(`${API_URL}/employees/${employeeId}/photo`, {
           method: 'POST',
+          headers: authHeaders(),
           body: formData
         });
 
@@ -204,38 +218,56 @@ function App() {
   };
 
   const handleDelete = async (id) => {
-    if (window.confirm("Czy na pewno chcesz usunąć pracownika?")) {
+    if (window.confirm("Czy na pewno chcesz zarchiwizować pracownika? Godziny pracy zostaną zachowane.")) {
       try {
-        await fetch(`${API_URL}/employees/${id}`, { method: 'DELETE' });
+        await fetch(`${API_URL}/employees/${id}`, { method: 'DELETE', headers: authHeaders() });
         setEmployees(employees.filter(e => e.id_person !== id));
-      } catch (e) { alert("Błąd usuwania"); }
+      } catch (e) { alert("Błąd archiwizacji"); }
     }
   };
 
-  // --- QR CODES (Generowane z ID) ---
+  // --- QR CODES (Generowane w backendzie) ---
 
-  const handleGenerateQR = (employee) => {
-    const qrContent = employee.id_person.toString();
-    const qrUrl = `https://api.qrserver.com/v1/create-qr-code/?size=150x150&data=${qrContent}`;
-    window.open(qrUrl, "_blank", "width=300,height=300");
+  const fetchBadge = async (employee, format) => {
+    const response = await fetch(`${API_URL}/employees/${employee.id_person}/badge?format=${format}`, {
+      headers: authHeaders()
+    });
+    if (!response.ok) throw new Error(await response.text());
+    return URL.createObjectURL(await response.blob());
   };
 
-  const handleDownloadQR = async (employee) => {
-    const qrContent = employee.id_person.toString();
-    const qrUrl = `https://api.qrserver.com/v1/create-qr-code/?size=300x300&data=${qrContent}`;
+  const downloadBadge = async (employee, format, filename) => {
     try {
-      const blob = await (await fetch(qrUrl)).blob();
       const link = document.createElement("a");
-      link.href = URL.createObjectURL(blob);
-      link.download = `QR_${employee.last_name}_${employee.first_name}.png`;
+      link.href = await fetchBadge(employee, format);
+      link.download = filename;
       link.click();
-    } catch(e) { window.open(qrUrl); }
+    } catch(e) { alert("Błąd generowania identyfikatora: " + e.message); }
+  };
+
+  const handleGenerateQR = async (employee) => {
+    try {
+      window.open(await fetchBadge(employee, "svg"), "_blank", "width=350,height=350");
+    } catch(e) { alert("Błąd generowania identyfikatora: " + e.message); }
   };
 
+  const handleDownloadQR = (employee) =>
+    downloadBadge(employee, "png", `QR_${employee.last_name}_${employee.first_name}.png`);
+
+  const handlePrintBadge = (employee) =>
+    downloadBadge(employee, "pdf", `Identyfikator_${employee.last_name}_${employee.first_name}.pdf`);
+
   // --- INTERFEJS (JSX) ---
 
-  if (!isAuthenticated) {
-    return <Login onLogin={(status) => setIsAuthenticated(status)} />;
+  const handleLogout = async () => {
+    try {
+      await fetch(`${API_URL}/logout`, { method: 'POST', headers: authHeaders() });
+    } catch (e) { console.error("Błąd wylogowania:", e); }
+    setSession(null);
+  };
+
+  if (!session) {
+    return <Login apiUrl={API_URL} onLogin={setSession} />;
   }
 
   return (
@@ -243,7 +275,7 @@ function App() {
         <header className="App-header">
           <div style={{width: '100%', padding: '10px', textAlign: 'right'}}>
             <button
-                onClick={() => setIsAuthenticated(false)}
+                onClick={handleLogout}
                 style={{background: 'red', color: 'white', border: 'none', padding: '5px 10px', cursor: 'pointer'}}>
               Wyloguj
             </button>
@@ -260,6 +292,9 @@ function App() {
             <button className={activeTab === 'logs' ? 'tab active' : 'tab'} onClick={() => setActiveTab('logs')}>
               📋 Logi i Raporty
             </button>
+            <button className={activeTab === 'stations' ? 'tab active' : 'tab'} onClick={() => setActiveTab('stations')}>
+              🖥️ Stacje
+            </button>
           </div>
 
           {activeTab === 'employees' ? (
@@ -275,10 +310,13 @@ function App() {
                         onDelete={handleDelete}
                         onGenerateQR={handleGenerateQR}
                         onDownloadQR={handleDownloadQR}
+                        onPrintBadge={handlePrintBadge}
                         onEdit={(emp) => { setEditingEmployee(emp); setIsModalOpen(true); }}
                     />
                 )}
               </>
+          ) : activeTab === 'stations' ? (
+              <StationTable stations={stations} onRefresh={fetchStations} />
           ) : (
               <LogTable logs={allLogs} onExport={handleExportCSV} />
           )}
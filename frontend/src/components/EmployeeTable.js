@@ -1,7 +1,7 @@
 import React from 'react';
 
 // Ten komponent przyjmuje listę pracowników i funkcje do obsługi przycisków
-function EmployeeTable({ employees, onDelete, onGenerateQR, onDownloadQR, onEdit }) {
+function EmployeeTable({ employees, onDelete, onGenerateQR, onDownloadQR, onPrintBadge, onEdit }) {
 
     return (
         <div className="table-container">
@@ -35,6 +35,7 @@ function EmployeeTable({ employees, onDelete, onGenerateQR, onDownloadQR, onEdit
                             </button>
                             <button title="Pokaż kod QR" onClick={() => onGenerateQR(emp)}>👁️</button>
                             <button title="Pobierz kod QR" onClick={() => onDownloadQR(emp)}>💾️</button>
+                            <button title="Drukuj identyfikator" onClick={() => onPrintBadge(emp)}>🪪</button>
                         </td>
                     </tr>
                 ))}
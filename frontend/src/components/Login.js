@@ -1,20 +1,28 @@
 import React, { useState } from 'react';
 
-function Login({ onLogin }) {
+function Login({ apiUrl, onLogin }) {
     const [username, setUsername] = useState("");
     const [password, setPassword] = useState("");
     const [error, setError] = useState("");
 
-    const handleSubmit = (e) => {
+    const handleSubmit = async (e) => {
         e.preventDefault();
 
-        // Tutaj ustawiamy login i hasło "na sztywno" dla admina
-        // W przyszłości można to przenieść do zmiennych środowiskowych (.env)
-        if (username === "ioio" && password === "bajojajo") {
-            onLogin(true);
-        } else {
-            setError("Nieprawidłowy login lub hasło 🔒");
-            onLogin(false);
+        try {
+            const response = await fetch(`${apiUrl}/login`, {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ login: username, password })
+            });
+            if (!response.ok) {
+                setError(response.status === 429
+                    ? "Zbyt wiele prób, spróbuj za minutę ⏳"
+                    : "Nieprawidłowy login lub hasło 🔒");
+                return;
+            }
+            onLogin(await response.json());
+        } catch (err) {
+            setError("Błąd połączenia z serwerem");
         }
     };
 
@@ -0,0 +1,47 @@
+import React from 'react';
+
+function StationTable({ stations, onRefresh }) {
+    return (
+        <div className="table-container">
+            <div className="header-actions">
+                <h2>Stacje</h2>
+                <button className="btn-export" onClick={onRefresh}>🔄 Odśwież</button>
+            </div>
+            <table className="employee-table">
+                <thead>
+                <tr>
+                    <th>Nazwa</th>
+                    <th>Miejsce</th>
+                    <th>Kierunek</th>
+                    <th>Drzwi</th>
+                    <th>Stan</th>
+                    <th>Ostatnio widziana</th>
+                    <th>Wersja</th>
+                    <th>Kamera</th>
+                </tr>
+                </thead>
+                <tbody>
+                {stations.map((station) => (
+                    <tr key={station.id_station}>
+                        <td><strong>{station.name}</strong></td>
+                        <td>{station.location || '-'}</td>
+                        <td>{station.direction}</td>
+                        <td>{station.door || '-'}</td>
+                        <td>
+                            {station.online ?
+                                <span className="badge badge-employee">ONLINE</span> :
+                                <span className="badge badge-admin">OFFLINE</span>
+                            }
+                        </td>
+                        <td>{station.last_seen_at ? new Date(station.last_seen_at).toLocaleString() : 'nigdy'}</td>
+                        <td>{station.version || '-'}</td>
+                        <td>{station.camera_status || '-'}</td>
+                    </tr>
+                ))}
+                </tbody>
+            </table>
+        </div>
+    );
+}
+
+export default StationTable;
@@ -1,18 +1,45 @@
-from os import getenv
+from os import getenv, path, remove, replace
+import threading
 from datetime import datetime
+from uuid import uuid4
+import base64
+import hashlib
+import hmac
+import json
 import requests
 import cv2
 from station_secrets import sweet_secrets
 
+STATION_VERSION = "1.0"
+
+
+def failure_reason( error ):
+    """backend_error, gdy serwer odpowiedział błędem, inaczej backend_unreachable."""
+    if isinstance( error, requests.HTTPError ):
+        return "backend_error"
+    return "backend_unreachable"
+
 
 class Contact_API:
     def __init__( self ):
         self.qr_url = sweet_secrets[ "qr_url" ]
         self.face_url = sweet_secrets[ "face_url" ]
         self.ack_url = sweet_secrets[ "ack_url" ]
-        self.token = sweet_secrets[ "auth_token" ]
+        self.sync_url = sweet_secrets[ "sync_url" ]
+        self.heartbeat_url = sweet_secrets[ "heartbeat_url" ]
+        self.config_url = sweet_secrets[ "config_url" ]
+        # token z rejestracji stacji (POST /stations)
+        self.token = getenv( "STATION_TOKEN" ) or sweet_secrets[ "auth_token" ]
 
         self.direction = getenv( "STATION_DIRECTION" )
+        self.station = getenv( "STATION_NAME" )
+
+        # zdarzenia, których nie udało się wysłać, czekają tu na sync_offline
+        self.secret = getenv( "STATION_SECRET" )
+        self.queue_path = getenv( "STATION_QUEUE", "offline_events.jsonl" )
+        # wysyłana paczka, kolejka jest tu przenoszona przed wysłaniem
+        self.sending_path = self.queue_path + ".sending"
+        self.queue_lock = threading.Lock()
 
         print( f'Station direction is: {self.direction}' )
 
@@ -39,15 +66,19 @@ class Contact_API:
             }
         return {}
 
-    def check_qr( self, qr_code : int ):
+    def check_qr( self, qr_code : str ):
 
         # return self.mock_good_qr
 
         try:
-            payload = {
-                "employee_id" : qr_code,
-                "direction"   : self.direction
-            }
+            payload = { "direction" : self.direction }
+            if qr_code.strip().isdigit():
+                # stary identyfikator z samym numerem pracownika
+                payload[ "employee_id" ] = int( qr_code )
+            else:
+                payload[ "code" ] = qr_code.strip()
+            if self.station:
+                payload[ "station" ] = self.station
 
             response = requests.post(
                 self.qr_url,
@@ -61,23 +92,32 @@ class Contact_API:
 
         except requests.Timeout:
             print( "[API] Timeout - serwer nie odpowiada" )
+            self.queue_failure( "backend_unreachable", payload.get( "employee_id" ) )
             return None
 
         except requests.RequestException as e:
             print( f"[API] check_qr error: {e}" )
+            self.queue_failure( failure_reason( e ), payload.get( "employee_id" ) )
             return None
 
-    def check_face( self, frame, employee_id ):
+    def check_face( self, frame, employee_id, code = None, pass_code = None ):
         try:
             ok, buf = cv2.imencode( ".jpg", frame )
             if not ok:
                 return None
 
             files = { "photo" : ( "frame.jpg", buf.tobytes(), "image/jpeg") }
-            data = {
-                "employee_id" : employee_id,
-                "direction"   : self.direction
-            }
+            data = { "direction" : self.direction }
+            if pass_code:
+                # gość, backend sprawdza podpisaną przepustkę zamiast pracownika
+                data[ "pass_code" ] = pass_code
+            elif code and not code.strip().isdigit():
+                # ten sam identyfikator co przy check_qr, backend sprawdza go ponownie
+                data[ "code" ] = code.strip()
+            else:
+                data[ "employee_id" ] = employee_id
+            if self.station:
+                data[ "station" ] = self.station
 
             response = requests.post(
                 self.face_url,
@@ -92,25 +132,69 @@ class Contact_API:
 
         except requests.Timeout:
             print( "[API] Timeout - serwer nie odpowiada" )
+            self.queue_failure( "backend_unreachable", employee_id or None )
             return None
 
         except requests.RequestException as e:
             print( f"[API] check_face error: {e}")
+            self.queue_failure( failure_reason( e ), employee_id or None )
             return None
 
-    def acknowledge_decision( self, employee_id, allowed ):
+    def acknowledge_decision( self, employee_id, allowed, retries = 3, pass_id = None, authorization_id = None ):
 
-        try:
-            payload = {
-                "employee_id" : employee_id,
-                "direction"   : self.direction,
-                "timestamp"   : datetime.now().strftime("%Y-%m-%dT%H:%M:%S")
-            }
+        # ten sam event_id przy każdej próbie, backend zapisze wejście tylko raz
+        payload = {
+            "event_id"    : str( uuid4() ),
+            "employee_id" : employee_id,
+            "direction"   : self.direction,
+            "timestamp"   : datetime.now().astimezone().isoformat( timespec = "seconds" )
+        }
+        if pass_id:
+            payload[ "pass_id" ] = pass_id
+        if authorization_id:
+            # wejście potwierdzone przez osobę towarzyszącą
+            payload[ "authorization_id" ] = authorization_id
+        if self.station:
+            payload[ "station" ] = self.station
+
+        for attempt in range( retries ):
+            try:
+                response = requests.post(
+                    self.ack_url,
+                    headers = self._headers(),
+                    json = payload,
+                    timeout = self.timeout
+                )
+
+                response.raise_for_status()
+                return response.json()
+
+            except ( requests.Timeout, requests.ConnectionError ):
+                print( f"[API] acknowledge_decision brak odpowiedzi, próba {attempt + 1}/{retries}" )
+
+            except requests.RequestException as e:
+                print( f"[API] acknowledge_decision error: {e}" )
+                if failure_reason( e ) == "backend_error" and e.response.status_code < 500:
+                    # backend odrzucił wejście, ponowne wysłanie nic nie zmieni
+                    return None
+                break
 
+        # serwer nie odpowiada, zapisujemy wejście i wyślemy je później
+        if pass_id or authorization_id:
+            print( "[API] wejścia gości i z osobą towarzyszącą nie są kolejkowane offline" )
+            return None
+        self.queue_event( "access_ack", payload )
+        return None
+
+    def heartbeat( self, camera_status ):
+        if not self.token:
+            return None
+
+        try:
             response = requests.post(
-                self.ack_url,
+                self.heartbeat_url,
                 headers = self._headers(),
-                json = payload,
+                json = { "version" : STATION_VERSION, "camera_status" : camera_status },
                 timeout = self.timeout
             )
 
@@ -118,7 +202,119 @@ class Contact_API:
             return response.json()
 
         except requests.RequestException as e:
-            print( f"[API] acknowledge_decision error: {e}" )
+            print( f"[API] heartbeat error: {e}" )
             return None
 
+    def fetch_config( self, station_id, etag = None ):
+        """Zwraca ( config, etag ), config jest None, gdy się nie zmienił."""
+        headers = self._headers()
+        if etag:
+            headers[ "If-None-Match" ] = etag
+
+        try:
+            response = requests.get(
+                self.config_url.format( id = station_id ),
+                headers = headers,
+                timeout = self.timeout
+            )
+
+            if response.status_code == 304:
+                return None, etag
+
+            response.raise_for_status()
+            return response.json(), response.headers.get( "ETag" )
+
+        except requests.RequestException as e:
+            print( f"[API] fetch_config error: {e}" )
+            return None, etag
+
+    def _sign( self, event ):
+        # ta sama kolejność pól co w backend/src/sync.rs
+        timestamp = int( datetime.fromisoformat( event[ "timestamp" ] ).timestamp() )
+        message = "|".join( [
+            event[ "event_id" ],
+            event[ "kind" ],
+            self.station or "",
+            str( event.get( "employee_id" ) or "" ),
+            event[ "direction" ],
+            str( timestamp ),
+            event.get( "reason" ) or ""
+        ] )
+        tag = hmac.new( self.secret.encode(), message.encode(), hashlib.sha256 ).digest()
+        return base64.urlsafe_b64encode( tag ).rstrip( b"=" ).decode()
+
+    def queue_event( self, kind, payload ):
+        if not self.secret or not self.station or not payload.get( "direction" ):
+            print( "[API] brak STATION_SECRET / STATION_NAME / kierunku, zdarzenie przepada" )
+            return
+
+        event = {
+            "event_id"    : payload[ "event_id" ],
+            "kind"        : kind,
+            "employee_id" : payload.get( "employee_id" ),
+            "direction"   : payload[ "direction" ],
+            "timestamp"   : payload[ "timestamp" ],
+            "reason"      : payload.get( "reason" )
+        }
+        event[ "signature" ] = self._sign( event )
+
+        with self.queue_lock:
+            with open( self.queue_path, "a" ) as f:
+                f.write( json.dumps( event ) + "\n" )
+
+    def queue_failure( self, reason, employee_id = None ):
+        """Nieudane wywołanie backendu, wysyłane później jako failed_attempt."""
+        self.queue_event( "failed_attempt", {
+            "event_id"    : str( uuid4() ),
+            "employee_id" : employee_id,
+            "direction"   : self.direction,
+            "timestamp"   : datetime.now().astimezone().isoformat( timespec = "seconds" ),
+            "reason"      : reason
+        } )
+
+    def sync_offline( self ):
+        # resztę poprzedniej paczki wysyłamy przed nowymi zdarzeniami
+        if not path.exists( self.sending_path ):
+            with self.queue_lock:
+                try:
+                    replace( self.queue_path, self.sending_path )
+                except FileNotFoundError:
+                    return
+
+        with open( self.sending_path ) as f:
+            events = [ json.loads( line ) for line in f if line.strip() ]
+
+        if not events:
+            remove( self.sending_path )
+            return
+
+        try:
+            response = requests.post(
+                self.sync_url,
+                headers = self._headers(),
+                json = { "events" : events[ :500 ] },
+                timeout = self.timeout
+            )
+            response.raise_for_status()
+            results = response.json()[ "results" ]
+
+        except requests.RequestException as e:
+            print( f"[API] sync_offline error: {e}" )
+            return
+
+        # "error" zostaje w kolejce, reszta jest już załatwiona
+        done = { r[ "event_id" ] for r in results if r[ "status" ] != "error" }
+        for r in results:
+            if r[ "status" ] == "rejected":
+                print( f"[API] zdarzenie {r[ 'event_id' ]} odrzucone: {r.get( 'reason' )}" )
+
+        # plik .sending zapisuje tylko ten wątek, nowe zdarzenia idą do kolejki
+        left = [ event for event in events if event[ "event_id" ] not in done ]
+        if not left:
+            remove( self.sending_path )
+            return
+        with open( self.sending_path, "w" ) as f:
+            for event in left:
+                f.write( json.dumps( event ) + "\n" )
+
 
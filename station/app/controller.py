@@ -1,9 +1,50 @@
 import threading
 import time
+from datetime import datetime
 
 from camera import CameraControl
 from api import Contact_API
 
+# Odpowiedzi backendu przy blokadzie po zbyt wielu nieudanych próbach
+BLOCKED_MESSAGES = {
+    "locked_out"   : "ZABLOKOWANO: Zbyt wiele nieudanych prób",
+    "rate_limited" : "Zbyt wiele prób, spróbuj za chwilę",
+    "zone_not_permitted" : "BRAK DOSTĘPU DO TEJ STREFY",
+    "outside_access_hours" : "POZA GODZINAMI DOSTĘPU",
+    "lockdown" : "BLOKADA OBIEKTU - PRZEJŚCIE ZAMKNIĘTE",
+    "passback" : "NAJPIERW ODBIJ WYJŚCIE ZE STREFY",
+}
+
+# Tryb awaryjny z heartbeatu, pokazywany na stałe w statusie
+MODE_LABELS = {
+    "lockdown"   : "TRYB BLOKADY - przejście tylko dla administratorów",
+    "evacuation" : "EWAKUACJA - wyjście otwarte",
+}
+
+
+def blocked_message(data):
+    """Komunikat dla odmowy, z godziną następnego wejścia, jeśli backend ją podał."""
+    msg = BLOCKED_MESSAGES[data["reason"]]
+    if data.get("next_allowed_at"):
+        next_at = datetime.fromisoformat(data["next_allowed_at"])
+        msg += f"\nWejście od {next_at.strftime('%d.%m %H:%M')}"
+    return msg
+
+# Odpowiedzi backendu przy nieważnym identyfikatorze
+BADGE_MESSAGES = {
+    "invalid_badge" : "NIEWAŻNY IDENTYFIKATOR",
+    "badge_expired" : "IDENTYFIKATOR WYGASŁ",
+    "badge_revoked" : "IDENTYFIKATOR ZABLOKOWANY",
+    "invalid_code"      : "KOD NIEWAŻNY",
+    "code_reused"       : "KOD JUŻ UŻYTY, zaczekaj na nowy",
+    "totp_not_enrolled" : "TELEFON NIEZAREJESTROWANY",
+    "invalid_pass"       : "NIEWAŻNA PRZEPUSTKA",
+    "pass_expired"       : "PRZEPUSTKA WYGASŁA",
+    "pass_revoked"       : "PRZEPUSTKA ZABLOKOWANA",
+    "pass_not_yet_valid" : "PRZEPUSTKA JESZCZE NIEWAŻNA",
+    "pass_used_up"       : "PRZEPUSTKA JUŻ WYKORZYSTANA",
+}
+
 class Controller:
     def __init__(self, root, gui):
         self.root = root
@@ -11,13 +52,52 @@ class Controller:
         self.api = Contact_API()
         self.camera = CameraControl( callback_qr=None )
 
+        # Domyślne ustawienia, nadpisywane przez config z backendu
         self.FPS = 30
+        self.countdown_seconds = 3
+        self.message_seconds = 2
+        self.config_version = None
+        self.config_etag = None
+        self.mode = "normal"
+        # osoba czekająca na osobę towarzyszącą: authorization_id, employee_id, pass_id
+        self.pending_escort = None
+
         self.running = True
         self.is_busy = False # Jedna flaga wystarczy, by wiedzieć czy stacja "pracuje"
 
         self.current_frame = None
 
         threading.Thread(target=self.loop, daemon=True).start()
+        threading.Thread(target=self.sync_loop, daemon=True).start()
+
+    def sync_loop(self):
+        """Co 30 s zgłasza się do serwera i wysyła zdarzenia zebrane, gdy był niedostępny."""
+        while self.running:
+            camera_status = "ok" if self.current_frame is not None else "no_frames"
+            station = self.api.heartbeat(camera_status)
+            if station and station.get("config_version") != self.config_version:
+                self.load_config(station["id_station"])
+            if station and station.get("mode", "normal") != self.mode:
+                self.mode = station.get("mode", "normal")
+                print(f"Tryb obiektu: {self.mode}")
+                label = MODE_LABELS.get(self.mode, self.gui.all_labels["wait_qr"])
+                self.root.after(0, lambda: self.gui.set_status(label))
+            self.api.sync_offline()
+            time.sleep(30)
+
+    def load_config(self, station_id):
+        """Pobiera ustawienia stacji z backendu (tylko gdy zmienił się ETag)."""
+        config, self.config_etag = self.api.fetch_config(station_id, self.config_etag)
+        if config is None:
+            return
+
+        self.FPS = config["fps"]
+        self.countdown_seconds = config["countdown_seconds"]
+        self.message_seconds = config["message_seconds"]
+        self.api.timeout = config["request_timeout_seconds"]
+        self.api.direction = config["direction"]
+        self.config_version = config["version"]
+        print(f"Config stacji w wersji {self.config_version}")
 
     def loop(self):
         """Pętla kamery - zajmuje się tylko wyświetlaniem obrazu i czekaniem na QR."""
@@ -44,17 +124,32 @@ class Controller:
         """Ta funkcja pilnuje kolejności kroków."""
         try:
             # 1. Sprawdź QR
-            employee_name = self.process_qr_logic(qr_code)
-            if not employee_name:
+            person = self.process_qr_logic(qr_code)
+            if not person:
                 return # Błąd obsłużony wewnątrz funkcji
 
             # 2. Odliczanie i Twarz
-            face_result = self.handle_face_recognition(qr_code)
+            employee_id, pass_id = person
+            pass_code = qr_code.strip() if pass_id else None
+            face_result = self.handle_face_recognition(employee_id, qr_code, pass_code, pass_id)
             if not face_result:
                 return # Błąd lub brak zgody
 
-            # 3. Sukces końcowy
-            self.gui_update_info("DOSTĘP PRZYZNANY", color="green")
+            # 3. Zapis wejścia w backendzie (bez odpowiedzi trafia do kolejki offline)
+            self.api.acknowledge_decision(employee_id, True, pass_id=pass_id)
+
+            # 4. Sukces końcowy
+            if face_result.get("reason") == "escort_confirmed":
+                # ta osoba potwierdziła wejście kogoś, kto czekał przy stacji
+                held = self.pending_escort
+                if held and held["authorization_id"] == face_result.get("authorization_id"):
+                    self.api.acknowledge_decision(
+                        held["employee_id"], True,
+                        pass_id=held["pass_id"], authorization_id=held["authorization_id"])
+                    self.pending_escort = None
+                self.gui_update_info("DOSTĘP PRZYZNANY DLA OBU OSÓB", color="green")
+            else:
+                self.gui_update_info("DOSTĘP PRZYZNANY", color="green")
             time.sleep(3) # Daj czas na przeczytanie
 
         finally:
@@ -63,28 +158,46 @@ class Controller:
 
     # --- KROKI PROCEDURY ---
     def process_qr_logic(self, qr_code):
-        self.gui_update_info(f"Sprawdzam pracownika {qr_code}...")
+        self.gui_update_info("Sprawdzam kod QR...")
         try:
-            qr_code = int( qr_code )
-
-
+            # Podpisany identyfikator albo stary kod z samym numerem pracownika
             data = self.api.check_qr(qr_code)
 
             if data is None:
                 self.gui_update_info("Błąd połączenia z serwerem", color="orange")
-                time.sleep(2)
+                time.sleep(self.message_seconds)
+                return None
+
+            if data.get("reason") == "evacuation":
+                # Ewakuacja: wyjście otwarte dla wszystkich, bez sprawdzania twarzy
+                self.gui_update_info("EWAKUACJA - WYJŚCIE OTWARTE", color="green")
+                time.sleep(self.message_seconds)
+                return None
+
+            if data.get("reason") in BADGE_MESSAGES:
+                self.gui_update_info(BADGE_MESSAGES[data["reason"]], color="red")
+                time.sleep(self.message_seconds)
+                return None
+
+            if data.get("reason") in BLOCKED_MESSAGES:
+                self.gui_update_info(blocked_message(data), color="red")
+                time.sleep(self.message_seconds)
                 return None
 
             if not data.get("exists"):
                 self.gui_update_info("PRACOWNIK NIEZNANY", color="red")
                 # Logowanie incydentu (zdjęcie nieznajomego)
-                self.api.check_face(self.camera.read_frame(), employee_id=0)
-                time.sleep(2)
+                self.api.check_face(self.camera.read_frame(), employee_id=0, code=qr_code)
+                time.sleep(self.message_seconds)
                 return None
 
             name = f"{data.get('first_name')} {data.get('last_name')}"
+            # Przepustka gościa: employee_id = 0, dalej liczy się pass_id
+            visitor = data.get("visitor")
+            if visitor:
+                name += " (gość)"
             self.root.after(0, lambda: self.gui.set_status(f"Witaj {name}"))
-            return name
+            return data.get("employee_id"), visitor["pass_id"] if visitor else None
 
         except ValueError:
             self.root.after(
@@ -93,15 +206,15 @@ class Controller:
             )
             return None
 
-    def handle_face_recognition(self, qr_code):
+    def handle_face_recognition(self, employee_id, code=None, pass_code=None, pass_id=None):
         """Odlicza i robi zdjęcie."""
-        for i in range(3, 0, -1):
+        for i in range(self.countdown_seconds, 0, -1):
             self.gui_update_info(f"Zdjęcie twarzy za {i}s")
             time.sleep(1)
 
         self.gui_update_info("Weryfikacja twarzy...")
 
-        data = self.api.check_face( self.current_frame, qr_code)
+        data = self.api.check_face( self.current_frame, employee_id, code, pass_code)
 
         print( data )
 
@@ -111,12 +224,27 @@ class Controller:
 
         if not data.get("access_granted"):
             reason = data.get("reason", "unknown")
-            msg = "ODMOWA: Twarz niezgodna" if reason == "face_mismatched" else "BŁĄD KIERUNKU"
+            if reason in BLOCKED_MESSAGES:
+                msg = blocked_message(data)
+            elif reason in BADGE_MESSAGES:
+                msg = BADGE_MESSAGES[reason]
+            elif reason == "face_mismatched":
+                msg = "ODMOWA: Twarz niezgodna"
+            elif reason == "escort_required":
+                # strefa z zasadą dwóch osób, backend czeka na osobę upoważnioną
+                self.pending_escort = {
+                    "authorization_id" : data.get("authorization_id"),
+                    "employee_id"      : employee_id,
+                    "pass_id"          : pass_id,
+                }
+                msg = "CZEKAJ NA OSOBĘ UPOWAŻNIONĄ\nmusi zweryfikować się na tej stacji"
+            else:
+                msg = "BŁĄD KIERUNKU"
             self.gui_update_info(msg, color="red")
-            time.sleep(3)
+            time.sleep(self.message_seconds)
             return False
 
-        return True
+        return data
 
     # --- POMOCNIKI ---
     def gui_update_info(self, text, color="default"):
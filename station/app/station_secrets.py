@@ -4,5 +4,8 @@ sweet_secrets = {
     "qr_url" : beginning + "/api/employee/check_qr",
     "face_url" : beginning + "/api/face/verify",
     "ack_url" : beginning + "/api/access/ack",
+    "sync_url" : beginning + "/api/stations/sync",
+    "heartbeat_url" : beginning + "/api/stations/heartbeat",
+    "config_url" : beginning + "/api/stations/{id}/config",
     "auth_token" : None  # "Here put token if API needs it"
 }